@@ -0,0 +1,297 @@
+//! Retention: moves processed or old feedback out of the live collection.
+//!
+//! Selected documents go into a gzip-compressed NDJSON file first, one line per document with its
+//! name, `createTime`, `updateTime` and the losslessly decoded `data`. The file is read back and
+//! every line re-encoded and compared with the fetched document before anything is copied to the
+//! archive collection or deleted; copies are read back and compared before the deletes. With the
+//! quarantine key set, the file is encrypted like the quarantine.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ring::aead::LessSafeKey;
+use serde_json::{json, Value};
+
+use crate::decode::{extract_document_id, string_field};
+use crate::lossless::{decode_lossless_fields, encode_firestore_fields};
+use crate::quarantine::{
+    decrypt, encrypt, is_encrypted, ARCHIVE_ENCRYPTION_AAD, QUARANTINE_KEY_ENV,
+};
+use crate::sync::timestamp_sort_key;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// What `archive` selects and whether it may delete.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveRequest {
+    /// Documents marked by `write_back` (`processedAt` set).
+    pub processed: bool,
+    /// Documents whose `createTime` is more than this many days ago.
+    pub older_than_days: Option<u32>,
+    /// Delete the archived documents from the live collection after verification.
+    pub confirm_delete: bool,
+}
+
+impl ArchiveRequest {
+    /// Raw documents matching any of the criteria, in input order.
+    pub fn select<'d>(&self, documents: &'d [Value], now_unix: u64) -> Vec<&'d Value> {
+        let cutoff = self.older_than_days.map(|days| {
+            let cutoff_unix = now_unix.saturating_sub(u64::from(days) * SECONDS_PER_DAY);
+            timestamp_sort_key(&format_utc_timestamp(cutoff_unix))
+        });
+        documents
+            .iter()
+            .filter(|raw| {
+                let processed = self.processed
+                    && raw
+                        .get("fields")
+                        .and_then(|fields| fields.get("processedAt"))
+                        .is_some_and(|value| value.get("nullValue").is_none());
+                let create_time = string_field(raw, "createTime");
+                let old = cutoff.as_ref().is_some_and(|cutoff| {
+                    !create_time.is_empty() && timestamp_sort_key(&create_time) < *cutoff
+                });
+                processed || old
+            })
+            .collect()
+    }
+}
+
+/// What `run_archive` did, for the caller to report.
+#[derive(Debug, Default)]
+pub struct ArchiveSummary {
+    /// Documents in the collection (or the `--input` dump).
+    pub fetched: usize,
+    /// Names of the selected documents.
+    pub selected: Vec<String>,
+    /// Unset when nothing was selected.
+    pub archive_path: Option<PathBuf>,
+    pub dry_run: bool,
+    /// On a dry run, `{"writes": [...]}` per commit that would be sent.
+    pub planned_commits: Vec<Value>,
+    /// Documents written to and verified in `archive_path`.
+    pub archived: usize,
+    /// The archive collection and the number of commits the copies took.
+    pub copied_to: Option<(String, usize)>,
+    /// Commits the deletes took; unset without `confirm_delete`.
+    pub delete_commits: Option<usize>,
+}
+
+/// One archived document: REST metadata plus the Firestore `fields`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedDocument {
+    pub name: String,
+    pub create_time: String,
+    pub update_time: String,
+    pub fields: Value,
+}
+
+impl ArchivedDocument {
+    pub fn from_raw(raw: &Value) -> Self {
+        ArchivedDocument {
+            name: string_field(raw, "name"),
+            create_time: string_field(raw, "createTime"),
+            update_time: string_field(raw, "updateTime"),
+            fields: raw.get("fields").cloned().unwrap_or_else(|| json!({})),
+        }
+    }
+
+    /// The NDJSON line, without the newline.
+    pub fn to_line(&self) -> String {
+        json!({
+            "name": self.name,
+            "createTime": self.create_time,
+            "updateTime": self.update_time,
+            "data": decode_lossless_fields(&self.fields)
+        })
+        .to_string()
+    }
+
+    pub fn from_line(line: &str) -> Result<Self> {
+        let parsed: Value = serde_json::from_str(line).context("archive line is not JSON")?;
+        let fields = encode_firestore_fields(parsed.get("data").unwrap_or(&Value::Null))
+            .context("archive line has no encodable `data`")?;
+        Ok(ArchivedDocument {
+            name: string_field(&parsed, "name"),
+            create_time: string_field(&parsed, "createTime"),
+            update_time: string_field(&parsed, "updateTime"),
+            fields,
+        })
+    }
+}
+
+/// The gzip-compressed NDJSON archive of `documents`, encrypted when a key is given.
+pub fn archive_bytes(documents: &[ArchivedDocument], key: Option<&LessSafeKey>) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for document in documents {
+        encoder
+            .write_all(document.to_line().as_bytes())
+            .and_then(|()| encoder.write_all(b"\n"))
+            .context("failed to compress the archive")?;
+    }
+    let compressed = encoder.finish().context("failed to compress the archive")?;
+    match key {
+        Some(key) => encrypt(key, ARCHIVE_ENCRYPTION_AAD, compressed),
+        None => Ok(compressed),
+    }
+}
+
+/// Decompresses a whole gzip file; damaged or truncated input is an error.
+pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    GzDecoder::new(bytes)
+        .read_to_end(&mut data)
+        .context("not a complete gzip file")?;
+    Ok(data)
+}
+
+/// Reads the archive back from disk and checks it line by line against the documents it was
+/// written from: same order, names and `updateTime`, and `data` that encodes to the same fields.
+/// Fields are compared in the encoder's form, where e.g. an empty map is always `{"mapValue": {}}`.
+pub fn verify_archive(
+    path: &Path,
+    expected: &[ArchivedDocument],
+    key: Option<&LessSafeKey>,
+) -> Result<Vec<ArchivedDocument>> {
+    let mut bytes =
+        fs::read(path).with_context(|| format!("failed to read archive {}", path.display()))?;
+    if is_encrypted(&bytes) {
+        let Some(key) = key else {
+            bail!(
+                "archive {} is encrypted; set {} to read it",
+                path.display(),
+                QUARANTINE_KEY_ENV
+            );
+        };
+        bytes = decrypt(key, ARCHIVE_ENCRYPTION_AAD, &bytes)
+            .with_context(|| format!("failed to decrypt archive {}", path.display()))?;
+    }
+    let ndjson =
+        gunzip(&bytes).with_context(|| format!("archive {} is damaged", path.display()))?;
+    let text = String::from_utf8(ndjson)
+        .with_context(|| format!("archive {} is not UTF-8", path.display()))?;
+    let archived = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            ArchivedDocument::from_line(line).with_context(|| format!("archive line {}", index + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if archived.len() != expected.len() {
+        bail!(
+            "archive {} holds {} documents, expected {}",
+            path.display(),
+            archived.len(),
+            expected.len()
+        );
+    }
+    for (index, (found, wanted)) in archived.iter().zip(expected).enumerate() {
+        if found.name != wanted.name {
+            bail!(
+                "archive line {} is {:?}, expected {:?}",
+                index + 1,
+                found.name,
+                wanted.name
+            );
+        }
+        let wanted_fields = encode_firestore_fields(&decode_lossless_fields(&wanted.fields))
+            .with_context(|| format!("failed to encode the fields of {}", wanted.name))?;
+        if found.update_time != wanted.update_time || found.fields != wanted_fields {
+            bail!("archive line {} does not match {}", index + 1, wanted.name);
+        }
+    }
+    Ok(archived)
+}
+
+/// `<collection>_<YYYYMMDDTHHMMSSZ>.ndjson.gz`, with `.enc` appended when encrypted.
+pub fn archive_file_name(collection: &str, now_unix: u64, encrypted: bool) -> String {
+    let stamp: String = format_utc_timestamp(now_unix)
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    let suffix = if encrypted { ".enc" } else { "" };
+    format!("{}_{}.ndjson.gz{}", collection, stamp, suffix)
+}
+
+/// Writes copying every document into `archive_collection` (same id and fields), overwriting
+/// earlier copies so an interrupted run can be repeated.
+pub fn plan_archive_copies(
+    documents: &[ArchivedDocument],
+    archive_collection: &str,
+) -> Result<Vec<Value>> {
+    documents
+        .iter()
+        .map(|document| {
+            let Some((database, _)) = document.name.split_once("/documents/") else {
+                bail!("{:?} is not a Firestore document name", document.name);
+            };
+            let name = format!(
+                "{}/documents/{}/{}",
+                database,
+                archive_collection,
+                extract_document_id(&document.name)
+            );
+            Ok(json!({ "update": { "name": name, "fields": document.fields } }))
+        })
+        .collect()
+}
+
+/// Checks the documents read back from `archive_collection` against the archived ones: every copy
+/// must exist and hold the same fields, compared in the encoder's form like [`verify_archive`].
+pub fn verify_archive_copies(
+    documents: &[ArchivedDocument],
+    archive_collection: &str,
+    found: &[Value],
+) -> Result<()> {
+    let copies = plan_archive_copies(documents, archive_collection)?;
+    for (copy, document) in copies.iter().zip(documents) {
+        let name = copy["update"]["name"].as_str().unwrap_or_default();
+        let Some(stored) = found
+            .iter()
+            .find(|doc| doc.get("name").and_then(Value::as_str) == Some(name))
+        else {
+            bail!("copy {} is missing", name);
+        };
+        let canonical = |fields: &Value| encode_firestore_fields(&decode_lossless_fields(fields));
+        let stored_fields = canonical(stored.get("fields").unwrap_or(&json!({})))
+            .with_context(|| format!("failed to encode the fields of {}", name))?;
+        let wanted_fields = canonical(&document.fields)
+            .with_context(|| format!("failed to encode the fields of {}", document.name))?;
+        if stored_fields != wanted_fields {
+            bail!("copy {} does not match {}", name, document.name);
+        }
+    }
+    Ok(())
+}
+
+/// Deletes that only apply while a document is unchanged since it was archived.
+pub fn plan_archive_deletes(documents: &[ArchivedDocument]) -> Vec<Value> {
+    documents
+        .iter()
+        .map(|document| {
+            let precondition = if document.update_time.is_empty() {
+                json!({ "exists": true })
+            } else {
+                json!({ "updateTime": document.update_time })
+            };
+            json!({ "delete": document.name, "currentDocument": precondition })
+        })
+        .collect()
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` for a Unix time.
+pub fn format_utc_timestamp(unix: u64) -> String {
+    i64::try_from(unix)
+        .ok()
+        .and_then(|seconds| DateTime::<Utc>::from_timestamp(seconds, 0))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
@@ -0,0 +1,85 @@
+//! Service account loading and the OAuth token exchange for Firestore.
+
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+const TOKEN_SCOPE: &str = "https://www.googleapis.com/auth/datastore";
+const TOKEN_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+
+#[derive(Debug, Deserialize)]
+pub struct ServiceAccount {
+    pub project_id: String,
+    private_key: String,
+    client_email: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Debug, Serialize)]
+struct JwtClaims {
+    iss: String,
+    scope: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+}
+
+pub fn read_service_account(path: &Path) -> Result<ServiceAccount> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed to read service account file {}", path.display()))?;
+    serde_json::from_str(&raw).with_context(|| {
+        format!(
+            "failed to parse service account JSON from {}",
+            path.display()
+        )
+    })
+}
+
+pub fn fetch_access_token(client: &Client, service_account: &ServiceAccount) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("system time is before UNIX_EPOCH")?
+        .as_secs() as i64;
+
+    let claims = JwtClaims {
+        iss: service_account.client_email.clone(),
+        scope: TOKEN_SCOPE.to_string(),
+        aud: service_account.token_uri.clone(),
+        iat: now,
+        exp: now + 3600,
+    };
+
+    let header = Header::new(Algorithm::RS256);
+    let encoding_key = EncodingKey::from_rsa_pem(service_account.private_key.as_bytes())
+        .context("failed to load RSA private key from service account JSON")?;
+    let assertion =
+        encode(&header, &claims, &encoding_key).context("failed to sign JWT assertion")?;
+
+    let token_response = client
+        .post(&service_account.token_uri)
+        .form(&[
+            ("grant_type", TOKEN_GRANT_TYPE),
+            ("assertion", assertion.as_str()),
+        ])
+        .send()
+        .context("token endpoint request failed")?
+        .error_for_status()
+        .context("token endpoint returned non-success status")?
+        .json::<TokenResponse>()
+        .context("failed to parse token endpoint response")?;
+
+    if token_response.access_token.trim().is_empty() {
+        bail!("token endpoint returned an empty access token");
+    }
+    Ok(token_response.access_token)
+}
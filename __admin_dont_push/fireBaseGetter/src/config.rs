@@ -0,0 +1,560 @@
+//! Settings layers (defaults < `fireBaseGetter.toml` < environment < CLI) and the resolved run paths.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::decode::DecodeMode;
+use crate::export::LearningFolderMatcher;
+use crate::sanitize::{
+    DEFAULT_BLOCK_SCORE_THRESHOLD, DEFAULT_COMMENT_MAX_CHARS, DEFAULT_QUARANTINE_SCORE_THRESHOLD,
+};
+
+const CONFIG_RELATIVE_PATH: &str = "__admin_dont_push/fireBaseGetter/fireBaseGetter.toml";
+const ENV_PREFIX: &str = "FIREBASE_GETTER_";
+const DEFAULT_COLLECTION_NAME: &str = "feedback_all_games";
+const DEFAULT_SERVICE_ACCOUNT_FILE: &str = "__admin_dont_push/firebase-service-account.local.json";
+const DEFAULT_OUTPUT_RELATIVE_PATH: &str =
+    "__admin_dont_push/fireBaseGetter/feedback_all_games.json";
+const DEFAULT_PROTOCOL_RELATIVE_PATH: &str =
+    "__admin_dont_push/fireBaseGetter/codex_protocoll_allFeedBack.txt";
+const DEFAULT_REVIEW_RELATIVE_PATH: &str =
+    "__admin_dont_push/fireBaseGetter/feedback_review.local.json";
+const DEFAULT_QUARANTINE_PATH: &str = "~/.local/share/fireBaseGetter/quarantine.json";
+pub const DEFAULT_FIRESTORE_BASE_URL: &str = "https://firestore.googleapis.com/v1";
+const EMULATOR_HOST_ENV: &str = "FIRESTORE_EMULATOR_HOST";
+const DEFAULT_FIRESTORE_PAGE_SIZE: u32 = 1000;
+const DEFAULT_LEARNING_EXPORT_SUBDIR: &str = "firebase_feedback_import";
+const DEFAULT_LEARNING_FOLDER_PATTERN: &str = "__dokumentation/__04_lernings";
+const DEFAULT_SITE_PATH_PREFIX: &str = "easyPV";
+const DEFAULT_EXPORT_ROOTS: &[&str] = &["databases"];
+const DEFAULT_STATE_RELATIVE_PATH: &str =
+    "__admin_dont_push/fireBaseGetter/fireBaseGetter.state.json";
+const DEFAULT_WATERMARK_FIELD: &str = "createdAt";
+const DEFAULT_FULL_SYNC_INTERVAL_HOURS: u32 = 24;
+const DEFAULT_PROCESSED_BY: &str = "fireBaseGetter";
+const DEFAULT_ARCHIVE_DIR: &str = "~/.local/share/fireBaseGetter/archive";
+
+/// Tunable settings, merged from defaults, `fireBaseGetter.toml`, `FIREBASE_GETTER_*`
+/// environment variables and CLI flags (later layers win).
+#[derive(Debug, Clone, Serialize)]
+pub struct Settings {
+    pub collection: String,
+    pub service_account_file: String,
+    pub output_path: String,
+    pub protocol_path: String,
+    /// Restricted review output with match excerpts; never goes into the learning folders.
+    pub review_path: String,
+    /// Originals of blocked comments awaiting review; `~/` is the home directory. Outside the repo
+    /// unless `FIREBASE_GETTER_QUARANTINE_KEY` encrypts it.
+    pub quarantine_path: String,
+    pub page_size: u32,
+    pub learning_export_subdir: String,
+    pub learning_folder_pattern: String,
+    pub site_path_prefix: String,
+    pub export_roots: Vec<String>,
+    pub comment_max_chars: usize,
+    pub quarantine_score_threshold: u32,
+    pub block_score_threshold: u32,
+    pub firestore_base_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emulator_host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<String>,
+    /// Rule pack TOML; unset means the built-in `rules/prompt_injection.toml`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_pack: Option<String>,
+    pub incremental: bool,
+    pub state_path: String,
+    pub watermark_field: String,
+    /// With `incremental`, a full download once the last one is this old; `0` never forces one.
+    pub full_sync_interval_hours: u32,
+    /// Keep Firestore types (`$timestamp`, `$ref`, ...) in the output JSON instead of plain strings.
+    pub lossless_decoding: bool,
+    /// Mark exported documents in Firestore (`processedAt`, `processedBy`, ...).
+    pub write_back: bool,
+    /// Written as `processedBy`.
+    pub processed_by: String,
+    /// Local `archive` files; they hold the original comments. `~/` is the home directory; outside the
+    /// repo unless `FIREBASE_GETTER_QUARANTINE_KEY` encrypts them.
+    pub archive_dir: String,
+    /// Collection `archive` copies documents into before deleting them; unset means local file only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_collection: Option<String>,
+}
+
+/// One configuration layer; unset fields keep the value of the layer below.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsLayer {
+    pub collection: Option<String>,
+    pub service_account_file: Option<String>,
+    pub output_path: Option<String>,
+    pub protocol_path: Option<String>,
+    pub review_path: Option<String>,
+    pub quarantine_path: Option<String>,
+    pub page_size: Option<u32>,
+    pub learning_export_subdir: Option<String>,
+    pub learning_folder_pattern: Option<String>,
+    pub site_path_prefix: Option<String>,
+    pub export_roots: Option<Vec<String>>,
+    pub comment_max_chars: Option<usize>,
+    pub quarantine_score_threshold: Option<u32>,
+    pub block_score_threshold: Option<u32>,
+    pub firestore_base_url: Option<String>,
+    pub emulator_host: Option<String>,
+    pub project_id: Option<String>,
+    pub rule_pack: Option<String>,
+    pub incremental: Option<bool>,
+    pub state_path: Option<String>,
+    pub watermark_field: Option<String>,
+    pub full_sync_interval_hours: Option<u32>,
+    pub lossless_decoding: Option<bool>,
+    pub write_back: Option<bool>,
+    pub processed_by: Option<String>,
+    pub archive_dir: Option<String>,
+    pub archive_collection: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            collection: DEFAULT_COLLECTION_NAME.to_string(),
+            service_account_file: DEFAULT_SERVICE_ACCOUNT_FILE.to_string(),
+            output_path: DEFAULT_OUTPUT_RELATIVE_PATH.to_string(),
+            protocol_path: DEFAULT_PROTOCOL_RELATIVE_PATH.to_string(),
+            review_path: DEFAULT_REVIEW_RELATIVE_PATH.to_string(),
+            quarantine_path: DEFAULT_QUARANTINE_PATH.to_string(),
+            page_size: DEFAULT_FIRESTORE_PAGE_SIZE,
+            learning_export_subdir: DEFAULT_LEARNING_EXPORT_SUBDIR.to_string(),
+            learning_folder_pattern: DEFAULT_LEARNING_FOLDER_PATTERN.to_string(),
+            site_path_prefix: DEFAULT_SITE_PATH_PREFIX.to_string(),
+            export_roots: DEFAULT_EXPORT_ROOTS.iter().map(|r| r.to_string()).collect(),
+            comment_max_chars: DEFAULT_COMMENT_MAX_CHARS,
+            quarantine_score_threshold: DEFAULT_QUARANTINE_SCORE_THRESHOLD,
+            block_score_threshold: DEFAULT_BLOCK_SCORE_THRESHOLD,
+            firestore_base_url: DEFAULT_FIRESTORE_BASE_URL.to_string(),
+            emulator_host: None,
+            project_id: None,
+            rule_pack: None,
+            incremental: false,
+            state_path: DEFAULT_STATE_RELATIVE_PATH.to_string(),
+            watermark_field: DEFAULT_WATERMARK_FIELD.to_string(),
+            full_sync_interval_hours: DEFAULT_FULL_SYNC_INTERVAL_HOURS,
+            lossless_decoding: false,
+            write_back: false,
+            processed_by: DEFAULT_PROCESSED_BY.to_string(),
+            archive_dir: DEFAULT_ARCHIVE_DIR.to_string(),
+            archive_collection: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn apply(&mut self, layer: SettingsLayer) {
+        if let Some(v) = layer.collection {
+            self.collection = v;
+        }
+        if let Some(v) = layer.service_account_file {
+            self.service_account_file = v;
+        }
+        if let Some(v) = layer.output_path {
+            self.output_path = v;
+        }
+        if let Some(v) = layer.protocol_path {
+            self.protocol_path = v;
+        }
+        if let Some(v) = layer.review_path {
+            self.review_path = v;
+        }
+        if let Some(v) = layer.quarantine_path {
+            self.quarantine_path = v;
+        }
+        if let Some(v) = layer.page_size {
+            self.page_size = v;
+        }
+        if let Some(v) = layer.learning_export_subdir {
+            self.learning_export_subdir = v;
+        }
+        if let Some(v) = layer.learning_folder_pattern {
+            self.learning_folder_pattern = v;
+        }
+        if let Some(v) = layer.site_path_prefix {
+            self.site_path_prefix = v;
+        }
+        if let Some(v) = layer.export_roots {
+            self.export_roots = v;
+        }
+        if let Some(v) = layer.comment_max_chars {
+            self.comment_max_chars = v;
+        }
+        if let Some(v) = layer.quarantine_score_threshold {
+            self.quarantine_score_threshold = v;
+        }
+        if let Some(v) = layer.block_score_threshold {
+            self.block_score_threshold = v;
+        }
+        if let Some(v) = layer.firestore_base_url {
+            self.firestore_base_url = v;
+        }
+        if let Some(v) = layer.emulator_host {
+            self.emulator_host = Some(v);
+        }
+        if let Some(v) = layer.project_id {
+            self.project_id = Some(v);
+        }
+        if let Some(v) = layer.rule_pack {
+            self.rule_pack = Some(v);
+        }
+        if let Some(v) = layer.incremental {
+            self.incremental = v;
+        }
+        if let Some(v) = layer.state_path {
+            self.state_path = v;
+        }
+        if let Some(v) = layer.watermark_field {
+            self.watermark_field = v;
+        }
+        if let Some(v) = layer.full_sync_interval_hours {
+            self.full_sync_interval_hours = v;
+        }
+        if let Some(v) = layer.lossless_decoding {
+            self.lossless_decoding = v;
+        }
+        if let Some(v) = layer.write_back {
+            self.write_back = v;
+        }
+        if let Some(v) = layer.processed_by {
+            self.processed_by = v;
+        }
+        if let Some(v) = layer.archive_dir {
+            self.archive_dir = v;
+        }
+        if let Some(v) = layer.archive_collection {
+            // Empty means no archive collection, e.g. to unset one from the config file.
+            self.archive_collection = Some(v.trim().to_string()).filter(|name| !name.is_empty());
+        }
+    }
+
+    pub fn decode_mode(&self) -> DecodeMode {
+        if self.lossless_decoding {
+            DecodeMode::Lossless
+        } else {
+            DecodeMode::Plain
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (key, value) in [
+            ("collection", &self.collection),
+            ("service_account_file", &self.service_account_file),
+            ("output_path", &self.output_path),
+            ("protocol_path", &self.protocol_path),
+            ("review_path", &self.review_path),
+            ("quarantine_path", &self.quarantine_path),
+            ("state_path", &self.state_path),
+            ("watermark_field", &self.watermark_field),
+            ("processed_by", &self.processed_by),
+            ("archive_dir", &self.archive_dir),
+        ] {
+            if value.trim().is_empty() {
+                bail!("config `{}` must not be empty", key);
+            }
+        }
+        LearningFolderMatcher::from_pattern(&self.learning_folder_pattern)
+            .context("config `learning_folder_pattern` is invalid")?;
+        let base_url = Url::parse(&self.firestore_base_url).with_context(|| {
+            format!(
+                "config `firestore_base_url` is not a URL: {:?}",
+                self.firestore_base_url
+            )
+        })?;
+        if !matches!(base_url.scheme(), "http" | "https") {
+            bail!("config `firestore_base_url` must use http or https");
+        }
+        if self.page_size == 0 {
+            bail!("config `page_size` must be greater than 0");
+        }
+        if self.comment_max_chars == 0 {
+            bail!("config `comment_max_chars` must be greater than 0");
+        }
+        if self.quarantine_score_threshold > self.block_score_threshold {
+            bail!("config `quarantine_score_threshold` must not exceed `block_score_threshold`");
+        }
+        if let Some(archive_collection) = &self.archive_collection {
+            let name = archive_collection.trim();
+            if name.is_empty() || name.contains('/') {
+                bail!(
+                    "config `archive_collection` must be a top-level collection id, got {:?}",
+                    archive_collection
+                );
+            }
+            // Copying into the live collection and then deleting would lose the documents.
+            if name == self.collection.trim() {
+                bail!("config `archive_collection` must differ from `collection`");
+            }
+        }
+        if self.export_roots.is_empty() {
+            bail!("config `export_roots` must list at least one directory");
+        }
+        for root in &self.export_roots {
+            let normalized = root.trim().replace('\\', "/");
+            if normalized.trim_matches('/').is_empty()
+                || Path::new(&normalized).is_absolute()
+                || normalized.split('/').any(|segment| segment == "..")
+            {
+                bail!(
+                    "config `export_roots` entries must be directories inside the repo, got {:?}",
+                    root
+                );
+            }
+        }
+        // The export subdirectory is removed recursively before every export,
+        // so it has to stay a single plain directory name.
+        let subdir = self.learning_export_subdir.trim();
+        if subdir.is_empty()
+            || subdir.starts_with('.')
+            || subdir.contains(['/', '\\'])
+            || Path::new(subdir).is_absolute()
+        {
+            bail!(
+                "config `learning_export_subdir` must be a plain directory name, got {:?}",
+                self.learning_export_subdir
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct RunOptions {
+    pub repo_root: PathBuf,
+    pub config_path: PathBuf,
+    pub config_loaded: bool,
+    pub settings: Settings,
+    pub service_account_path: PathBuf,
+    pub output_path: PathBuf,
+    pub protocol_path: PathBuf,
+    pub review_path: PathBuf,
+    pub quarantine_path: PathBuf,
+    pub state_path: PathBuf,
+    pub archive_dir: PathBuf,
+    pub rule_pack_path: Option<PathBuf>,
+    pub input_path: Option<PathBuf>,
+    pub save_raw_path: Option<PathBuf>,
+    /// Show planned Firestore writes instead of committing them.
+    pub dry_run: bool,
+}
+
+/// What a caller (the CLI, another tool) asks for before the layers are merged.
+#[derive(Debug, Default)]
+pub struct Invocation {
+    /// Default: first parent directory of the current directory containing `.git`.
+    pub repo_root: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    /// Highest-priority layer, e.g. from CLI flags.
+    pub overrides: SettingsLayer,
+    pub input: Option<PathBuf>,
+    pub save_raw: Option<PathBuf>,
+    pub dry_run: bool,
+}
+
+pub fn resolve_run_options(args: Invocation) -> Result<RunOptions> {
+    let repo_root = match args.repo_root.clone() {
+        Some(path) => path,
+        None => {
+            find_repo_root(std::env::current_dir().context("failed to read current directory")?)?
+        }
+    };
+
+    let explicit_config = args
+        .config
+        .clone()
+        .or_else(|| std::env::var_os(format!("{}CONFIG", ENV_PREFIX)).map(PathBuf::from));
+    let config_path = resolve_against(
+        &repo_root,
+        explicit_config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(CONFIG_RELATIVE_PATH)),
+    );
+
+    let mut settings = Settings::default();
+    let config_loaded = match read_settings_file(&config_path, explicit_config.is_some())? {
+        Some(layer) => {
+            settings.apply(layer);
+            true
+        }
+        None => false,
+    };
+    settings.apply(settings_layer_from_env()?);
+    settings.apply(args.overrides);
+    settings.collection = settings.collection.trim().to_string();
+    settings.validate()?;
+
+    let service_account_path =
+        resolve_against(&repo_root, PathBuf::from(&settings.service_account_file));
+    let output_path = resolve_against(&repo_root, PathBuf::from(&settings.output_path));
+    let protocol_path = resolve_against(&repo_root, PathBuf::from(&settings.protocol_path));
+    let review_path = resolve_against(&repo_root, PathBuf::from(&settings.review_path));
+    let quarantine_path = resolve_against(&repo_root, expand_home(&settings.quarantine_path)?);
+    let archive_dir = resolve_against(&repo_root, expand_home(&settings.archive_dir)?);
+    let save_raw_path = args.save_raw.map(|p| resolve_against(&repo_root, p));
+    // All of them hold raw comment text; inside an export root they would be published.
+    for (key, path) in [
+        ("review_path", &review_path),
+        ("quarantine_path", &quarantine_path),
+        ("archive_dir", &archive_dir),
+    ]
+    .into_iter()
+    .chain(save_raw_path.as_ref().map(|path| ("--save-raw", path)))
+    {
+        if let Some(root) = settings
+            .export_roots
+            .iter()
+            .find(|root| path.starts_with(repo_root.join(root.trim().trim_matches('/'))))
+        {
+            bail!(
+                "`{}` {} must not lie inside the export root {:?}",
+                key,
+                path.display(),
+                root
+            );
+        }
+    }
+    let state_path = resolve_against(&repo_root, PathBuf::from(&settings.state_path));
+    let rule_pack_path = settings
+        .rule_pack
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| resolve_against(&repo_root, PathBuf::from(p)));
+    let input_path = args.input.map(|p| resolve_against(&repo_root, p));
+
+    Ok(RunOptions {
+        repo_root,
+        config_path,
+        config_loaded,
+        settings,
+        service_account_path,
+        output_path,
+        protocol_path,
+        review_path,
+        quarantine_path,
+        state_path,
+        archive_dir,
+        rule_pack_path,
+        input_path,
+        save_raw_path,
+        dry_run: args.dry_run,
+    })
+}
+
+/// Reads a TOML settings layer. A missing file is only an error when it was requested explicitly.
+fn read_settings_file(path: &Path, required: bool) -> Result<Option<SettingsLayer>> {
+    if !path.is_file() {
+        if required {
+            bail!("config file {} does not exist", path.display());
+        }
+        return Ok(None);
+    }
+
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    let layer = toml::from_str(&raw)
+        .with_context(|| format!("failed to parse config file {}", path.display()))?;
+    Ok(Some(layer))
+}
+
+fn settings_layer_from_env() -> Result<SettingsLayer> {
+    Ok(SettingsLayer {
+        collection: env_setting("COLLECTION"),
+        service_account_file: env_setting("SERVICE_ACCOUNT_FILE"),
+        output_path: env_setting("OUTPUT_PATH"),
+        protocol_path: env_setting("PROTOCOL_PATH"),
+        review_path: env_setting("REVIEW_PATH"),
+        quarantine_path: env_setting("QUARANTINE_PATH"),
+        page_size: parse_env_setting("PAGE_SIZE")?,
+        learning_export_subdir: env_setting("LEARNING_EXPORT_SUBDIR"),
+        learning_folder_pattern: env_setting("LEARNING_FOLDER_PATTERN"),
+        site_path_prefix: env_setting("SITE_PATH_PREFIX"),
+        export_roots: env_setting("EXPORT_ROOTS").map(|raw| {
+            raw.split(',')
+                .map(str::trim)
+                .filter(|r| !r.is_empty())
+                .map(str::to_string)
+                .collect()
+        }),
+        comment_max_chars: parse_env_setting("COMMENT_MAX_CHARS")?,
+        quarantine_score_threshold: parse_env_setting("QUARANTINE_SCORE_THRESHOLD")?,
+        block_score_threshold: parse_env_setting("BLOCK_SCORE_THRESHOLD")?,
+        firestore_base_url: env_setting("FIRESTORE_BASE_URL"),
+        emulator_host: std::env::var(EMULATOR_HOST_ENV)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .or_else(|| env_setting("EMULATOR_HOST")),
+        project_id: env_setting("PROJECT_ID"),
+        rule_pack: env_setting("RULE_PACK"),
+        incremental: parse_env_setting("INCREMENTAL")?,
+        state_path: env_setting("STATE_PATH"),
+        watermark_field: env_setting("WATERMARK_FIELD"),
+        full_sync_interval_hours: parse_env_setting("FULL_SYNC_INTERVAL_HOURS")?,
+        lossless_decoding: parse_env_setting("LOSSLESS_DECODING")?,
+        write_back: parse_env_setting("WRITE_BACK")?,
+        processed_by: env_setting("PROCESSED_BY"),
+        archive_dir: env_setting("ARCHIVE_DIR"),
+        archive_collection: env_setting("ARCHIVE_COLLECTION"),
+    })
+}
+
+fn env_setting(key: &str) -> Option<String> {
+    std::env::var(format!("{}{}", ENV_PREFIX, key))
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+}
+
+fn parse_env_setting<T>(key: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    env_setting(key)
+        .map(|raw| {
+            raw.trim()
+                .parse::<T>()
+                .with_context(|| format!("invalid value {:?} in {}{}", raw, ENV_PREFIX, key))
+        })
+        .transpose()
+}
+
+pub(crate) fn resolve_against(base: &Path, path: PathBuf) -> PathBuf {
+    if path.is_absolute() {
+        path
+    } else {
+        base.join(path)
+    }
+}
+
+/// Expands a leading `~/` to `HOME` (or `USERPROFILE` on Windows).
+fn expand_home(path: &str) -> Result<PathBuf> {
+    let Some(rest) = path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")) else {
+        return Ok(PathBuf::from(path));
+    };
+    let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) else {
+        bail!("cannot expand {:?}: HOME is not set", path);
+    };
+    Ok(PathBuf::from(home).join(rest))
+}
+
+fn find_repo_root(start: PathBuf) -> Result<PathBuf> {
+    let mut cursor = start;
+    loop {
+        if cursor.join(".git").is_dir() {
+            return Ok(cursor);
+        }
+        if !cursor.pop() {
+            bail!("could not find repository root (.git directory)");
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Labeled regression corpus for the rule pack, scored per rule (precision/recall) by `test-rules`.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::rules::{CommentContext, RuleAction};
+use crate::sanitize::{CommentSanitizer, RuleSanitizer};
+
+/// The corpus shipped in `rules/corpus.toml`, used when `test-rules` gets no `--corpus`.
+const BUILTIN_CORPUS: &str = include_str!("../rules/corpus.toml");
+const BUILTIN_CORPUS_ORIGIN: &str = "built-in rules/corpus.toml";
+const SUPPORTED_FORMAT: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CorpusFile {
+    format: u32,
+    cases: Vec<CorpusCase>,
+}
+
+/// Ground truth for a comment, independent of what the current rules make of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseLabel {
+    Benign,
+    Injection,
+}
+
+impl CaseLabel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CaseLabel::Benign => "benign",
+            CaseLabel::Injection => "injection",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorpusCase {
+    pub id: String,
+    pub text: String,
+    pub label: CaseLabel,
+    /// Rules that should fire, whatever their action; any other rule firing is a false positive.
+    #[serde(default)]
+    pub rules: Vec<String>,
+    /// `source` of the document, for source policies.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Learning folder of the game, for topic policies.
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Set while the pack is known to get this case wrong; such cases do not fail the run.
+    #[serde(default)]
+    pub known_issue: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Corpus {
+    pub cases: Vec<CorpusCase>,
+}
+
+impl Corpus {
+    pub fn builtin() -> Self {
+        Corpus::parse(BUILTIN_CORPUS, BUILTIN_CORPUS_ORIGIN).expect("built-in corpus is valid")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("failed to read corpus {}", path.display()))?;
+        Corpus::parse(&raw, &path.display().to_string())
+    }
+
+    /// Parses and validates a corpus; `origin` only appears in error messages.
+    pub fn parse(raw: &str, origin: &str) -> Result<Self> {
+        let file: CorpusFile =
+            toml::from_str(raw).with_context(|| format!("failed to parse corpus {}", origin))?;
+        if file.format != SUPPORTED_FORMAT {
+            bail!(
+                "corpus {} has format {}, supported is {}",
+                origin,
+                file.format,
+                SUPPORTED_FORMAT
+            );
+        }
+        let mut seen_ids = HashSet::new();
+        for case in &file.cases {
+            if case.id.trim().is_empty() || case.text.is_empty() {
+                bail!("corpus {} has a case without `id` or `text`", origin);
+            }
+            if !seen_ids.insert(case.id.as_str()) {
+                bail!("corpus {} defines case `{}` twice", origin, case.id);
+            }
+        }
+        Ok(Corpus { cases: file.cases })
+    }
+}
+
+#[derive(Debug)]
+pub struct CaseResult {
+    pub id: String,
+    pub label: CaseLabel,
+    pub blocked: bool,
+    pub expected_rules: BTreeSet<String>,
+    pub fired_rules: BTreeSet<String>,
+    pub known_issue: Option<String>,
+}
+
+impl CaseResult {
+    pub fn decision_correct(&self) -> bool {
+        self.blocked == (self.label == CaseLabel::Injection)
+    }
+
+    pub fn missing_rules(&self) -> Vec<&str> {
+        self.expected_rules
+            .difference(&self.fired_rules)
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn unexpected_rules(&self) -> Vec<&str> {
+        self.fired_rules
+            .difference(&self.expected_rules)
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.decision_correct() && self.expected_rules == self.fired_rules
+    }
+}
+
+/// Confusion counts of one rule, or of the withhold decision as a whole.
+#[derive(Debug, Clone, Default)]
+pub struct Score {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub false_negatives: usize,
+}
+
+impl Score {
+    fn count(&mut self, expected: bool, actual: bool) {
+        match (expected, actual) {
+            (true, true) => self.true_positives += 1,
+            (false, true) => self.false_positives += 1,
+            (true, false) => self.false_negatives += 1,
+            (false, false) => {}
+        }
+    }
+
+    /// `None` when the rule never fired.
+    pub fn precision(&self) -> Option<f64> {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+
+    /// `None` when no case expects the rule.
+    pub fn recall(&self) -> Option<f64> {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+#[derive(Debug)]
+pub struct RuleScore {
+    pub rule_id: String,
+    pub action: RuleAction,
+    pub score: Score,
+}
+
+#[derive(Debug)]
+pub struct CorpusEvaluation {
+    pub cases: Vec<CaseResult>,
+    /// In rule pack order.
+    pub rules: Vec<RuleScore>,
+    /// Withheld (quarantined or blocked) vs. labeled `injection`.
+    pub decision: Score,
+}
+
+impl CorpusEvaluation {
+    /// Cases that disagree with their label or expected rules and are not marked `known_issue`.
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.cases
+            .iter()
+            .filter(|case| !case.passed() && case.known_issue.is_none())
+    }
+}
+
+/// Runs every case through `sanitizer` and scores each rule of its pack.
+pub fn evaluate(corpus: &Corpus, sanitizer: &RuleSanitizer) -> Result<CorpusEvaluation> {
+    let pack = sanitizer.rules();
+    let known_rules: HashSet<&str> = pack
+        .rules
+        .iter()
+        .map(|rule| rule.spec.id.as_str())
+        .collect();
+    for case in &corpus.cases {
+        if let Some(unknown) = case
+            .rules
+            .iter()
+            .find(|id| !known_rules.contains(id.as_str()))
+        {
+            bail!(
+                "corpus case `{}` expects rule `{}`, which is not in rule pack {}",
+                case.id,
+                unknown,
+                sanitizer.version()
+            );
+        }
+    }
+
+    let mut rules: Vec<RuleScore> = pack
+        .rules
+        .iter()
+        .map(|rule| RuleScore {
+            rule_id: rule.spec.id.clone(),
+            action: rule.spec.action,
+            score: Score::default(),
+        })
+        .collect();
+    let mut decision = Score::default();
+    let mut cases = Vec::with_capacity(corpus.cases.len());
+
+    for case in &corpus.cases {
+        let context = CommentContext {
+            source: case.source.clone(),
+            learning_folder: case.path.clone(),
+        };
+        let outcome = sanitizer.sanitize_in_context(&case.text, &context);
+        let fired_rules = fired_rule_ids(&outcome.reasons);
+        let expected_rules: BTreeSet<String> = case.rules.iter().cloned().collect();
+        for rule in &mut rules {
+            rule.score.count(
+                expected_rules.contains(&rule.rule_id),
+                fired_rules.contains(&rule.rule_id),
+            );
+        }
+        decision.count(case.label == CaseLabel::Injection, outcome.blocked);
+        cases.push(CaseResult {
+            id: case.id.clone(),
+            label: case.label,
+            blocked: outcome.blocked,
+            expected_rules,
+            fired_rules,
+            known_issue: case.known_issue.clone(),
+        });
+    }
+
+    Ok(CorpusEvaluation {
+        cases,
+        rules,
+        decision,
+    })
+}
+
+/// Rule ids from `detected:<id>[@...]`, `flagged:<id>[@...]` and `redacted:<id>` reasons.
+fn fired_rule_ids(reasons: &[String]) -> BTreeSet<String> {
+    reasons
+        .iter()
+        .filter_map(|reason| {
+            reason
+                .strip_prefix("detected:")
+                .or_else(|| reason.strip_prefix("flagged:"))
+                .or_else(|| reason.strip_prefix("redacted:"))
+        })
+        .map(|id| id.split('@').next().unwrap_or(id).to_string())
+        .collect()
+}
@@ -0,0 +1,189 @@
+//! Decoding of Firestore REST values into plain JSON.
+
+use serde_json::{Map, Number, Value};
+
+use crate::lossless::decode_lossless_fields;
+
+/// How Firestore values become JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeMode {
+    /// Timestamps, references and bytes become strings; easiest to read.
+    #[default]
+    Plain,
+    /// Keeps every Firestore type, see [`crate::lossless`].
+    Lossless,
+}
+
+impl DecodeMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DecodeMode::Plain => "plain",
+            DecodeMode::Lossless => "lossless",
+        }
+    }
+}
+
+/// A Firestore document with its fields decoded into plain JSON.
+#[derive(Debug, Clone)]
+pub struct DecodedDocument {
+    pub name: String,
+    pub create_time: String,
+    pub update_time: String,
+    pub data: Value,
+}
+
+pub fn decode_raw_document(raw_doc: &Value) -> DecodedDocument {
+    decode_raw_document_with(raw_doc, DecodeMode::Plain)
+}
+
+pub fn decode_raw_document_with(raw_doc: &Value, mode: DecodeMode) -> DecodedDocument {
+    let fields = raw_doc
+        .get("fields")
+        .cloned()
+        .unwrap_or_else(|| Value::Object(Map::new()));
+
+    DecodedDocument {
+        name: string_field(raw_doc, "name"),
+        create_time: string_field(raw_doc, "createTime"),
+        update_time: string_field(raw_doc, "updateTime"),
+        data: match mode {
+            DecodeMode::Plain => decode_firestore_fields(&fields),
+            DecodeMode::Lossless => decode_lossless_fields(&fields),
+        },
+    }
+}
+
+pub(crate) fn decoded_documents_from_output(payload: &Value) -> Vec<DecodedDocument> {
+    payload
+        .get("documents")
+        .and_then(Value::as_array)
+        .map(|docs| {
+            docs.iter()
+                .map(|doc| DecodedDocument {
+                    name: string_field(doc, "name"),
+                    create_time: string_field(doc, "createTime"),
+                    update_time: string_field(doc, "updateTime"),
+                    data: doc
+                        .get("data")
+                        .cloned()
+                        .unwrap_or_else(|| Value::Object(Map::new())),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn string_field(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub(crate) fn extract_document_id(full_name: &str) -> String {
+    full_name
+        .rsplit('/')
+        .next()
+        .map(|v| v.to_string())
+        .unwrap_or_default()
+}
+
+pub fn decode_firestore_fields(fields: &Value) -> Value {
+    let Some(object) = fields.as_object() else {
+        return Value::Object(Map::new());
+    };
+
+    let mut decoded = Map::new();
+    for (key, value) in object {
+        decoded.insert(key.clone(), decode_firestore_value(value));
+    }
+
+    Value::Object(decoded)
+}
+
+pub fn decode_firestore_value(value: &Value) -> Value {
+    let Some(object) = value.as_object() else {
+        return Value::Null;
+    };
+
+    if object.contains_key("nullValue") {
+        return Value::Null;
+    }
+    if let Some(v) = object.get("booleanValue").and_then(Value::as_bool) {
+        return Value::Bool(v);
+    }
+    if let Some(v) = object.get("stringValue").and_then(Value::as_str) {
+        return Value::String(v.to_string());
+    }
+    if let Some(v) = object.get("timestampValue").and_then(Value::as_str) {
+        return Value::String(v.to_string());
+    }
+    if let Some(v) = object.get("referenceValue").and_then(Value::as_str) {
+        return Value::String(v.to_string());
+    }
+    if let Some(v) = object.get("bytesValue").and_then(Value::as_str) {
+        return Value::String(v.to_string());
+    }
+    if let Some(v) = object.get("integerValue").and_then(Value::as_str) {
+        if let Ok(parsed) = v.parse::<i64>() {
+            return Value::Number(Number::from(parsed));
+        }
+        return Value::String(v.to_string());
+    }
+    if let Some(raw_double) = object.get("doubleValue") {
+        if let Some(parsed) = parse_firestore_number(raw_double) {
+            if let Some(num) = Number::from_f64(parsed) {
+                return Value::Number(num);
+            }
+        }
+    }
+    if let Some(geo) = object.get("geoPointValue").and_then(Value::as_object) {
+        let mut decoded_geo = Map::new();
+        if let Some(lat) = geo.get("latitude").and_then(parse_firestore_number) {
+            if let Some(n) = Number::from_f64(lat) {
+                decoded_geo.insert("latitude".to_string(), Value::Number(n));
+            }
+        }
+        if let Some(lng) = geo.get("longitude").and_then(parse_firestore_number) {
+            if let Some(n) = Number::from_f64(lng) {
+                decoded_geo.insert("longitude".to_string(), Value::Number(n));
+            }
+        }
+        if !decoded_geo.is_empty() {
+            return Value::Object(decoded_geo);
+        }
+        return Value::Object(geo.clone());
+    }
+    if let Some(array_obj) = object.get("arrayValue").and_then(Value::as_object) {
+        if let Some(values) = array_obj.get("values").and_then(Value::as_array) {
+            return Value::Array(values.iter().map(decode_firestore_value).collect());
+        }
+        return Value::Array(Vec::new());
+    }
+    if let Some(map_obj) = object.get("mapValue").and_then(Value::as_object) {
+        let fields = map_obj
+            .get("fields")
+            .cloned()
+            .unwrap_or_else(|| Value::Object(Map::new()));
+        return decode_firestore_fields(&fields);
+    }
+
+    Value::Object(object.clone())
+}
+
+fn parse_firestore_number(value: &Value) -> Option<f64> {
+    if let Some(v) = value.as_f64() {
+        return Some(v);
+    }
+    if let Some(v) = value.as_i64() {
+        return Some(v as f64);
+    }
+    if let Some(v) = value.as_u64() {
+        return Some(v as f64);
+    }
+    if let Some(v) = value.as_str() {
+        return v.parse::<f64>().ok();
+    }
+    None
+}
@@ -0,0 +1,192 @@
+//! The detection view: a folded, de-obfuscated copy of a comment that only the detection rules see.
+//!
+//! The stored comment keeps its readable form; this view undoes the usual tricks to slip past the
+//! patterns: look-alike letters from other scripts, leetspeak, spaced-out letters and punctuation
+//! inside words.
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters that are dropped when they sit between two word characters (`i.g.n.o.r.e`).
+const SPLIT_CHARS: &[char] = &['.', '-', '_', '*', '|', '+', '~', '\'', '^', '·', '•'];
+
+/// Minimum number of single characters in a row before `i g n o r e` is joined.
+const MIN_SPACED_RUN: usize = 3;
+
+/// Builds the detection view of `text`.
+///
+/// Steps, in order: lowercase and confusables skeleton (umlauts become `ae`/`oe`/`ue`, accents
+/// are dropped), punctuation inside words removed, runs of single spaced-out characters joined
+/// (a gap of two or more whitespace characters separates words), leetspeak mapped in tokens that
+/// contain a letter, whitespace compacted.
+pub fn detection_view(text: &str) -> String {
+    let folded = fold_characters(text);
+    let joined_words = remove_split_chars(&folded);
+    let tokens = join_spaced_characters(&joined_words);
+    tokens
+        .iter()
+        .map(|token| map_leetspeak(token))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn fold_characters(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match c {
+            'ä' => folded.push_str("ae"),
+            'ö' => folded.push_str("oe"),
+            'ü' => folded.push_str("ue"),
+            'ß' => folded.push_str("ss"),
+            _ => {
+                for d in c.nfd().filter(|d| !is_combining_mark(*d)) {
+                    folded.push(confusable_skeleton(d));
+                }
+            }
+        }
+    }
+    folded
+}
+
+/// Maps Cyrillic, Greek and other look-alikes of Latin letters (lowercase) to the Latin letter.
+fn confusable_skeleton(c: char) -> char {
+    match c {
+        // Cyrillic
+        'а' => 'a',
+        'в' => 'b',
+        'г' => 'r',
+        'е' | 'ё' | 'є' => 'e',
+        'і' | 'ї' | 'ӏ' => 'i',
+        'ј' => 'j',
+        'к' => 'k',
+        'м' => 'm',
+        'н' | 'һ' => 'h',
+        'о' => 'o',
+        'п' => 'n',
+        'р' => 'p',
+        'с' => 'c',
+        'т' => 't',
+        'у' | 'ү' => 'y',
+        'х' => 'x',
+        'ѕ' => 's',
+        'ԁ' => 'd',
+        'ԛ' => 'q',
+        'ԝ' => 'w',
+        // Greek
+        'α' => 'a',
+        'β' => 'b',
+        'γ' => 'y',
+        'ε' => 'e',
+        'ζ' => 'z',
+        'η' => 'n',
+        'ι' => 'i',
+        'κ' => 'k',
+        'μ' | 'υ' => 'u',
+        'ν' => 'v',
+        'ο' => 'o',
+        'ρ' => 'p',
+        'ς' => 's',
+        'τ' => 't',
+        'χ' => 'x',
+        'ω' => 'w',
+        // Armenian and Latin variants
+        'օ' => 'o',
+        'ս' => 'u',
+        'հ' => 'h',
+        'ı' => 'i',
+        'ɑ' => 'a',
+        'ɡ' => 'g',
+        'ʟ' => 'l',
+        _ => c,
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '@' || c == '$'
+}
+
+fn remove_split_chars(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut kept = String::with_capacity(text.len());
+    for (index, &c) in chars.iter().enumerate() {
+        let between_words = index > 0
+            && index + 1 < chars.len()
+            && is_word_char(chars[index - 1])
+            && is_word_char(chars[index + 1]);
+        if between_words && SPLIT_CHARS.contains(&c) {
+            continue;
+        }
+        kept.push(c);
+    }
+    kept
+}
+
+/// Splits on whitespace and joins runs of single characters that are one whitespace apart.
+fn join_spaced_characters(text: &str) -> Vec<String> {
+    let mut tokens: Vec<(String, usize)> = Vec::new();
+    let mut gap = 0usize;
+    let mut current = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !current.is_empty() {
+                tokens.push((std::mem::take(&mut current), gap));
+                gap = 0;
+            }
+            gap += 1;
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push((current, gap));
+    }
+
+    let is_single = |token: &str| {
+        let mut chars = token.chars();
+        matches!((chars.next(), chars.next()), (Some(c), None) if is_word_char(c))
+    };
+
+    let mut words = Vec::with_capacity(tokens.len());
+    let mut index = 0;
+    while index < tokens.len() {
+        let mut end = index + 1;
+        if is_single(&tokens[index].0) {
+            while end < tokens.len() && tokens[end].1 == 1 && is_single(&tokens[end].0) {
+                end += 1;
+            }
+        }
+        let run = &tokens[index..end];
+        if run.len() >= MIN_SPACED_RUN
+            && run.iter().any(|(t, _)| t.chars().any(char::is_alphabetic))
+        {
+            words.push(run.iter().map(|(t, _)| t.as_str()).collect());
+        } else {
+            words.extend(run.iter().map(|(t, _)| t.clone()));
+        }
+        index = end;
+    }
+    words
+}
+
+fn map_leetspeak(token: &str) -> String {
+    if !token.chars().any(|c| c.is_ascii_alphabetic()) {
+        return token.to_string();
+    }
+    let chars: Vec<char> = token.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .map(|(index, &c)| match c {
+            '0' => 'o',
+            '1' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            '8' => 'b',
+            '9' => 'g',
+            '!' if chars.get(index + 1).is_some_and(char::is_ascii_alphabetic) => 'i',
+            _ => c,
+        })
+        .collect()
+}
@@ -0,0 +1,173 @@
+//! Finds base64, hex, URL-encoded and rot13 payloads inside a comment and decodes them, so the
+//! detection rules can look at what an encoded instruction would say.
+
+use std::fmt;
+
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+use once_cell::sync::Lazy;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+
+/// How deep decoded payloads are decoded again (`base64` inside `url` counts as 2).
+pub const MAX_DECODE_DEPTH: usize = 3;
+/// Segments decoded per comment over all depths; later ones are ignored.
+pub const MAX_SEGMENTS: usize = 16;
+/// Longest encoded segment that is decoded, in bytes.
+pub const MAX_SEGMENT_BYTES: usize = 8192;
+/// Fewest letters a decoded payload needs to count as text.
+const MIN_DECODED_LETTERS: usize = 4;
+
+static BASE64_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[A-Za-z0-9+/_-]{16,}={0,2}").expect("valid regex"));
+static HEX_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)(?:\\x[0-9a-f]{2}){4,}|(?:0x)?(?:[0-9a-f]{2}){8,}|(?:[0-9a-f]{2}[ :]){7,}[0-9a-f]{2}",
+    )
+    .expect("valid regex")
+});
+static URL_ENCODED_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[^\s%]*(?:%[0-9A-Fa-f]{2}[^\s%]*){3,}").expect("valid regex"));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Base64,
+    Hex,
+    Url,
+    Rot13,
+}
+
+impl Encoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Base64 => "base64",
+            Encoding::Hex => "hex",
+            Encoding::Url => "url",
+            Encoding::Rot13 => "rot13",
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedSegment {
+    pub encoding: Encoding,
+    pub text: String,
+}
+
+/// Decodes up to `limit` encoded segments of `text`, one level deep.
+///
+/// Only results that are valid UTF-8 and read like text are returned. rot13 has no visible
+/// marker, so the whole text is rotated once whenever it has enough letters; `skip_rot13` avoids
+/// rotating a rot13 result back.
+pub fn decode_segments(text: &str, skip_rot13: bool, limit: usize) -> Vec<DecodedSegment> {
+    let mut segments = Vec::new();
+
+    for found in URL_ENCODED_RE.find_iter(text) {
+        let decoded = percent_decode_str(&found.as_str().replace('+', " "))
+            .decode_utf8()
+            .ok()
+            .map(|text| text.into_owned());
+        push_segment(&mut segments, limit, Encoding::Url, found.as_str(), decoded);
+    }
+    for found in HEX_RE.find_iter(text) {
+        let decoded = decode_hex(found.as_str()).and_then(|bytes| String::from_utf8(bytes).ok());
+        push_segment(&mut segments, limit, Encoding::Hex, found.as_str(), decoded);
+    }
+    for found in BASE64_RE.find_iter(text) {
+        let decoded = decode_base64(found.as_str()).and_then(|bytes| String::from_utf8(bytes).ok());
+        push_segment(
+            &mut segments,
+            limit,
+            Encoding::Base64,
+            found.as_str(),
+            decoded,
+        );
+    }
+    if !skip_rot13 && text.len() <= MAX_SEGMENT_BYTES {
+        let rotated = rot13(text);
+        push_segment(&mut segments, limit, Encoding::Rot13, text, Some(rotated));
+    }
+
+    segments
+}
+
+fn push_segment(
+    segments: &mut Vec<DecodedSegment>,
+    limit: usize,
+    encoding: Encoding,
+    raw: &str,
+    decoded: Option<String>,
+) {
+    if segments.len() >= limit || raw.len() > MAX_SEGMENT_BYTES {
+        return;
+    }
+    let Some(decoded) = decoded else {
+        return;
+    };
+    if decoded == raw || !looks_like_text(&decoded) {
+        return;
+    }
+    segments.push(DecodedSegment {
+        encoding,
+        text: decoded,
+    });
+}
+
+fn looks_like_text(decoded: &str) -> bool {
+    let total = decoded.chars().count();
+    let letters = decoded.chars().filter(|c| c.is_alphabetic()).count();
+    let printable = decoded
+        .chars()
+        .filter(|c| !c.is_control() || c.is_whitespace())
+        .count();
+    letters >= MIN_DECODED_LETTERS && printable * 100 >= total * 95
+}
+
+fn decode_base64(segment: &str) -> Option<Vec<u8>> {
+    let url_safe = segment.contains(['-', '_']);
+    if url_safe && segment.contains(['+', '/']) {
+        return None;
+    }
+    let engines = if url_safe {
+        [&URL_SAFE, &URL_SAFE_NO_PAD]
+    } else {
+        [&STANDARD, &STANDARD_NO_PAD]
+    };
+    engines
+        .iter()
+        .find_map(|engine| engine.decode(segment).ok())
+}
+
+fn decode_hex(segment: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = segment
+        .trim_start_matches("0x")
+        .trim_start_matches("0X")
+        .replace("\\x", "")
+        .replace("\\X", "")
+        .bytes()
+        .filter(u8::is_ascii_hexdigit)
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn rot13(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'a'..='m' | 'A'..='M' => (c as u8 + 13) as char,
+            'n'..='z' | 'N'..='Z' => (c as u8 - 13) as char,
+            _ => c,
+        })
+        .collect()
+}
@@ -0,0 +1,1090 @@
+//! Learning export: the [`FeedbackSink`] stage and its default [`LearningFolderSink`], which
+//! routes every feedback into the learning folder of its game.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::{json, Value};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::{RunOptions, Settings};
+use crate::model::{FeedbackContext, FeedbackDocument};
+use crate::sanitize::{BLOCKED_COMMENT_TOKEN, EMPTY_COMMENT_TOKEN};
+
+/// Last pipeline stage: receives the mapped documents of the output JSON.
+pub trait FeedbackSink {
+    fn export(&mut self, mapped_docs: &[Value]) -> Result<LearningExportSummary>;
+}
+
+/// Writes every unblocked feedback into `<learning folder>/<learning_export_subdir>/`.
+#[derive(Debug, Clone)]
+pub struct LearningFolderSink {
+    repo_root: PathBuf,
+    settings: Settings,
+}
+
+impl LearningFolderSink {
+    pub fn new(repo_root: PathBuf, settings: Settings) -> Self {
+        LearningFolderSink {
+            repo_root,
+            settings,
+        }
+    }
+
+    pub fn from_options(options: &RunOptions) -> Self {
+        LearningFolderSink::new(options.repo_root.clone(), options.settings.clone())
+    }
+}
+
+impl FeedbackSink for LearningFolderSink {
+    fn export(&mut self, mapped_docs: &[Value]) -> Result<LearningExportSummary> {
+        export_feedback_to_learning_folders(&self.repo_root, mapped_docs, &self.settings)
+    }
+}
+
+#[derive(Debug)]
+pub struct LearningExportSummary {
+    pub checked_documents: usize,
+    pub exported_feedbacks: usize,
+    pub filtered_feedbacks: usize,
+    pub unresolved_folder_feedbacks: usize,
+    pub rejected_candidates: usize,
+    pub learning_folder_pattern: String,
+    pub export_roots: Vec<String>,
+    pub learning_folders_found: usize,
+    pub game_folders_indexed: usize,
+    pub written_paths: Vec<PathBuf>,
+    pub routes: Vec<FeedbackRoute>,
+}
+
+fn export_feedback_to_learning_folders(
+    repo_root: &Path,
+    mapped_docs: &[Value],
+    settings: &Settings,
+) -> Result<LearningExportSummary> {
+    let resolver = LearningFolderResolver::new(repo_root, settings)?;
+    cleanup_previous_learning_exports(
+        &resolver.learning_folders,
+        &settings.learning_export_subdir,
+    )?;
+    let router = resolver.router();
+
+    let mut written_paths: Vec<PathBuf> = Vec::new();
+    let mut used_paths: HashSet<String> = HashSet::new();
+    let mut routes: Vec<FeedbackRoute> = Vec::new();
+    let mut filtered_feedbacks = 0usize;
+    let mut unresolved_folder_feedbacks = 0usize;
+    let mut rejected_candidates = 0usize;
+
+    for doc in mapped_docs {
+        let doc_id = doc
+            .get("id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+
+        let Some(data) = doc.get("data").filter(|data| data.is_object()) else {
+            filtered_feedbacks += 1;
+            routes.push(FeedbackRoute::filtered(&doc_id, "no_data"));
+            continue;
+        };
+        let feedback = FeedbackDocument::from_data(data);
+
+        let blocked_fields = doc
+            .get("commentSecurity")
+            .and_then(Value::as_object)
+            .and_then(|s| s.get("blockedFields"))
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+
+        let comment_text = feedback.comment.as_deref().unwrap_or_default().trim();
+
+        let filter_reason = if blocked_fields > 0 || comment_text == BLOCKED_COMMENT_TOKEN {
+            Some("blocked_comment")
+        } else if comment_text.is_empty() || comment_text == EMPTY_COMMENT_TOKEN {
+            Some("empty_comment")
+        } else {
+            None
+        };
+        if let Some(reason) = filter_reason {
+            filtered_feedbacks += 1;
+            routes.push(FeedbackRoute::filtered(&doc_id, reason));
+            continue;
+        }
+
+        let resolution = router.resolve(&feedback);
+        rejected_candidates += resolution.rejections.len();
+        let Some(resolved) = resolution.resolved else {
+            unresolved_folder_feedbacks += 1;
+            routes.push(FeedbackRoute {
+                id: doc_id,
+                outcome: "unresolved",
+                reason: Some(
+                    if resolution.candidates_checked == 0 {
+                        "no_candidate_paths"
+                    } else if resolution.rejections.len() == resolution.candidates_checked {
+                        "all_candidates_rejected"
+                    } else {
+                        "no_learning_folder_for_candidates"
+                    }
+                    .to_string(),
+                ),
+                rejected_candidates: resolution.rejections,
+                ..FeedbackRoute::default()
+            });
+            continue;
+        };
+
+        let export_dir = resolved
+            .learning_folder
+            .join(&settings.learning_export_subdir);
+        fs::create_dir_all(&export_dir).with_context(|| {
+            format!(
+                "failed to create learning export directory {}",
+                export_dir.display()
+            )
+        })?;
+        // Discovery skips symlinks, but the export directory may have been replaced by one since.
+        if !router.is_confined(&export_dir) {
+            unresolved_folder_feedbacks += 1;
+            rejected_candidates += 1;
+            let mut rejections = resolution.rejections;
+            rejections.push(CandidateRejection {
+                field: resolved.field,
+                candidate: resolved.candidate,
+                reason: "symlink_escape",
+            });
+            routes.push(FeedbackRoute {
+                id: doc_id,
+                outcome: "unresolved",
+                reason: Some("export_dir_outside_export_roots".to_string()),
+                rejected_candidates: rejections,
+                ..FeedbackRoute::default()
+            });
+            continue;
+        }
+
+        let safe_id = sanitize_file_component(if doc_id.is_empty() {
+            "unknown"
+        } else {
+            &doc_id
+        });
+        let mut target_path = export_dir.join(format!("feedback_{}.json", safe_id));
+        let mut suffix = 2usize;
+
+        loop {
+            let unique_key = target_path.to_string_lossy().to_string();
+            if !used_paths.contains(&unique_key) {
+                used_paths.insert(unique_key);
+                break;
+            }
+            target_path = export_dir.join(format!("feedback_{}_{}.json", safe_id, suffix));
+            suffix += 1;
+        }
+
+        let export_payload = json!({
+            "id": doc_id,
+            "source": feedback.source,
+            "comment": feedback.comment,
+            "createdAtIso": feedback.created_at_iso,
+            "context": feedback.context,
+            "commentSecurity": doc.get("commentSecurity").cloned().unwrap_or(Value::Null)
+        });
+
+        let encoded = serde_json::to_vec_pretty(&export_payload)
+            .context("failed to serialize learning export payload")?;
+        fs::write(&target_path, encoded).with_context(|| {
+            format!(
+                "failed to write learning export file {}",
+                target_path.display()
+            )
+        })?;
+
+        routes.push(FeedbackRoute {
+            id: doc_id,
+            outcome: "exported",
+            reason: None,
+            matched_field: Some(resolved.field),
+            candidate: Some(resolved.candidate),
+            learning_folder: Some(path_to_repo_relative(repo_root, &resolved.learning_folder)),
+            written_path: Some(path_to_repo_relative(repo_root, &target_path)),
+            rejected_candidates: resolution.rejections,
+        });
+        written_paths.push(target_path);
+    }
+
+    written_paths.sort();
+
+    Ok(LearningExportSummary {
+        checked_documents: mapped_docs.len(),
+        exported_feedbacks: written_paths.len(),
+        filtered_feedbacks,
+        unresolved_folder_feedbacks,
+        rejected_candidates,
+        learning_folder_pattern: resolver.matcher.pattern.clone(),
+        export_roots: resolver
+            .export_roots
+            .iter()
+            .map(|r| path_to_repo_relative(repo_root, &r.path))
+            .collect(),
+        learning_folders_found: resolver.learning_folders.len(),
+        game_folders_indexed: resolver.game_index.game_folders,
+        written_paths,
+        routes,
+    })
+}
+
+/// The learning folders below the export roots and the game index, to route feedback documents
+/// without exporting them.
+#[derive(Debug)]
+pub struct LearningFolderResolver {
+    repo_root: PathBuf,
+    matcher: LearningFolderMatcher,
+    export_roots: Vec<ExportRoot>,
+    learning_folders: Vec<PathBuf>,
+    site_path_prefix: String,
+    game_index: GameIndex,
+}
+
+impl LearningFolderResolver {
+    pub fn new(repo_root: &Path, settings: &Settings) -> Result<Self> {
+        let matcher = LearningFolderMatcher::from_pattern(&settings.learning_folder_pattern)?;
+        let export_roots = resolve_export_roots(repo_root, &settings.export_roots)?;
+        let mut learning_folders = Vec::new();
+        for root in &export_roots {
+            learning_folders.extend(discover_learning_folders(repo_root, &root.path, &matcher)?);
+        }
+        learning_folders.sort();
+        let game_index = build_game_index(
+            repo_root,
+            &export_roots
+                .iter()
+                .map(|r| r.path.clone())
+                .collect::<Vec<PathBuf>>(),
+        )?;
+        Ok(LearningFolderResolver {
+            repo_root: repo_root.to_path_buf(),
+            matcher,
+            export_roots,
+            learning_folders,
+            site_path_prefix: settings.site_path_prefix.clone(),
+            game_index,
+        })
+    }
+
+    /// The repo-relative learning folder a document's `data` is exported to, confined to the
+    /// export roots like the export itself.
+    pub fn learning_folder(&self, data: &Value) -> Option<String> {
+        let resolved = self
+            .router()
+            .resolve(&FeedbackDocument::from_data(data))
+            .resolved?;
+        Some(path_to_repo_relative(
+            &self.repo_root,
+            &resolved.learning_folder,
+        ))
+    }
+
+    fn router(&self) -> LearningFolderRouter<'_> {
+        LearningFolderRouter {
+            repo_root: &self.repo_root,
+            export_roots: &self.export_roots,
+            learning_folders: &self.learning_folders,
+            matcher: &self.matcher,
+            site_path_prefix: &self.site_path_prefix,
+            game_index: &self.game_index,
+        }
+    }
+}
+
+/// Decides which directories count as learning folders.
+///
+/// The pattern describes the learning folder relative to its game folder, e.g. the default
+/// `__dokumentation/__04_lernings`. Plain patterns are globs (`*` stays within one path segment,
+/// `**` crosses segments); a `regex:` prefix switches to a regular expression. Paths are compared
+/// with `/` separators.
+#[derive(Debug)]
+pub struct LearningFolderMatcher {
+    pattern: String,
+    /// Matches the tail of a repo-relative path.
+    tail: Regex,
+    /// Matches a path relative to the game folder exactly.
+    exact: Regex,
+}
+
+impl LearningFolderMatcher {
+    pub fn from_pattern(pattern: &str) -> Result<Self> {
+        let trimmed = pattern.trim();
+        let body = match trimmed.strip_prefix("regex:") {
+            Some(regex) => regex.trim().to_string(),
+            None => glob_to_regex(trimmed.trim_matches('/')),
+        };
+        if body.is_empty() {
+            bail!("learning folder pattern must not be empty");
+        }
+
+        let tail = Regex::new(&format!("(?:^|/)(?:{})$", body))
+            .with_context(|| format!("invalid learning folder pattern {:?}", pattern))?;
+        let exact = Regex::new(&format!("^(?:{})$", body))
+            .with_context(|| format!("invalid learning folder pattern {:?}", pattern))?;
+
+        Ok(LearningFolderMatcher {
+            pattern: trimmed.to_string(),
+            tail,
+            exact,
+        })
+    }
+
+    /// Whether `path` ends in the pattern, as during discovery below an export root.
+    pub fn is_learning_folder(&self, repo_root: &Path, path: &Path) -> bool {
+        self.tail.is_match(&path_to_repo_relative(repo_root, path))
+    }
+
+    /// Whether `path` is exactly the learning folder of `game_folder`.
+    pub fn is_learning_folder_of(&self, game_folder: &Path, path: &Path) -> bool {
+        path.strip_prefix(game_folder)
+            .map(|rel| {
+                self.exact
+                    .is_match(&rel.to_string_lossy().replace('\\', "/"))
+            })
+            .unwrap_or(false)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    out.push_str("(?:.*/)?");
+                } else {
+                    out.push_str(".*");
+                }
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            other => out.push_str(&regex::escape(&other.to_string())),
+        }
+    }
+    out
+}
+
+/// How one feedback document was handled by the learning export.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedbackRoute {
+    pub id: String,
+    /// `exported`, `filtered` or `unresolved`.
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub learning_folder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub written_path: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rejected_candidates: Vec<CandidateRejection>,
+}
+
+/// A candidate path that was not followed because it points outside the export roots.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidateRejection {
+    pub field: String,
+    pub candidate: String,
+    /// `absolute_path`, `parent_traversal`, `outside_export_roots`, `symlink_escape` or `unreadable_path`.
+    pub reason: &'static str,
+}
+
+impl FeedbackRoute {
+    fn filtered(id: &str, reason: &str) -> Self {
+        FeedbackRoute {
+            id: id.to_string(),
+            outcome: "filtered",
+            reason: Some(reason.to_string()),
+            ..FeedbackRoute::default()
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ResolvedLearningFolder {
+    learning_folder: PathBuf,
+    field: String,
+    candidate: String,
+}
+
+#[derive(Debug)]
+struct LearningFolderResolution {
+    resolved: Option<ResolvedLearningFolder>,
+    candidates_checked: usize,
+    rejections: Vec<CandidateRejection>,
+}
+
+/// A directory below the repo root that learning exports may be written to.
+#[derive(Debug)]
+struct ExportRoot {
+    /// `repo_root.join(<configured root>)`, the form candidate paths are compared against.
+    path: PathBuf,
+    /// The same directory with symlinks resolved.
+    canonical: PathBuf,
+}
+
+/// Resolves the configured `export_roots`. Missing directories are skipped, roots nested inside
+/// another root are dropped, and a root that resolves outside the repo is a configuration error.
+fn resolve_export_roots(repo_root: &Path, configured: &[String]) -> Result<Vec<ExportRoot>> {
+    let canonical_repo_root = repo_root
+        .canonicalize()
+        .with_context(|| format!("failed to resolve repo root {}", repo_root.display()))?;
+
+    let mut roots: Vec<ExportRoot> = Vec::new();
+    for entry in configured {
+        let relative = entry.trim().replace('\\', "/");
+        let path = repo_root.join(relative.trim_matches('/'));
+        if !path.is_dir() {
+            continue;
+        }
+        let canonical = path
+            .canonicalize()
+            .with_context(|| format!("failed to resolve export root {}", path.display()))?;
+        if !canonical.starts_with(&canonical_repo_root) {
+            bail!(
+                "export root {:?} resolves to {} outside the repo",
+                entry,
+                canonical.display()
+            );
+        }
+        roots.push(ExportRoot { path, canonical });
+    }
+
+    roots.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut confined: Vec<ExportRoot> = Vec::new();
+    for root in roots {
+        if !confined
+            .iter()
+            .any(|kept| root.path.starts_with(&kept.path))
+        {
+            confined.push(root);
+        }
+    }
+    Ok(confined)
+}
+
+fn discover_learning_folders(
+    repo_root: &Path,
+    databases_root: &Path,
+    matcher: &LearningFolderMatcher,
+) -> Result<Vec<PathBuf>> {
+    if !databases_root.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut result = Vec::new();
+    let mut stack = vec![databases_root.to_path_buf()];
+
+    while let Some(current) = stack.pop() {
+        let entries = fs::read_dir(&current)
+            .with_context(|| format!("failed to read directory {}", current.display()))?;
+
+        for entry in entries {
+            let entry = entry.with_context(|| {
+                format!("failed to read directory entry under {}", current.display())
+            })?;
+            let file_type = entry.file_type().with_context(|| {
+                format!("failed to read file type of {}", entry.path().display())
+            })?;
+            // Symlinked directories could lead out of the export root.
+            if !file_type.is_dir() {
+                continue;
+            }
+            let path = entry.path();
+
+            if matcher.is_learning_folder(repo_root, &path) {
+                result.push(path);
+                continue;
+            }
+
+            stack.push(path);
+        }
+    }
+
+    result.sort();
+    Ok(result)
+}
+
+fn cleanup_previous_learning_exports(
+    learning_folders: &[PathBuf],
+    export_subdir: &str,
+) -> Result<()> {
+    for folder in learning_folders {
+        let export_dir = folder.join(export_subdir);
+        if export_dir.exists() {
+            fs::remove_dir_all(&export_dir).with_context(|| {
+                format!(
+                    "failed to clean previous learning export directory {}",
+                    export_dir.display()
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Context fields holding repo paths, most specific first.
+const PATH_CONTEXT_FIELDS: &[&str] = &["folderPath", "gamePath", "jsonPath"];
+/// Context fields holding page or asset URLs sent by the web clients (`buildFeedbackContext`).
+const URL_CONTEXT_FIELDS: &[&str] = &[
+    "gameUrl",
+    "jsonUrl",
+    "frameUrl",
+    "locationHref",
+    "locationPath",
+];
+/// Query parameters of `generic_page.html` that carry repo paths or asset URLs.
+const URL_QUERY_PATH_KEYS: &[&str] = &["gameRel", "jsonRel", "game", "json", "config"];
+/// Context fields identifying the game rather than its location.
+const IDENTITY_CONTEXT_FIELDS: &[&str] = &["gameId", "nodeId"];
+/// Nested URLs (`?json=https://...?game=...`) are followed at most this deep.
+const MAX_URL_NESTING: usize = 3;
+
+/// Maps feedback documents onto the learning folders found during discovery.
+struct LearningFolderRouter<'a> {
+    repo_root: &'a Path,
+    export_roots: &'a [ExportRoot],
+    learning_folders: &'a [PathBuf],
+    matcher: &'a LearningFolderMatcher,
+    site_path_prefix: &'a str,
+    game_index: &'a GameIndex,
+}
+
+impl LearningFolderRouter<'_> {
+    fn resolve(&self, feedback: &FeedbackDocument) -> LearningFolderResolution {
+        let mut candidates: Vec<(String, String)> = Vec::new();
+
+        for (prefix, context) in feedback.contexts() {
+            let Some(context) = context else {
+                continue;
+            };
+            for key in PATH_CONTEXT_FIELDS {
+                self.push_candidates(context, prefix, key, false, &mut candidates);
+            }
+        }
+        for (prefix, context) in feedback.contexts() {
+            let Some(context) = context else {
+                continue;
+            };
+            for key in URL_CONTEXT_FIELDS {
+                self.push_candidates(context, prefix, key, true, &mut candidates);
+            }
+        }
+
+        let mut identities: Vec<(String, String)> = Vec::new();
+        for (prefix, context) in feedback.contexts() {
+            let Some(context) = context else {
+                continue;
+            };
+            for key in IDENTITY_CONTEXT_FIELDS {
+                if let Some(value) = context.field(key).map(str::trim) {
+                    if !value.is_empty() {
+                        identities.push((format!("{}{}", prefix, key), value.to_string()));
+                    }
+                }
+            }
+        }
+
+        let candidates_checked = candidates.len() + identities.len();
+        let mut rejections: Vec<CandidateRejection> = Vec::new();
+        for (field, candidate) in candidates {
+            let (absolute, root) = match self.confine_candidate(&candidate) {
+                Ok(confined) => confined,
+                Err(reason) => {
+                    rejections.push(CandidateRejection {
+                        field,
+                        candidate,
+                        reason,
+                    });
+                    continue;
+                }
+            };
+            if let Some(path) = self.resolve_candidate(absolute, root) {
+                return LearningFolderResolution {
+                    resolved: Some(ResolvedLearningFolder {
+                        learning_folder: path,
+                        field,
+                        candidate,
+                    }),
+                    candidates_checked,
+                    rejections,
+                };
+            }
+        }
+
+        // Paths missing or stale: fall back to the game identity.
+        for (field, identity) in identities {
+            let Some(game_folder) = self.game_index.lookup(&identity) else {
+                continue;
+            };
+            if let Some(path) =
+                find_learning_folder_of(&game_folder, self.learning_folders, self.matcher)
+            {
+                return LearningFolderResolution {
+                    resolved: Some(ResolvedLearningFolder {
+                        learning_folder: path,
+                        field,
+                        candidate: identity,
+                    }),
+                    candidates_checked,
+                    rejections,
+                };
+            }
+        }
+
+        LearningFolderResolution {
+            resolved: None,
+            candidates_checked,
+            rejections,
+        }
+    }
+
+    fn push_candidates(
+        &self,
+        context: &FeedbackContext,
+        prefix: &str,
+        key: &str,
+        is_url_field: bool,
+        out: &mut Vec<(String, String)>,
+    ) {
+        let Some(value) = context.field(key) else {
+            return;
+        };
+        let field = format!("{}{}", prefix, key);
+        for path in candidate_repo_paths(value, is_url_field, self.site_path_prefix, 0) {
+            if !out.iter().any(|(_, existing)| existing == &path) {
+                out.push((field.clone(), path));
+            }
+        }
+    }
+
+    /// Checks that a candidate stays inside an export root, lexically and after resolving symlinks.
+    fn confine_candidate(
+        &self,
+        normalized: &str,
+    ) -> std::result::Result<(PathBuf, &ExportRoot), &'static str> {
+        let has_drive_prefix = normalized.as_bytes().get(1) == Some(&b':');
+        if Path::new(normalized).is_absolute() || normalized.starts_with('/') || has_drive_prefix {
+            return Err("absolute_path");
+        }
+        if normalized.split('/').any(|segment| segment == "..") {
+            return Err("parent_traversal");
+        }
+
+        let absolute = self.repo_root.join(normalized);
+        let Some(root) = self
+            .export_roots
+            .iter()
+            .find(|r| absolute.starts_with(&r.path))
+        else {
+            return Err("outside_export_roots");
+        };
+
+        // The candidate itself may not exist (stale path, file name); its nearest existing
+        // ancestor must still resolve into the root.
+        let mut existing = absolute.as_path();
+        while fs::symlink_metadata(existing).is_err() {
+            existing = existing.parent().ok_or("unreadable_path")?;
+        }
+        let canonical = existing.canonicalize().map_err(|_| "symlink_escape")?;
+        if !canonical.starts_with(&root.canonical) {
+            return Err("symlink_escape");
+        }
+
+        Ok((absolute, root))
+    }
+
+    /// Walks up from a confined candidate, never above its export root.
+    fn resolve_candidate(&self, mut absolute: PathBuf, root: &ExportRoot) -> Option<PathBuf> {
+        if (!absolute.exists() && absolute.extension().is_some()) || absolute.is_file() {
+            absolute = absolute.parent()?.to_path_buf();
+        }
+
+        let mut cursor = Some(absolute.as_path());
+        while let Some(current) = cursor {
+            if !current.starts_with(&root.path) {
+                break;
+            }
+            if self.learning_folders.iter().any(|f| f == current) {
+                return Some(current.to_path_buf());
+            }
+            if let Some(found) =
+                find_learning_folder_of(current, self.learning_folders, self.matcher)
+            {
+                return Some(found);
+            }
+            cursor = current.parent();
+        }
+
+        None
+    }
+
+    fn is_confined(&self, path: &Path) -> bool {
+        path.canonicalize()
+            .map(|canonical| {
+                self.export_roots
+                    .iter()
+                    .any(|r| canonical.starts_with(&r.canonical))
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Identity lookup from the `gameId`/`nodeId` values the web clients send to game folders.
+#[derive(Debug, Default)]
+pub struct GameIndex {
+    /// `gameId` slugs of game, JSON and folder paths plus folder-tree node ids.
+    exact: HashMap<String, BTreeSet<PathBuf>>,
+    /// Slugs of game file names; a `gameId` ending in one of them still matches after a move.
+    file_slugs: HashMap<String, BTreeSet<PathBuf>>,
+    pub game_folders: usize,
+}
+
+impl GameIndex {
+    fn insert(map: &mut HashMap<String, BTreeSet<PathBuf>>, key: String, folder: &Path) {
+        if !key.is_empty() {
+            map.entry(key).or_default().insert(folder.to_path_buf());
+        }
+    }
+
+    /// Exact keys first, then a unique game file name at the end of the `gameId`.
+    /// Keys shared by several folders never resolve.
+    pub fn lookup(&self, identity: &str) -> Option<PathBuf> {
+        let trimmed = identity.trim();
+        for key in [trimmed.to_string(), slugify_game_id(trimmed)] {
+            if let Some(folders) = self.exact.get(&key) {
+                return single_folder(folders);
+            }
+        }
+
+        let slug = slugify_game_id(trimmed);
+        let mut matches: BTreeSet<&PathBuf> = BTreeSet::new();
+        for (file_slug, folders) in &self.file_slugs {
+            if slug == *file_slug || slug.ends_with(&format!("_{}", file_slug)) {
+                matches.extend(folders.iter());
+            }
+        }
+        if matches.len() == 1 {
+            matches.into_iter().next().cloned()
+        } else {
+            None
+        }
+    }
+}
+
+fn single_folder(folders: &BTreeSet<PathBuf>) -> Option<PathBuf> {
+    if folders.len() == 1 {
+        folders.iter().next().cloned()
+    } else {
+        None
+    }
+}
+
+/// Walks the export roots and indexes every game folder, i.e. a folder with a `_ghtml*`/`_gjs*`
+/// file or a `_data/_gg01_*.json` config. Symlinked directories are not followed.
+pub fn build_game_index(repo_root: &Path, export_roots: &[PathBuf]) -> Result<GameIndex> {
+    let mut index = GameIndex::default();
+    let mut stack: Vec<PathBuf> = export_roots
+        .iter()
+        .filter(|r| r.is_dir())
+        .cloned()
+        .collect();
+    while let Some(current) = stack.pop() {
+        let entries = fs::read_dir(&current)
+            .with_context(|| format!("failed to read directory {}", current.display()))?;
+
+        let mut game_files: Vec<PathBuf> = Vec::new();
+        let mut is_game_folder = false;
+        for entry in entries {
+            let entry = entry.with_context(|| {
+                format!("failed to read directory entry under {}", current.display())
+            })?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || name == "node_modules" {
+                continue;
+            }
+            let file_type = entry
+                .file_type()
+                .with_context(|| format!("failed to read file type of {}", path.display()))?;
+            if file_type.is_symlink() {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if name == "_data" {
+                    let configs = game_config_files(&path)?;
+                    is_game_folder |= configs.iter().any(|p| {
+                        p.file_name()
+                            .is_some_and(|n| n.to_string_lossy().starts_with("_gg01_"))
+                    });
+                    game_files.extend(configs);
+                }
+                stack.push(path);
+            } else if name.starts_with("_ghtml") || name.starts_with("_gjs") {
+                is_game_folder = true;
+                if name.starts_with("_ghtml") {
+                    game_files.push(path);
+                }
+            }
+        }
+
+        if !is_game_folder {
+            continue;
+        }
+
+        index.game_folders += 1;
+        let folder_rel = path_to_repo_relative(repo_root, &current);
+        GameIndex::insert(
+            &mut index.exact,
+            slugify_game_id(strip_last_extension(&folder_rel)),
+            &current,
+        );
+        let node_id = folder_node_id(&folder_rel);
+        GameIndex::insert(&mut index.exact, slugify_game_id(&node_id), &current);
+        GameIndex::insert(&mut index.exact, node_id, &current);
+
+        for file in game_files {
+            let file_rel = path_to_repo_relative(repo_root, &file);
+            GameIndex::insert(
+                &mut index.exact,
+                slugify_game_id(strip_last_extension(&file_rel)),
+                &current,
+            );
+            if let Some(stem) = file.file_stem() {
+                GameIndex::insert(
+                    &mut index.file_slugs,
+                    slugify_game_id(&stem.to_string_lossy()),
+                    &current,
+                );
+            }
+        }
+    }
+
+    Ok(index)
+}
+
+fn game_config_files(data_dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(data_dir)
+        .with_context(|| format!("failed to read directory {}", data_dir.display()))?;
+    let mut configs = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| {
+            format!(
+                "failed to read directory entry under {}",
+                data_dir.display()
+            )
+        })?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("_gg") && name.ends_with(".json") {
+            configs.push(entry.path());
+        }
+    }
+    Ok(configs)
+}
+
+/// Mirrors `slugify` in `generic_pages/generic_page.js` (the `gameId` of a feedback).
+fn slugify_game_id(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut pending_separator = false;
+    for ch in value.to_lowercase().chars() {
+        if ch.is_ascii_lowercase() || ch.is_ascii_digit() {
+            if pending_separator && !out.is_empty() {
+                out.push('_');
+            }
+            pending_separator = false;
+            out.push(ch);
+        } else {
+            pending_separator = true;
+        }
+    }
+    out
+}
+
+/// Mirrors `idSeed.replace(/\.[^.]+$/, '')` from `buildFeedbackContext`.
+fn strip_last_extension(value: &str) -> &str {
+    match value.rfind('.') {
+        Some(index) if index + 1 < value.len() => &value[..index],
+        _ => value,
+    }
+}
+
+/// Mirrors the folder ids written by `rebuild_root_index.mjs` (`folder_` + `toId(parts)`).
+fn folder_node_id(repo_relative: &str) -> String {
+    let joined = repo_relative.split('/').collect::<Vec<&str>>().join("__");
+    let folded: String = joined
+        .nfkd()
+        .filter(|c| !('\u{0300}'..='\u{036f}').contains(c))
+        .collect();
+
+    let mut out = String::with_capacity(folded.len());
+    for ch in folded.chars() {
+        let keep = ch.is_ascii_alphanumeric() || ch == '-';
+        if keep {
+            out.push(ch);
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    format!("folder_{}", out.trim_matches('_').to_lowercase())
+}
+
+/// Turns one context value into repo-relative candidate paths.
+///
+/// URLs (absolute, or relative with a query string) lose their origin, are percent-decoded and
+/// have the GitHub Pages project prefix removed. Path-like query parameters (`?json=`, `?game=`,
+/// ...) are expanded first because they point at the game itself, the page path comes last.
+/// Values of URL fields that start with `/` are URL paths, not absolute file system paths.
+/// `depth` is the URL nesting level, `0` for a context value.
+pub fn candidate_repo_paths(
+    value: &str,
+    is_url_field: bool,
+    site_path_prefix: &str,
+    depth: usize,
+) -> Vec<String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() || depth > MAX_URL_NESTING {
+        return Vec::new();
+    }
+
+    let mut out = Vec::new();
+    if let Some(url) = parse_candidate_url(trimmed) {
+        for key in URL_QUERY_PATH_KEYS {
+            for (query_key, query_value) in url.query_pairs() {
+                if query_key == *key {
+                    out.extend(candidate_repo_paths(
+                        &query_value,
+                        true,
+                        site_path_prefix,
+                        depth + 1,
+                    ));
+                }
+            }
+        }
+        let path = percent_decode_str(url.path())
+            .decode_utf8_lossy()
+            .to_string();
+        out.extend(normalize_url_path(&path, site_path_prefix));
+    } else {
+        let decoded = percent_decode_str(trimmed).decode_utf8_lossy().to_string();
+        if is_url_field {
+            out.extend(normalize_url_path(&decoded, site_path_prefix));
+        } else {
+            out.extend(normalize_repo_candidate_path(&decoded));
+        }
+    }
+    out
+}
+
+fn parse_candidate_url(value: &str) -> Option<Url> {
+    if value.contains("://") {
+        return Url::parse(value).ok();
+    }
+    if value.contains('?') {
+        return Url::parse("http://repo.invalid/").ok()?.join(value).ok();
+    }
+    None
+}
+
+/// Strips leading `/`, `./` and `../` segments and the site prefix from a URL path.
+pub fn normalize_url_path(path: &str, site_path_prefix: &str) -> Option<String> {
+    let normalized = path.trim().replace('\\', "/");
+    let mut segments: Vec<&str> = normalized
+        .split('/')
+        .skip_while(|segment| segment.is_empty() || *segment == "." || *segment == "..")
+        .collect();
+
+    let prefix = site_path_prefix.trim_matches('/');
+    if !prefix.is_empty() && segments.len() > 1 && segments[0].eq_ignore_ascii_case(prefix) {
+        segments.remove(0);
+    }
+
+    let joined = segments.join("/");
+    let joined = joined.trim_end_matches('/');
+    if joined.is_empty() {
+        None
+    } else {
+        Some(joined.to_string())
+    }
+}
+
+/// Drops `.` and empty segments. Absolute paths and `..` segments are kept so that
+/// `confine_candidate` can reject them.
+fn normalize_repo_candidate_path(candidate: &str) -> Option<String> {
+    let trimmed = candidate.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    let normalized = trimmed.replace('\\', "/");
+    let leading_slash = if normalized.starts_with('/') { "/" } else { "" };
+    let relative = normalized
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<&str>>()
+        .join("/");
+    if relative.is_empty() {
+        None
+    } else {
+        Some(format!("{}{}", leading_slash, relative))
+    }
+}
+
+/// Returns the learning folder that belongs to `dir` as a game folder (first one when sorted).
+fn find_learning_folder_of(
+    dir: &Path,
+    learning_folders: &[PathBuf],
+    matcher: &LearningFolderMatcher,
+) -> Option<PathBuf> {
+    learning_folders
+        .iter()
+        .find(|folder| matcher.is_learning_folder_of(dir, folder))
+        .cloned()
+}
+
+pub(crate) fn path_to_repo_relative(repo_root: &Path, absolute: &Path) -> String {
+    absolute
+        .strip_prefix(repo_root)
+        .map(|p| p.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_else(|_| absolute.to_string_lossy().replace('\\', "/"))
+}
+
+fn sanitize_file_component(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for ch in input.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+            out.push(ch);
+        } else {
+            out.push('_');
+        }
+    }
+
+    let collapsed = out.trim_matches('_').to_string();
+    if collapsed.is_empty() {
+        "unknown".to_string()
+    } else {
+        collapsed
+    }
+}
@@ -0,0 +1,224 @@
+//! Field policy for the user-controlled strings of a feedback document: which sanitizer profile each
+//! known field gets, and what happens to strings the schema does not know.
+
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Url;
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::sanitize::{CONTROL_CHAR_RE, ZERO_WIDTH_RE};
+
+pub const INVALID_URL_TOKEN: &str = "[invalid-url]";
+pub const INVALID_PATH_TOKEN: &str = "[invalid-path]";
+pub const INVALID_IDENTIFIER_TOKEN: &str = "[invalid-identifier]";
+pub const WITHHELD_FIELD_TOKEN: &str = "[withheld-unknown-field]";
+
+const MAX_URL_CHARS: usize = 2048;
+const MAX_PATH_CHARS: usize = 512;
+
+/// `@` for sanitizer versions (`<name>@<version>+sha256:<hash>`) written back by `write_back`.
+static IDENTIFIER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[A-Za-z0-9][A-Za-z0-9._:+@-]{0,199}$").expect("valid regex"));
+/// Characters no repo path of the games contains; `:` also rules out drive letters and schemes.
+static PATH_FORBIDDEN_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"[<>"|?*:\n\r\t]"#).expect("valid regex"));
+static REPEATED_SLASH_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"/{2,}").expect("valid regex"));
+
+/// How a string field is sanitized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldProfile {
+    /// The full comment sanitizer: rules, markup, personal data, quarantine.
+    FreeText,
+    /// An absolute http(s) URL or a relative reference (`/generic_pages/...`, `../databases/...`).
+    Url,
+    /// A repo-relative path or a single folder name.
+    Path,
+    /// A slug, enum value or timestamp: `[A-Za-z0-9._:+-]`, at most 200 characters.
+    Identifier,
+}
+
+impl FieldProfile {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FieldProfile::FreeText => "free_text",
+            FieldProfile::Url => "url",
+            FieldProfile::Path => "path",
+            FieldProfile::Identifier => "identifier",
+        }
+    }
+}
+
+/// Schema of known fields. Paths are relative to the document data, with `.` between keys and
+/// without array indices (`context.folderPath`). Fields with a comment-like key anywhere in the
+/// document are always free text.
+#[derive(Debug, Clone, Default)]
+pub struct FieldPolicy {
+    fields: BTreeMap<String, FieldProfile>,
+}
+
+/// Fields the web clients send (`generic_page.js`, `firebase-feedback-client.js`) and the markers
+/// `write_back` adds. Path, URL and identity fields are also accepted at the top level, where older
+/// clients wrote them.
+const BUILTIN_FIELDS: &[(&str, FieldProfile)] = &[
+    ("comment", FieldProfile::FreeText),
+    ("source", FieldProfile::Identifier),
+    ("createdAt", FieldProfile::Identifier),
+    ("createdAtIso", FieldProfile::Identifier),
+    ("context.gameTitle", FieldProfile::FreeText),
+    ("context.feedbackOrigin", FieldProfile::Identifier),
+    ("context.folder", FieldProfile::Path),
+    ("gameTitle", FieldProfile::FreeText),
+    ("folder", FieldProfile::Path),
+    ("processedAt", FieldProfile::Identifier),
+    ("processedBy", FieldProfile::Identifier),
+    ("sanitizerVersion", FieldProfile::Identifier),
+    ("exportPath", FieldProfile::Path),
+];
+const CONTEXT_IDENTIFIER_FIELDS: &[&str] = &["gameId", "nodeId"];
+const CONTEXT_PATH_FIELDS: &[&str] = &["folderPath", "gamePath", "jsonPath"];
+const CONTEXT_URL_FIELDS: &[&str] = &[
+    "gameUrl",
+    "jsonUrl",
+    "frameUrl",
+    "locationHref",
+    "locationPath",
+];
+
+static BUILTIN_POLICY: Lazy<FieldPolicy> = Lazy::new(|| {
+    let mut policy = FieldPolicy::default();
+    for (path, profile) in BUILTIN_FIELDS {
+        policy = policy.with_field(path, *profile);
+    }
+    for (keys, profile) in [
+        (CONTEXT_IDENTIFIER_FIELDS, FieldProfile::Identifier),
+        (CONTEXT_PATH_FIELDS, FieldProfile::Path),
+        (CONTEXT_URL_FIELDS, FieldProfile::Url),
+    ] {
+        for key in keys {
+            policy = policy
+                .with_field(&format!("context.{}", key), profile)
+                .with_field(key, profile);
+        }
+    }
+    policy
+});
+
+impl FieldPolicy {
+    /// The schema of the feedback documents the web clients write.
+    pub fn builtin() -> &'static FieldPolicy {
+        &BUILTIN_POLICY
+    }
+
+    pub fn with_field(mut self, path: &str, profile: FieldProfile) -> Self {
+        self.fields.insert(path.to_string(), profile);
+        self
+    }
+
+    /// `None` for fields outside the schema.
+    pub fn profile_of(&self, path: &str) -> Option<FieldProfile> {
+        self.fields.get(path).copied()
+    }
+}
+
+/// A string field outside free text that was changed, rejected or withheld.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldReport {
+    pub field_path: String,
+    /// `unknown` for fields outside the schema.
+    pub profile: &'static str,
+    /// `normalized`, `invalid_url`, `invalid_path`, `invalid_identifier` or `unknown_field`.
+    pub reason: &'static str,
+    pub original_length: usize,
+}
+
+/// The cleaned value and, if it differs, why.
+#[derive(Debug)]
+pub struct FieldCleanup {
+    pub value: String,
+    pub reason: Option<&'static str>,
+}
+
+/// Cleans `text` according to a structured profile; free text belongs to the comment sanitizer.
+pub fn clean_field(profile: FieldProfile, text: &str) -> FieldCleanup {
+    let scrubbed = scrub(text);
+    let (cleaned, invalid) = match profile {
+        FieldProfile::Url => (clean_url(&scrubbed), ("invalid_url", INVALID_URL_TOKEN)),
+        FieldProfile::Path => (clean_path(&scrubbed), ("invalid_path", INVALID_PATH_TOKEN)),
+        FieldProfile::Identifier => (
+            IDENTIFIER_RE.is_match(&scrubbed).then(|| scrubbed.clone()),
+            ("invalid_identifier", INVALID_IDENTIFIER_TOKEN),
+        ),
+        // Only the character cleanup; the rules need the comment sanitizer.
+        FieldProfile::FreeText => (Some(scrubbed), ("", "")),
+    };
+    match cleaned {
+        Some(value) if value == text => FieldCleanup {
+            value,
+            reason: None,
+        },
+        Some(value) => FieldCleanup {
+            value,
+            reason: Some("normalized"),
+        },
+        None => FieldCleanup {
+            value: invalid.1.to_string(),
+            reason: Some(invalid.0),
+        },
+    }
+}
+
+/// NFKC, no control or zero-width characters, trimmed.
+fn scrub(text: &str) -> String {
+    let normalized: String = text.nfkc().collect();
+    let without_control = CONTROL_CHAR_RE.replace_all(&normalized, "");
+    ZERO_WIDTH_RE
+        .replace_all(&without_control, "")
+        .trim()
+        .to_string()
+}
+
+/// Without credentials and fragment, otherwise as written; `None` for other schemes or oversized URLs.
+fn clean_url(text: &str) -> Option<String> {
+    if text.chars().count() > MAX_URL_CHARS {
+        return None;
+    }
+    let Ok(mut url) = Url::parse(text) else {
+        // Relative references (`/generic_pages/...`, `../databases/...`) stay as written if they
+        // resolve; protocol-relative `//host` ones do not count.
+        let base = Url::parse("http://localhost/").expect("valid base URL");
+        let valid = !text.starts_with("//")
+            && !text.contains(|c: char| c.is_whitespace() || c == '\\')
+            && base.join(text).is_ok();
+        return valid.then(|| text.to_string());
+    };
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return None;
+    }
+    if url.username().is_empty() && url.password().is_none() && url.fragment().is_none() {
+        return Some(text.to_string());
+    }
+    url.set_username("").ok()?;
+    url.set_password(None).ok()?;
+    url.set_fragment(None);
+    Some(url.to_string())
+}
+
+/// Forward slashes, no `.`/`..` segments, relative; `None` if anything else remains.
+fn clean_path(text: &str) -> Option<String> {
+    let slashed = text.replace('\\', "/");
+    let collapsed = REPEATED_SLASH_RE.replace_all(&slashed, "/");
+    let cleaned = collapsed.trim_end_matches('/');
+    let valid = !cleaned.is_empty()
+        && cleaned.chars().count() <= MAX_PATH_CHARS
+        && !cleaned.starts_with('/')
+        && !PATH_FORBIDDEN_RE.is_match(cleaned)
+        && cleaned
+            .split('/')
+            .all(|segment| !matches!(segment.trim(), "" | "." | ".."));
+    valid.then(|| cleaned.to_string())
+}
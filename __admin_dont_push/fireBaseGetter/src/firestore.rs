@@ -0,0 +1,413 @@
+//! Firestore REST access: the [`FeedbackSource`] stage and its default [`FirestoreSource`], and
+//! the [`DocumentWriter`] used by the opt-in write steps.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use once_cell::unsync::OnceCell;
+use reqwest::blocking::Client;
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use crate::auth::{fetch_access_token, read_service_account};
+use crate::config::{RunOptions, Settings, DEFAULT_FIRESTORE_BASE_URL};
+
+const EMULATOR_ACCESS_TOKEN: &str = "owner";
+/// Firestore accepts at most this many writes per commit.
+pub const MAX_WRITES_PER_COMMIT: usize = 500;
+
+/// Raw Firestore REST documents (`name`, `fields`, `createTime`, ...) of one project.
+#[derive(Debug, Clone, Default)]
+pub struct RawDocuments {
+    pub project_id: String,
+    pub documents: Vec<Value>,
+}
+
+/// First pipeline stage: delivers the raw feedback documents.
+pub trait FeedbackSource {
+    /// Returns all documents, or with `since` (RFC 3339) only those whose watermark field is at or
+    /// after it. Sources without a query may ignore `since`; the pipeline merges by document id.
+    fn fetch(&self, since: Option<&str>) -> Result<RawDocuments>;
+}
+
+/// Fixed documents, e.g. for tests or tools that load the documents themselves.
+impl FeedbackSource for RawDocuments {
+    fn fetch(&self, _since: Option<&str>) -> Result<RawDocuments> {
+        Ok(self.clone())
+    }
+}
+
+/// Reads the configured collection from Firestore, the emulator or a mock server.
+#[derive(Debug, Clone)]
+pub struct FirestoreSource {
+    settings: Settings,
+    service_account_path: PathBuf,
+}
+
+impl FirestoreSource {
+    pub fn new(settings: Settings, service_account_path: PathBuf) -> Self {
+        FirestoreSource {
+            settings,
+            service_account_path,
+        }
+    }
+
+    pub fn from_options(options: &RunOptions) -> Self {
+        FirestoreSource::new(
+            options.settings.clone(),
+            options.service_account_path.clone(),
+        )
+    }
+}
+
+impl FeedbackSource for FirestoreSource {
+    fn fetch(&self, since: Option<&str>) -> Result<RawDocuments> {
+        let session = FirestoreSession::open(&self.settings, &self.service_account_path)?;
+        let documents = match since {
+            Some(watermark) => query_feedback_since(&session, &self.settings, watermark)?,
+            None => download_feedback_collection(&session, &self.settings)?,
+        };
+
+        Ok(RawDocuments {
+            project_id: session.project_id,
+            documents,
+        })
+    }
+}
+
+/// Applies Firestore `Write`s (REST JSON: `update`, `delete`, `updateMask`, ...) in one atomic commit.
+pub trait DocumentWriter {
+    /// Project the writes belong to; document names must lie below its default database.
+    fn project_id(&self) -> Result<String>;
+
+    /// At most [`MAX_WRITES_PER_COMMIT`] writes.
+    fn commit(&self, writes: &[Value]) -> Result<()>;
+
+    /// Reads the named documents back after a commit; missing ones are left out. Writers that
+    /// cannot read refuse, so nothing that depends on the check goes ahead.
+    fn get_documents(&self, _names: &[String]) -> Result<Vec<Value>> {
+        bail!("this writer cannot read documents back")
+    }
+}
+
+/// Commits `writes` in chunks of [`MAX_WRITES_PER_COMMIT`] and returns the number of commits.
+/// Every document name is checked against the writer's project before the first commit.
+pub fn commit_in_batches(writer: &dyn DocumentWriter, writes: &[Value]) -> Result<usize> {
+    if writes.is_empty() {
+        return Ok(0);
+    }
+    let prefix = format!(
+        "projects/{}/databases/(default)/documents/",
+        writer.project_id()?
+    );
+    for write in writes {
+        let name = write_document_name(write);
+        if !name.is_some_and(|name| name.starts_with(&prefix) && name.len() > prefix.len()) {
+            bail!(
+                "write for {:?} does not target a document of {}; refusing to commit",
+                name.unwrap_or_default(),
+                prefix.trim_end_matches('/')
+            );
+        }
+    }
+
+    let mut commits = 0usize;
+    for chunk in writes.chunks(MAX_WRITES_PER_COMMIT) {
+        writer.commit(chunk).with_context(|| {
+            format!(
+                "commit {} failed after {} of {} writes",
+                commits + 1,
+                commits * MAX_WRITES_PER_COMMIT,
+                writes.len()
+            )
+        })?;
+        commits += 1;
+    }
+    Ok(commits)
+}
+
+/// The document a REST `Write` updates or deletes.
+pub fn write_document_name(write: &Value) -> Option<&str> {
+    write
+        .get("update")
+        .and_then(|update| update.get("name"))
+        .or_else(|| write.get("delete"))
+        .and_then(Value::as_str)
+}
+
+/// Commits to the configured Firestore, emulator or mock server. Authenticates on first use.
+#[derive(Debug)]
+pub struct FirestoreWriter {
+    settings: Settings,
+    service_account_path: PathBuf,
+    session: OnceCell<FirestoreSession>,
+}
+
+impl FirestoreWriter {
+    pub fn new(settings: Settings, service_account_path: PathBuf) -> Self {
+        FirestoreWriter {
+            settings,
+            service_account_path,
+            session: OnceCell::new(),
+        }
+    }
+
+    pub fn from_options(options: &RunOptions) -> Self {
+        FirestoreWriter::new(
+            options.settings.clone(),
+            options.service_account_path.clone(),
+        )
+    }
+
+    fn session(&self) -> Result<&FirestoreSession> {
+        self.session
+            .get_or_try_init(|| FirestoreSession::open(&self.settings, &self.service_account_path))
+    }
+}
+
+impl DocumentWriter for FirestoreWriter {
+    fn project_id(&self) -> Result<String> {
+        Ok(self.session()?.project_id.clone())
+    }
+
+    fn commit(&self, writes: &[Value]) -> Result<()> {
+        let session = self.session()?;
+        let endpoint = format!(
+            "{}/projects/{}/databases/(default)/documents:commit",
+            session.target.base_url, session.project_id
+        );
+        session
+            .client
+            .post(&endpoint)
+            .bearer_auth(&session.access_token)
+            .json(&json!({ "writes": writes }))
+            .send()
+            .context("Firestore commit request failed")?
+            .error_for_status()
+            .context("Firestore commit returned non-success status")?;
+        Ok(())
+    }
+
+    fn get_documents(&self, names: &[String]) -> Result<Vec<Value>> {
+        let session = self.session()?;
+        let endpoint = format!(
+            "{}/projects/{}/databases/(default)/documents:batchGet",
+            session.target.base_url, session.project_id
+        );
+        let mut documents = Vec::new();
+        for chunk in names.chunks(MAX_WRITES_PER_COMMIT) {
+            let results = session
+                .client
+                .post(&endpoint)
+                .bearer_auth(&session.access_token)
+                .json(&json!({ "documents": chunk }))
+                .send()
+                .context("Firestore batchGet request failed")?
+                .error_for_status()
+                .context("Firestore batchGet returned non-success status")?
+                .json::<Value>()
+                .context("failed to parse Firestore batchGet response")?;
+            // One item per name: `{"found": document}` or `{"missing": name}`.
+            documents.extend(
+                results
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|item| item.get("found").cloned()),
+            );
+        }
+        Ok(documents)
+    }
+}
+
+/// An HTTP client with the token and project id for one target.
+#[derive(Debug)]
+struct FirestoreSession {
+    client: Client,
+    target: FirestoreTarget,
+    access_token: String,
+    project_id: String,
+}
+
+impl FirestoreSession {
+    fn open(settings: &Settings, service_account_path: &Path) -> Result<Self> {
+        let target = FirestoreTarget::from_settings(settings);
+        let client = Client::builder()
+            .build()
+            .context("failed to create HTTP client")?;
+
+        let (project_id, access_token) = if target.requires_oauth {
+            let service_account = read_service_account(service_account_path).with_context(|| {
+                if target.base_url == DEFAULT_FIRESTORE_BASE_URL {
+                    "Firestore needs service account credentials".to_string()
+                } else {
+                    format!(
+                        "firestore_base_url {} needs service account credentials; use emulator_host for an \
+                         emulator or mock server without OAuth",
+                        target.base_url
+                    )
+                }
+            })?;
+            let access_token = fetch_access_token(&client, &service_account)?;
+            let project_id = settings
+                .project_id
+                .clone()
+                .unwrap_or(service_account.project_id);
+            (project_id, access_token)
+        } else {
+            let project_id = match settings.project_id.clone() {
+                Some(project_id) => project_id,
+                None => read_service_account(service_account_path)
+                    .context("no project id configured for the emulator; set `project_id` or --project-id")?
+                    .project_id,
+            };
+            (project_id, EMULATOR_ACCESS_TOKEN.to_string())
+        };
+
+        Ok(FirestoreSession {
+            client,
+            target,
+            access_token,
+            project_id,
+        })
+    }
+}
+
+/// Where Firestore requests go and whether they need a real OAuth token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirestoreTarget {
+    pub base_url: String,
+    pub requires_oauth: bool,
+}
+
+impl FirestoreTarget {
+    /// The emulator host wins over a configured base URL and is the only target called with the
+    /// emulator's `owner` token. Every base URL, the Google default or not, gets a real OAuth token,
+    /// so a mistyped or proxied production URL never runs without credentials.
+    pub fn from_settings(settings: &Settings) -> Self {
+        if let Some(host) = settings
+            .emulator_host
+            .as_deref()
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+        {
+            let host = host.trim_start_matches("http://").trim_end_matches('/');
+            return FirestoreTarget {
+                base_url: format!("http://{}/v1", host),
+                requires_oauth: false,
+            };
+        }
+
+        FirestoreTarget {
+            base_url: settings
+                .firestore_base_url
+                .trim_end_matches('/')
+                .to_string(),
+            requires_oauth: true,
+        }
+    }
+}
+
+/// Runs a structured query for documents whose watermark field (default `createdAt`, set by the web
+/// clients via `serverTimestamp()`) is at or after `since`. Firestore cannot filter on the
+/// `createTime`/`updateTime` metadata itself; the server timestamp matches `createTime` of the
+/// initial write. Documents edited later without touching that field, or without it at all, are
+/// only seen by a full download, which `--incremental` runs every `full_sync_interval_hours`.
+fn query_feedback_since(
+    session: &FirestoreSession,
+    settings: &Settings,
+    since: &str,
+) -> Result<Vec<Value>> {
+    let endpoint = format!(
+        "{}/projects/{}/databases/(default)/documents:runQuery",
+        session.target.base_url, session.project_id
+    );
+    let body = json!({
+        "structuredQuery": {
+            "from": [{ "collectionId": settings.collection }],
+            "where": {
+                "fieldFilter": {
+                    "field": { "fieldPath": settings.watermark_field },
+                    "op": "GREATER_THAN_OR_EQUAL",
+                    "value": { "timestampValue": since }
+                }
+            },
+            "orderBy": [{
+                "field": { "fieldPath": settings.watermark_field },
+                "direction": "ASCENDING"
+            }]
+        }
+    });
+
+    let results = session
+        .client
+        .post(&endpoint)
+        .bearer_auth(&session.access_token)
+        .json(&body)
+        .send()
+        .context("Firestore runQuery request failed")?
+        .error_for_status()
+        .context("Firestore runQuery returned non-success status")?
+        .json::<Value>()
+        .context("failed to parse Firestore runQuery response")?;
+
+    Ok(results
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.get("document").cloned())
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn download_feedback_collection(
+    session: &FirestoreSession,
+    settings: &Settings,
+) -> Result<Vec<Value>> {
+    let mut all_documents: Vec<Value> = Vec::new();
+    let mut page_token: Option<String> = None;
+
+    loop {
+        let endpoint = format!(
+            "{}/projects/{}/databases/(default)/documents/{}",
+            session.target.base_url, session.project_id, settings.collection
+        );
+        let mut url = Url::parse(&endpoint).context("failed to build Firestore URL")?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("pageSize", &settings.page_size.to_string());
+            if let Some(token) = page_token.as_ref() {
+                query.append_pair("pageToken", token);
+            }
+        }
+
+        let page = session
+            .client
+            .get(url)
+            .bearer_auth(&session.access_token)
+            .send()
+            .context("Firestore request failed")?
+            .error_for_status()
+            .context("Firestore returned non-success status")?
+            .json::<Value>()
+            .context("failed to parse Firestore response")?;
+
+        if let Some(documents) = page.get("documents").and_then(Value::as_array) {
+            all_documents.extend(documents.iter().cloned());
+        }
+
+        page_token = page
+            .get("nextPageToken")
+            .and_then(Value::as_str)
+            .map(|token| token.to_string())
+            .filter(|token| !token.is_empty());
+
+        if page_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(all_documents)
+}
@@ -0,0 +1,41 @@
+//! Downloads the feedback collection from Firestore, sanitizes the comments and exports them into
+//! the learning folders of the games.
+//!
+//! The stages sit behind [`FeedbackSource`], [`CommentSanitizer`] and [`FeedbackSink`];
+//! [`Pipeline`] runs them with the defaults ([`FirestoreSource`], [`RuleSanitizer`],
+//! [`LearningFolderSink`]) unless single stages are swapped.
+
+pub mod archive;
+pub mod auth;
+pub mod config;
+pub mod corpus;
+pub mod decode;
+pub mod detection;
+pub mod encoded;
+pub mod export;
+pub mod fields;
+pub mod firestore;
+pub mod lossless;
+pub mod markup;
+pub mod model;
+pub mod offline;
+pub mod pii;
+pub mod pipeline;
+pub mod quarantine;
+pub mod report;
+pub mod rules;
+pub mod sanitize;
+pub mod sync;
+pub mod writeback;
+
+pub use archive::{ArchiveRequest, ArchiveSummary};
+pub use config::{resolve_run_options, Invocation, RunOptions, Settings, SettingsLayer};
+pub use export::{FeedbackSink, LearningExportSummary, LearningFolderResolver, LearningFolderSink};
+pub use firestore::{
+    DocumentWriter, FeedbackSource, FirestoreSource, FirestoreWriter, RawDocuments,
+};
+pub use model::FeedbackDocument;
+pub use pipeline::{IncrementalSummary, Pipeline, QuarantineSummary, RunSummary};
+pub use quarantine::{QuarantineEntry, QuarantineStore, ReviewStatus};
+pub use rules::RulePack;
+pub use sanitize::{CommentSanitizer, RuleMatch, RuleSanitizer, SanitizationOutcome};
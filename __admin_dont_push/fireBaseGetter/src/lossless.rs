@@ -0,0 +1,199 @@
+//! Lossless decoding of Firestore REST values and the matching encoder back to REST JSON.
+//!
+//! Plain JSON covers strings, booleans, null, finite doubles, int64 integers, arrays and maps.
+//! Everything else keeps its Firestore type in a single-key object: `{"$timestamp": "..."}`,
+//! `{"$ref": "..."}`, `{"$bytes": "<base64>"}`, `{"$integer": "<digits>"}` for integers outside
+//! int64, `{"$double": "NaN"}` (also `Infinity`, `-Infinity`), `{"$geo": {...}}`, and
+//! `{"$raw": {...}}` for values this decoder does not know. A map that itself looks like such an
+//! object is wrapped as `{"$map": {...}}`.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Number, Value};
+
+pub const TIMESTAMP_TAG: &str = "$timestamp";
+pub const REFERENCE_TAG: &str = "$ref";
+pub const BYTES_TAG: &str = "$bytes";
+pub const INTEGER_TAG: &str = "$integer";
+pub const DOUBLE_TAG: &str = "$double";
+pub const GEO_POINT_TAG: &str = "$geo";
+pub const MAP_TAG: &str = "$map";
+pub const RAW_TAG: &str = "$raw";
+
+const TAGS: &[&str] = &[
+    TIMESTAMP_TAG,
+    REFERENCE_TAG,
+    BYTES_TAG,
+    INTEGER_TAG,
+    DOUBLE_TAG,
+    GEO_POINT_TAG,
+    MAP_TAG,
+    RAW_TAG,
+];
+const NON_FINITE_DOUBLES: &[&str] = &["NaN", "Infinity", "-Infinity"];
+
+/// The tag of a typed value, `None` for ordinary maps.
+pub fn typed_tag(map: &Map<String, Value>) -> Option<&'static str> {
+    if map.len() != 1 {
+        return None;
+    }
+    let key = map.keys().next()?;
+    TAGS.iter().find(|tag| **tag == key).copied()
+}
+
+/// Decodes the `fields` of a Firestore document.
+pub fn decode_lossless_fields(fields: &Value) -> Value {
+    let Some(object) = fields.as_object() else {
+        return Value::Object(Map::new());
+    };
+    Value::Object(
+        object
+            .iter()
+            .map(|(key, value)| (key.clone(), decode_lossless_value(value)))
+            .collect(),
+    )
+}
+
+pub fn decode_lossless_value(value: &Value) -> Value {
+    let raw = || json!({ RAW_TAG: value });
+    let Some(object) = value.as_object() else {
+        return raw();
+    };
+    if object.len() != 1 {
+        return raw();
+    }
+    let (kind, inner) = object.iter().next().expect("one entry");
+    match (kind.as_str(), inner) {
+        ("nullValue", Value::Null) => Value::Null,
+        ("booleanValue", Value::Bool(v)) => Value::Bool(*v),
+        ("stringValue", Value::String(v)) => Value::String(v.clone()),
+        ("timestampValue", Value::String(v)) => json!({ TIMESTAMP_TAG: v }),
+        ("referenceValue", Value::String(v)) => json!({ REFERENCE_TAG: v }),
+        ("bytesValue", Value::String(v)) => json!({ BYTES_TAG: v }),
+        ("integerValue", Value::String(v)) => match v.parse::<i64>() {
+            Ok(parsed) if parsed.to_string() == *v => Value::Number(Number::from(parsed)),
+            _ => json!({ INTEGER_TAG: v }),
+        },
+        ("doubleValue", Value::Number(v)) => v
+            .as_f64()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .unwrap_or_else(raw),
+        ("doubleValue", Value::String(v)) if NON_FINITE_DOUBLES.contains(&v.as_str()) => {
+            json!({ DOUBLE_TAG: v })
+        }
+        ("geoPointValue", Value::Object(_)) => json!({ GEO_POINT_TAG: inner }),
+        ("arrayValue", Value::Object(array)) => match array.get("values") {
+            None => Value::Array(Vec::new()),
+            Some(Value::Array(values)) if array.len() == 1 => {
+                Value::Array(values.iter().map(decode_lossless_value).collect())
+            }
+            Some(_) => raw(),
+        },
+        ("mapValue", Value::Object(map)) => match map.get("fields") {
+            None => Value::Object(Map::new()),
+            Some(fields @ Value::Object(field_map)) if map.len() == 1 => {
+                let decoded = decode_lossless_fields(fields);
+                if typed_tag(field_map).is_some() {
+                    json!({ MAP_TAG: decoded })
+                } else {
+                    decoded
+                }
+            }
+            Some(_) => raw(),
+        },
+        _ => raw(),
+    }
+}
+
+/// Encodes document data (plain or lossless) as the `fields` of a Firestore document.
+pub fn encode_firestore_fields(data: &Value) -> Result<Value> {
+    let Some(object) = data.as_object() else {
+        bail!("document data must be a JSON object");
+    };
+    encode_map_fields(object)
+}
+
+pub fn encode_firestore_value(value: &Value) -> Result<Value> {
+    Ok(match value {
+        Value::Null => json!({ "nullValue": null }),
+        Value::Bool(v) => json!({ "booleanValue": v }),
+        Value::Number(v) if v.is_f64() => json!({ "doubleValue": v }),
+        Value::Number(v) => match v.as_i64() {
+            Some(int) => json!({ "integerValue": int.to_string() }),
+            None => bail!("integer {} is outside the Firestore int64 range", v),
+        },
+        Value::String(v) => json!({ "stringValue": v }),
+        Value::Array(items) if items.is_empty() => json!({ "arrayValue": {} }),
+        Value::Array(items) => {
+            let values = items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    encode_firestore_value(item).with_context(|| format!("array index {}", index))
+                })
+                .collect::<Result<Vec<Value>>>()?;
+            json!({ "arrayValue": { "values": values } })
+        }
+        Value::Object(map) => match typed_tag(map) {
+            Some(tag) => encode_typed(tag, &map[tag])?,
+            None => encode_map(map)?,
+        },
+    })
+}
+
+fn encode_typed(tag: &'static str, inner: &Value) -> Result<Value> {
+    let text = || {
+        inner
+            .as_str()
+            .with_context(|| format!("`{}` needs a string, got {}", tag, inner))
+    };
+    Ok(match tag {
+        TIMESTAMP_TAG => json!({ "timestampValue": text()? }),
+        REFERENCE_TAG => json!({ "referenceValue": text()? }),
+        BYTES_TAG => json!({ "bytesValue": text()? }),
+        INTEGER_TAG => {
+            let digits = text()?;
+            let unsigned = digits.strip_prefix('-').unwrap_or(digits);
+            if unsigned.is_empty() || !unsigned.bytes().all(|b| b.is_ascii_digit()) {
+                bail!("`{}` needs decimal digits, got {:?}", tag, digits);
+            }
+            json!({ "integerValue": digits })
+        }
+        DOUBLE_TAG => {
+            let special = text()?;
+            if !NON_FINITE_DOUBLES.contains(&special) {
+                bail!(
+                    "`{}` must be one of {:?}, got {:?}",
+                    tag,
+                    NON_FINITE_DOUBLES,
+                    special
+                );
+            }
+            json!({ "doubleValue": special })
+        }
+        GEO_POINT_TAG if inner.is_object() => json!({ "geoPointValue": inner }),
+        MAP_TAG => match inner.as_object() {
+            Some(map) => encode_map(map)?,
+            None => bail!("`{}` needs an object, got {}", tag, inner),
+        },
+        RAW_TAG => inner.clone(),
+        _ => bail!("`{}` needs an object, got {}", tag, inner),
+    })
+}
+
+fn encode_map(map: &Map<String, Value>) -> Result<Value> {
+    if map.is_empty() {
+        return Ok(json!({ "mapValue": {} }));
+    }
+    Ok(json!({ "mapValue": { "fields": encode_map_fields(map)? } }))
+}
+
+fn encode_map_fields(map: &Map<String, Value>) -> Result<Value> {
+    let mut fields = Map::new();
+    for (key, value) in map {
+        let encoded = encode_firestore_value(value)
+            .with_context(|| format!("failed to encode field `{}`", key))?;
+        fields.insert(key.clone(), encoded);
+    }
+    Ok(Value::Object(fields))
+}
@@ -1,1047 +1,574 @@
-use std::collections::{BTreeSet, HashSet};
-use std::fs;
-use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::PathBuf;
 
 use anyhow::{bail, Context, Result};
-use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
-use once_cell::sync::Lazy;
-use regex::Regex;
-use reqwest::blocking::Client;
-use reqwest::Url;
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Map, Number, Value};
-use unicode_normalization::UnicodeNormalization;
-
-const COLLECTION_NAME: &str = "feedback_all_games";
-const SERVICE_ACCOUNT_FILE: &str = "__admin_dont_push/firebase-service-account.local.json";
-const OUTPUT_RELATIVE_PATH: &str = "__admin_dont_push/fireBaseGetter/feedback_all_games.json";
-const PROTOCOL_RELATIVE_PATH: &str = "__admin_dont_push/fireBaseGetter/codex_protocoll_allFeedBack.txt";
-const TOKEN_SCOPE: &str = "https://www.googleapis.com/auth/datastore";
-const TOKEN_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
-const FIRESTORE_PAGE_SIZE: u32 = 1000;
-const LEARNING_EXPORT_SUBDIR: &str = "firebase_feedback_import";
-
-const SANITIZER_VERSION: &str = "hardcoded_prompt_injection_filter_v1";
-const COMMENT_MAX_CHARS: usize = 4000;
-const BLOCK_SCORE_THRESHOLD: u32 = 14;
-const BLOCKED_COMMENT_TOKEN: &str = "[blocked-by-fireBaseGetter-security]";
-const EMPTY_COMMENT_TOKEN: &str = "[empty-after-sanitization]";
-const REDACTION_TOKEN: &str = "[redacted]";
-
-static CONTROL_CHAR_RE: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"[\x00-\x08\x0B\x0C\x0E-\x1F\x7F]").expect("valid regex"));
-static ZERO_WIDTH_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"[\u{200B}-\u{200F}\u{202A}-\u{202E}\u{2060}-\u{2064}\u{FEFF}]")
-        .expect("valid regex")
-});
-static MULTI_SPACE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").expect("valid regex"));
-
-#[derive(Debug)]
-struct DetectionRule {
-    id: &'static str,
-    weight: u32,
-    regex: Regex,
-}
-
-#[derive(Debug)]
-struct RewriteRule {
-    regex: Regex,
-    replacement: &'static str,
-}
-
-static DETECTION_RULES: Lazy<Vec<DetectionRule>> = Lazy::new(|| {
-    vec![
-        rule(
-            "ignore_previous_instructions",
-            5,
-            r"(?is)\b(ignore|disregard|forget)\b.{0,60}\b(previous|prior|above|all)\b.{0,60}\b(instruction|rules?|prompt|message)\b",
-        ),
-        rule(
-            "prompt_exfiltration",
-            6,
-            r"(?is)\b(reveal|show|print|dump|expose|leak)\b.{0,80}\b(system|developer|hidden|internal)\b.{0,40}\b(prompt|instruction|message)\b",
-        ),
-        rule(
-            "role_override",
-            4,
-            r"(?is)\b(you are|act as|pretend to be|simulate|impersonate)\b.{0,80}\b(system|developer|assistant|admin|root)\b",
-        ),
-        rule(
-            "jailbreak_keyword",
-            5,
-            r"(?i)\b(jailbreak|dan mode|do anything now|bypass safety|override safety|ignore safeguards)\b",
-        ),
-        rule(
-            "role_prefix",
-            4,
-            r"(?im)(^|\s)(system|assistant|developer|user)\s*:",
-        ),
-        rule(
-            "xml_prompt_tag",
-            4,
-            r"(?is)<\s*/?\s*(system|assistant|developer|instructions?|prompt)\b[^>]*>",
-        ),
-        rule("code_fence", 3, r"(?s)```.*?```"),
-        rule(
-            "instruction_header",
-            3,
-            r"(?im)^#{1,6}\s*(system|developer|assistant|prompt|instruction)\b",
-        ),
-        rule(
-            "tool_injection",
-            4,
-            r"(?i)\b(function\s*call|tool\s*call|execute_command|shell\s*command|browser\.search|browser\.open)\b",
-        ),
-        rule(
-            "encoded_payload",
-            3,
-            r"(?is)\b(base64|rot13|hex)\b.{0,40}\b(decode|payload|instruction|prompt|command)\b",
-        ),
-        rule(
-            "dangerous_uri_scheme",
-            4,
-            r"(?i)\b(javascript|data|file|vbscript)\s*:",
-        ),
-        rule(
-            "command_payload",
-            4,
-            r"(?i)\b(rm\s+-rf|curl\s+https?://|wget\s+https?://|powershell\s+-|bash\s+-c)\b",
-        ),
-    ]
-});
-
-static REWRITE_RULES: Lazy<Vec<RewriteRule>> = Lazy::new(|| {
-    vec![
-        rewrite(r"(?s)```.*?```", REDACTION_TOKEN),
-        rewrite(r"(?im)(^|\s)(system|assistant|developer|user)\s*:", " $1[role-redacted]:"),
-        rewrite(
-            r"(?is)<\s*/?\s*(system|assistant|developer|instructions?|prompt)\b[^>]*>",
-            REDACTION_TOKEN,
-        ),
-        rewrite(r"(?i)\b(javascript|data|file|vbscript)\s*:", "[scheme-redacted]"),
-        rewrite(
-            r"(?is)\b(ignore|disregard|forget)\b.{0,60}\b(instruction|rules?|prompt|message)\b",
-            REDACTION_TOKEN,
-        ),
-        rewrite(
-            r"(?is)\b(reveal|show|print|dump|expose|leak)\b.{0,80}\b(system|developer|hidden|internal)\b.{0,40}\b(prompt|instruction|message)\b",
-            REDACTION_TOKEN,
-        ),
-    ]
-});
-
-#[derive(Debug, Deserialize)]
-struct ServiceAccount {
-    project_id: String,
-    private_key: String,
-    client_email: String,
-    token_uri: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct TokenResponse {
-    access_token: String,
-}
-
-#[derive(Debug, Serialize)]
-struct JwtClaims {
-    iss: String,
-    scope: String,
-    aud: String,
-    iat: i64,
-    exp: i64,
-}
-
-#[derive(Debug, Serialize)]
-struct CommentSanitizationReport {
-    field_path: String,
-    blocked: bool,
-    changed: bool,
-    score: u32,
-    reasons: Vec<String>,
-    original_length: usize,
-    sanitized_length: usize,
-}
-
-#[derive(Debug)]
-struct SanitizationOutcome {
-    sanitized: String,
-    blocked: bool,
-    changed: bool,
-    score: u32,
-    reasons: Vec<String>,
-    original_length: usize,
-    sanitized_length: usize,
-}
-
-#[derive(Debug)]
-struct BuildOutputResult {
-    payload: Value,
-    mapped_documents: Vec<Value>,
-}
-
-#[derive(Debug)]
-struct LearningExportSummary {
-    checked_documents: usize,
-    exported_feedbacks: usize,
-    filtered_feedbacks: usize,
-    unresolved_folder_feedbacks: usize,
-    written_paths: Vec<PathBuf>,
+use clap::{Args, Parser, Subcommand};
+use firebase_getter::corpus::{evaluate, Corpus};
+use firebase_getter::{
+    resolve_run_options, ArchiveRequest, ArchiveSummary, CommentSanitizer, Invocation, Pipeline, QuarantineStore, ReviewStatus, RuleSanitizer, RunOptions,
+    RunSummary, SettingsLayer,
+};
+use serde_json::json;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "fireBaseGetter",
+    about = "Laedt Feedback aus Firestore, filtert Kommentare und exportiert sie in die Lernordner"
+)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Args)]
+struct CommonArgs {
+    /// Repository root (default: first parent directory containing `.git`).
+    #[arg(long, global = true)]
+    repo_root: Option<PathBuf>,
+
+    /// Config file (default: `__admin_dont_push/fireBaseGetter/fireBaseGetter.toml`).
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Service account JSON (relative paths are resolved against the repo root).
+    #[arg(long, global = true)]
+    service_account: Option<PathBuf>,
+
+    /// Output JSON (relative paths are resolved against the repo root).
+    #[arg(long, global = true)]
+    output: Option<PathBuf>,
+
+    /// Firestore collection to read.
+    #[arg(long, global = true)]
+    collection: Option<String>,
+
+    /// Read documents from a saved raw Firestore dump or output JSON instead of Firestore.
+    #[arg(long, global = true)]
+    input: Option<PathBuf>,
+
+    /// Also save the raw Firestore documents to this file (`fetch` and `all`).
+    #[arg(long, global = true)]
+    save_raw: Option<PathBuf>,
+
+    /// Protocol file (relative paths are resolved against the repo root).
+    #[arg(long, global = true)]
+    protocol: Option<PathBuf>,
+
+    /// Restricted review output with match excerpts (relative paths are resolved against the repo root).
+    #[arg(long, global = true)]
+    review: Option<PathBuf>,
+
+    /// Quarantine of blocked comments (default: `~/.local/share/fireBaseGetter/quarantine.json`).
+    #[arg(long, global = true)]
+    quarantine: Option<PathBuf>,
+
+    /// Firestore list page size.
+    #[arg(long, global = true)]
+    page_size: Option<u32>,
+
+    /// Subdirectory written inside every learning folder.
+    #[arg(long, global = true)]
+    learning_export_subdir: Option<String>,
+
+    /// Learning folder path below a game folder (glob, or `regex:<expr>`).
+    #[arg(long, global = true)]
+    learning_folder_pattern: Option<String>,
+
+    /// First URL path segment of the deployed site (GitHub Pages project name), stripped from URLs.
+    #[arg(long, global = true)]
+    site_path_prefix: Option<String>,
+
+    /// Repo-relative directory learning exports may be written to (repeatable, default `databases`).
+    #[arg(long = "export-root", global = true)]
+    export_roots: Vec<String>,
+
+    /// Comments longer than this are truncated before rule matching.
+    #[arg(long, global = true)]
+    comment_max_chars: Option<usize>,
+
+    /// Score at which a comment is withheld for review.
+    #[arg(long, global = true)]
+    quarantine_score_threshold: Option<u32>,
+
+    /// Score at which a comment is blocked for good.
+    #[arg(long, global = true)]
+    block_score_threshold: Option<u32>,
+
+    /// Firestore REST base URL; always authenticated with the service account (mock servers: --emulator-host).
+    #[arg(long, global = true)]
+    firestore_base_url: Option<String>,
+
+    /// Firestore emulator `host:port` (also read from `FIRESTORE_EMULATOR_HOST`); skips OAuth.
+    #[arg(long, global = true)]
+    emulator_host: Option<String>,
+
+    /// Firebase project id (default: `project_id` from the service account).
+    #[arg(long, global = true)]
+    project_id: Option<String>,
+
+    /// Rule pack TOML for the comment sanitizer (default: built-in `rules/prompt_injection.toml`).
+    #[arg(long, global = true)]
+    rule_pack: Option<PathBuf>,
+
+    /// Only fetch documents newer than the stored watermark and merge them into the output JSON.
+    #[arg(long, global = true)]
+    incremental: bool,
+
+    /// Sync state file holding the watermark (relative paths are resolved against the repo root).
+    #[arg(long, global = true)]
+    state: Option<PathBuf>,
+
+    /// With `--incremental`, download everything again once the last full run is this many hours old.
+    #[arg(long, global = true)]
+    full_sync_interval_hours: Option<u32>,
+
+    /// Keep Firestore types (`$timestamp`, `$ref`, `$bytes`, ...) in the output JSON.
+    #[arg(long, global = true)]
+    lossless_decoding: bool,
+
+    /// Mark exported documents in Firestore as processed (`all`, `export-learnings`).
+    #[arg(long, global = true)]
+    write_back: bool,
+
+    /// Value written as `processedBy` (default: `fireBaseGetter`).
+    #[arg(long, global = true)]
+    processed_by: Option<String>,
+
+    /// Print the planned Firestore writes instead of committing them (`archive`: no file either).
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Local directory for `archive` files (relative paths are resolved against the repo root).
+    #[arg(long, global = true)]
+    archive_dir: Option<PathBuf>,
+
+    /// Collection `archive` copies documents into before deleting them.
+    #[arg(long, global = true)]
+    archive_collection: Option<String>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// Download the collection, sanitize comments and write the output JSON.
+    Fetch,
+    /// Re-run the comment sanitizer on an existing output JSON.
+    Sanitize,
+    /// Export the feedback from an existing output JSON into the learning folders.
+    ExportLearnings,
+    /// Write the protocol file from the `learningExport` section of an existing output JSON.
+    Protocol,
+    /// Run every step in order (default).
+    All,
+    /// Inspect the merged configuration.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Run the rule pack against a labeled corpus and print precision/recall per rule.
+    TestRules {
+        /// Corpus TOML (default: built-in `rules/corpus.toml`).
+        #[arg(long)]
+        corpus: Option<PathBuf>,
+    },
+    /// Review quarantined (blocked) comments; approved ones are exported on the next run.
+    Review {
+        #[command(subcommand)]
+        action: ReviewAction,
+    },
+    /// Move processed or old documents into a local gzip NDJSON archive (and `archive_collection`).
+    Archive {
+        /// Select documents marked by `--write-back` (`processedAt` set).
+        #[arg(long)]
+        processed: bool,
+        /// Select documents created more than this many days ago.
+        #[arg(long)]
+        older_than_days: Option<u32>,
+        /// Delete the archived documents from the collection once the archive is verified.
+        #[arg(long)]
+        confirm_delete: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+enum ConfigAction {
+    /// Print the merged configuration (defaults < config file < environment < CLI).
+    Show,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum ReviewAction {
+    /// List quarantined comments.
+    List {
+        /// `pending`, `approved`, `rejected`, `blocked` or `all`.
+        #[arg(long, default_value = "pending")]
+        status: String,
+    },
+    /// Print one entry with the original comment.
+    Show { id: String },
+    /// Release a comment into the learning export.
+    Approve {
+        id: String,
+        #[command(flatten)]
+        decision: DecisionArgs,
+    },
+    /// Keep a comment out of the learning export.
+    Reject {
+        id: String,
+        #[command(flatten)]
+        decision: DecisionArgs,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
+struct DecisionArgs {
+    /// Reviewer name (default: `USER` / `USERNAME`).
+    #[arg(long)]
+    reviewer: Option<String>,
+    /// Optional note stored with the decision.
+    #[arg(long)]
+    note: Option<String>,
 }
 
 fn main() -> Result<()> {
-    let repo_root = find_repo_root(std::env::current_dir().context("failed to read current directory")?)?;
-    let service_account_path = repo_root.join(SERVICE_ACCOUNT_FILE);
-    let output_path = repo_root.join(OUTPUT_RELATIVE_PATH);
-
-    let service_account = read_service_account(&service_account_path)?;
-    let http_client = Client::builder()
-        .build()
-        .context("failed to create HTTP client")?;
-
-    let access_token = fetch_access_token(&http_client, &service_account)?;
-    let documents = download_feedback_collection(&http_client, &access_token, &service_account.project_id)?;
-
-    let mut build_result = build_output_payload(&service_account.project_id, &documents);
-    let export_summary = export_feedback_to_learning_folders(&repo_root, &build_result.mapped_documents)?;
-    write_feedback_protocol_file(&repo_root, &export_summary.written_paths)?;
-
-    if let Some(root_obj) = build_result.payload.as_object_mut() {
-        root_obj.insert(
-            "learningExport".to_string(),
-            json!({
-                "checkedDocuments": export_summary.checked_documents,
-                "exportedFeedbacks": export_summary.exported_feedbacks,
-                "filteredFeedbacks": export_summary.filtered_feedbacks,
-                "unresolvedFolderFeedbacks": export_summary.unresolved_folder_feedbacks,
-                "exportSubdir": LEARNING_EXPORT_SUBDIR,
-                "protocolFile": PROTOCOL_RELATIVE_PATH,
-                "writtenPaths": export_summary
-                    .written_paths
-                    .iter()
-                    .map(|p| path_to_repo_relative(&repo_root, p))
-                    .collect::<Vec<String>>()
-            }),
-        );
-    }
-
-    if let Some(parent) = output_path.parent() {
-        fs::create_dir_all(parent).with_context(|| {
-            format!(
-                "failed to create output directory for {}",
-                output_path.display()
-            )
-        })?;
-    }
-
-    let serialized =
-        serde_json::to_vec_pretty(&build_result.payload).context("failed to serialize output JSON")?;
-    fs::write(&output_path, serialized)
-        .with_context(|| format!("failed to write output file {}", output_path.display()))?;
-
-    println!(
-        "Downloaded {} documents, exported {} feedback files, wrote {}",
-        documents.len(),
-        export_summary.exported_feedbacks,
-        output_path.display()
-    );
-
-    Ok(())
-}
-
-fn find_repo_root(start: PathBuf) -> Result<PathBuf> {
-    let mut cursor = start;
-    loop {
-        if cursor.join(".git").is_dir() {
-            return Ok(cursor);
-        }
-        if !cursor.pop() {
-            bail!("could not find repository root (.git directory)");
-        }
-    }
-}
-
-fn read_service_account(path: &Path) -> Result<ServiceAccount> {
-    let raw = fs::read_to_string(path)
-        .with_context(|| format!("failed to read service account file {}", path.display()))?;
-    serde_json::from_str(&raw).with_context(|| {
-        format!(
-            "failed to parse service account JSON from {}",
-            path.display()
-        )
-    })
-}
-
-fn fetch_access_token(client: &Client, service_account: &ServiceAccount) -> Result<String> {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .context("system time is before UNIX_EPOCH")?
-        .as_secs() as i64;
-
-    let claims = JwtClaims {
-        iss: service_account.client_email.clone(),
-        scope: TOKEN_SCOPE.to_string(),
-        aud: service_account.token_uri.clone(),
-        iat: now,
-        exp: now + 3600,
-    };
-
-    let header = Header::new(Algorithm::RS256);
-    let encoding_key = EncodingKey::from_rsa_pem(service_account.private_key.as_bytes())
-        .context("failed to load RSA private key from service account JSON")?;
-    let assertion = encode(&header, &claims, &encoding_key).context("failed to sign JWT assertion")?;
-
-    let token_response = client
-        .post(&service_account.token_uri)
-        .form(&[("grant_type", TOKEN_GRANT_TYPE), ("assertion", assertion.as_str())])
-        .send()
-        .context("token endpoint request failed")?
-        .error_for_status()
-        .context("token endpoint returned non-success status")?
-        .json::<TokenResponse>()
-        .context("failed to parse token endpoint response")?;
-
-    if token_response.access_token.trim().is_empty() {
-        bail!("token endpoint returned an empty access token");
-    }
-    Ok(token_response.access_token)
-}
-
-fn download_feedback_collection(client: &Client, access_token: &str, project_id: &str) -> Result<Vec<Value>> {
-    let mut all_documents: Vec<Value> = Vec::new();
-    let mut page_token: Option<String> = None;
-
-    loop {
-        let endpoint = format!(
-            "https://firestore.googleapis.com/v1/projects/{}/databases/(default)/documents/{}",
-            project_id, COLLECTION_NAME
-        );
-        let mut url = Url::parse(&endpoint).context("failed to build Firestore URL")?;
-        {
-            let mut query = url.query_pairs_mut();
-            query.append_pair("pageSize", &FIRESTORE_PAGE_SIZE.to_string());
-            if let Some(token) = page_token.as_ref() {
-                query.append_pair("pageToken", token);
-            }
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::All);
+    let options = resolve_run_options(Invocation {
+        overrides: settings_layer_from_args(&cli.common),
+        repo_root: cli.common.repo_root,
+        config: cli.common.config,
+        input: cli.common.input,
+        save_raw: cli.common.save_raw,
+        dry_run: cli.common.dry_run,
+    })?;
+
+    match command {
+        Command::Fetch => {
+            let summary = Pipeline::new(&options)?.run_fetch()?;
+            print_run_summary(&summary)?;
+            println!(
+                "Processed {} documents, wrote {}",
+                summary.documents,
+                options.output_path.display()
+            );
+            Ok(())
         }
-
-        let page = client
-            .get(url)
-            .bearer_auth(access_token)
-            .send()
-            .context("Firestore request failed")?
-            .error_for_status()
-            .context("Firestore returned non-success status")?
-            .json::<Value>()
-            .context("failed to parse Firestore response")?;
-
-        if let Some(documents) = page.get("documents").and_then(Value::as_array) {
-            all_documents.extend(documents.iter().cloned());
+        Command::Sanitize => {
+            let summary = Pipeline::new(&options)?.run_sanitize()?;
+            print_run_summary(&summary)?;
+            println!(
+                "Re-sanitized {} documents from {}, wrote {}",
+                summary.documents,
+                summary.source_path.as_deref().unwrap_or(&options.output_path).display(),
+                options.output_path.display()
+            );
+            Ok(())
         }
-
-        page_token = page
-            .get("nextPageToken")
-            .and_then(Value::as_str)
-            .map(|token| token.to_string())
-            .filter(|token| !token.is_empty());
-
-        if page_token.is_none() {
-            break;
+        Command::ExportLearnings => {
+            let summary = Pipeline::new(&options)?.run_export_learnings()?;
+            print_run_summary(&summary)?;
+            println!(
+                "Exported {} feedback files from {}",
+                summary.export.as_ref().map_or(0, |export| export.exported_feedbacks),
+                summary.source_path.as_deref().unwrap_or(&options.output_path).display()
+            );
+            Ok(())
         }
-    }
-
-    Ok(all_documents)
-}
-
-fn build_output_payload(project_id: &str, documents: &[Value]) -> BuildOutputResult {
-    let now_unix = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
-
-    let mut total_comment_fields = 0usize;
-    let mut total_changed_fields = 0usize;
-    let mut total_blocked_fields = 0usize;
-    let mut docs_with_blocked_comments = 0usize;
-
-    let mapped_docs: Vec<Value> = documents
-        .iter()
-        .map(|raw_doc| {
-            let name = raw_doc
-                .get("name")
-                .and_then(Value::as_str)
-                .unwrap_or_default()
-                .to_string();
-            let create_time = raw_doc
-                .get("createTime")
-                .and_then(Value::as_str)
-                .unwrap_or_default()
-                .to_string();
-            let update_time = raw_doc
-                .get("updateTime")
-                .and_then(Value::as_str)
-                .unwrap_or_default()
-                .to_string();
-            let fields = raw_doc
-                .get("fields")
-                .cloned()
-                .unwrap_or_else(|| Value::Object(Map::new()));
-
-            let mut data = decode_firestore_fields(&fields);
-            let reports = sanitize_comment_fields(&mut data);
-
-            let comment_field_count = reports.len();
-            let changed_count = reports.iter().filter(|r| r.changed).count();
-            let blocked_count = reports.iter().filter(|r| r.blocked).count();
-
-            total_comment_fields += comment_field_count;
-            total_changed_fields += changed_count;
-            total_blocked_fields += blocked_count;
-            if blocked_count > 0 {
-                docs_with_blocked_comments += 1;
-            }
-
-            json!({
-                "id": extract_document_id(&name),
-                "name": name,
-                "createTime": create_time,
-                "updateTime": update_time,
-                "data": data,
-                "commentSecurity": {
-                    "sanitizerVersion": SANITIZER_VERSION,
-                    "commentFieldsChecked": comment_field_count,
-                    "changedFields": changed_count,
-                    "blockedFields": blocked_count,
-                    "reports": reports
-                }
-            })
-        })
-        .collect();
-
-    let payload = json!({
-        "projectId": project_id,
-        "collection": COLLECTION_NAME,
-        "downloadedAtUnix": now_unix,
-        "documentCount": mapped_docs.len(),
-        "security": {
-            "sanitizerVersion": SANITIZER_VERSION,
-            "commentFieldsChecked": total_comment_fields,
-            "changedFields": total_changed_fields,
-            "blockedFields": total_blocked_fields,
-            "documentsWithBlockedComments": docs_with_blocked_comments,
-            "blockScoreThreshold": BLOCK_SCORE_THRESHOLD,
-            "commentMaxChars": COMMENT_MAX_CHARS
-        },
-        "documents": mapped_docs.clone()
-    });
-
-    BuildOutputResult {
-        payload,
-        mapped_documents: mapped_docs,
-    }
-}
-
-fn export_feedback_to_learning_folders(repo_root: &Path, mapped_docs: &[Value]) -> Result<LearningExportSummary> {
-    let learning_folders = discover_learning_folders(&repo_root.join("databases"))?;
-    cleanup_previous_learning_exports(&learning_folders)?;
-
-    let mut written_paths: Vec<PathBuf> = Vec::new();
-    let mut used_paths: HashSet<String> = HashSet::new();
-    let mut filtered_feedbacks = 0usize;
-    let mut unresolved_folder_feedbacks = 0usize;
-
-    for doc in mapped_docs {
-        let doc_id = doc
-            .get("id")
-            .and_then(Value::as_str)
-            .unwrap_or_default()
-            .trim()
-            .to_string();
-
-        let Some(data_obj) = doc.get("data").and_then(Value::as_object) else {
-            filtered_feedbacks += 1;
-            continue;
-        };
-
-        let blocked_fields = doc
-            .get("commentSecurity")
-            .and_then(Value::as_object)
-            .and_then(|s| s.get("blockedFields"))
-            .and_then(Value::as_u64)
-            .unwrap_or(0);
-
-        let comment_text = data_obj
-            .get("comment")
-            .and_then(Value::as_str)
-            .unwrap_or_default()
-            .trim()
-            .to_string();
-
-        if blocked_fields > 0
-            || comment_text.is_empty()
-            || comment_text == BLOCKED_COMMENT_TOKEN
-            || comment_text == EMPTY_COMMENT_TOKEN
-        {
-            filtered_feedbacks += 1;
-            continue;
+        Command::Protocol => {
+            let entries = Pipeline::new(&options)?.run_protocol()?;
+            println!("Wrote {} protocol entries to {}", entries, options.protocol_path.display());
+            Ok(())
         }
-
-        let Some(learning_folder) = resolve_learning_folder_for_feedback(repo_root, data_obj) else {
-            unresolved_folder_feedbacks += 1;
-            continue;
-        };
-
-        let export_dir = learning_folder.join(LEARNING_EXPORT_SUBDIR);
-        fs::create_dir_all(&export_dir).with_context(|| {
-            format!(
-                "failed to create learning export directory {}",
-                export_dir.display()
-            )
-        })?;
-
-        let safe_id = sanitize_file_component(if doc_id.is_empty() { "unknown" } else { &doc_id });
-        let mut target_path = export_dir.join(format!("feedback_{}.json", safe_id));
-        let mut suffix = 2usize;
-
-        loop {
-            let unique_key = target_path.to_string_lossy().to_string();
-            if !used_paths.contains(&unique_key) {
-                used_paths.insert(unique_key);
-                break;
-            }
-            target_path = export_dir.join(format!("feedback_{}_{}.json", safe_id, suffix));
-            suffix += 1;
+        Command::All => {
+            let summary = Pipeline::new(&options)?.run_all()?;
+            print_run_summary(&summary)?;
+            println!(
+                "Processed {} documents, exported {} feedback files, wrote {}",
+                summary.documents,
+                summary.export.as_ref().map_or(0, |export| export.exported_feedbacks),
+                options.output_path.display()
+            );
+            Ok(())
         }
-
-        let export_payload = json!({
-            "id": doc_id,
-            "source": data_obj.get("source").cloned().unwrap_or(Value::Null),
-            "comment": data_obj.get("comment").cloned().unwrap_or(Value::Null),
-            "createdAtIso": data_obj.get("createdAtIso").cloned().unwrap_or(Value::Null),
-            "context": data_obj.get("context").cloned().unwrap_or(Value::Null),
-            "commentSecurity": doc.get("commentSecurity").cloned().unwrap_or(Value::Null)
-        });
-
-        let encoded =
-            serde_json::to_vec_pretty(&export_payload).context("failed to serialize learning export payload")?;
-        fs::write(&target_path, encoded)
-            .with_context(|| format!("failed to write learning export file {}", target_path.display()))?;
-
-        written_paths.push(target_path);
-    }
-
-    written_paths.sort();
-
-    Ok(LearningExportSummary {
-        checked_documents: mapped_docs.len(),
-        exported_feedbacks: written_paths.len(),
-        filtered_feedbacks,
-        unresolved_folder_feedbacks,
-        written_paths,
-    })
-}
-
-fn discover_learning_folders(databases_root: &Path) -> Result<Vec<PathBuf>> {
-    if !databases_root.is_dir() {
-        return Ok(Vec::new());
-    }
-
-    let mut result = Vec::new();
-    let mut stack = vec![databases_root.to_path_buf()];
-
-    while let Some(current) = stack.pop() {
-        let entries = fs::read_dir(&current)
-            .with_context(|| format!("failed to read directory {}", current.display()))?;
-
-        for entry in entries {
-            let entry = entry.with_context(|| {
-                format!("failed to read directory entry under {}", current.display())
-            })?;
-            let path = entry.path();
-            if !path.is_dir() {
-                continue;
-            }
-
-            let name = path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or_default()
-                .to_string();
-
-            if name.starts_with("__04_lernings_") {
-                result.push(path);
-                continue;
-            }
-
-            stack.push(path);
-        }
-    }
-
-    result.sort();
-    Ok(result)
-}
-
-fn cleanup_previous_learning_exports(learning_folders: &[PathBuf]) -> Result<()> {
-    for folder in learning_folders {
-        let export_dir = folder.join(LEARNING_EXPORT_SUBDIR);
-        if export_dir.exists() {
-            fs::remove_dir_all(&export_dir).with_context(|| {
-                format!(
-                    "failed to clean previous learning export directory {}",
-                    export_dir.display()
-                )
+        Command::Config {
+            action: ConfigAction::Show,
+        } => run_config_show(&options),
+        Command::TestRules { corpus } => run_test_rules(&options, corpus),
+        Command::Review { action } => run_review(&options, action),
+        Command::Archive {
+            processed,
+            older_than_days,
+            confirm_delete,
+        } => {
+            let summary = Pipeline::new(&options)?.run_archive(&ArchiveRequest {
+                processed,
+                older_than_days,
+                confirm_delete,
             })?;
+            print_archive_summary(&options, &summary)
         }
     }
-    Ok(())
 }
 
-fn resolve_learning_folder_for_feedback(repo_root: &Path, data_obj: &Map<String, Value>) -> Option<PathBuf> {
-    let mut candidates: Vec<String> = Vec::new();
-
-    if let Some(context_obj) = data_obj.get("context").and_then(Value::as_object) {
-        push_candidate_path(context_obj, "folderPath", &mut candidates);
-        push_candidate_path(context_obj, "gamePath", &mut candidates);
-        push_candidate_path(context_obj, "jsonPath", &mut candidates);
-    }
-
-    push_candidate_path(data_obj, "folderPath", &mut candidates);
-    push_candidate_path(data_obj, "gamePath", &mut candidates);
-    push_candidate_path(data_obj, "jsonPath", &mut candidates);
-
-    for candidate in candidates {
-        if let Some(path) = resolve_learning_folder_from_candidate(repo_root, &candidate) {
-            return Some(path);
-        }
+/// The incremental merge, quarantine and write-back lines shared by the pipeline commands.
+fn print_run_summary(summary: &RunSummary) -> Result<()> {
+    if let Some(incremental) = &summary.incremental {
+        println!(
+            "Incremental sync: {} new documents since {}",
+            incremental.fetched, incremental.since
+        );
     }
-
-    None
-}
-
-fn push_candidate_path(map: &Map<String, Value>, key: &str, out: &mut Vec<String>) {
-    if let Some(value) = map.get(key).and_then(Value::as_str) {
-        let trimmed = value.trim();
-        if !trimmed.is_empty() {
-            out.push(trimmed.to_string());
-        }
+    let quarantine = &summary.quarantine;
+    if quarantine.added > 0 || quarantine.released > 0 || quarantine.pending > 0 {
+        println!(
+            "Quarantine: {} new, {} released after review, {} pending (`review list`)",
+            quarantine.added, quarantine.released, quarantine.pending
+        );
     }
-}
-
-fn resolve_learning_folder_from_candidate(repo_root: &Path, candidate: &str) -> Option<PathBuf> {
-    let normalized = normalize_repo_candidate_path(candidate)?;
-
-    let mut absolute = if Path::new(&normalized).is_absolute() {
-        PathBuf::from(&normalized)
-    } else {
-        repo_root.join(&normalized)
+    let Some(write_back) = &summary.write_back else {
+        return Ok(());
     };
-
-    if !absolute.exists() && absolute.extension().is_some() {
-        absolute = absolute.parent()?.to_path_buf();
-    } else if absolute.is_file() {
-        absolute = absolute.parent()?.to_path_buf();
-    }
-
-    if let Some(name) = absolute.file_name().and_then(|n| n.to_str()) {
-        if name.starts_with("__04_lernings_") && absolute.is_dir() {
-            return Some(absolute);
-        }
-    }
-
-    let mut cursor = Some(absolute.as_path());
-    while let Some(current) = cursor {
-        if let Some(found) = find_learning_child_directory(current) {
-            return Some(found);
-        }
-        if current == repo_root {
-            break;
-        }
-        cursor = current.parent();
+    if write_back.dry_run {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({ "dryRun": true, "commits": write_back.planned_commits }))
+                .context("failed to render planned writes")?
+        );
+        println!(
+            "Write-back (dry run): {} documents would be marked, {} already marked",
+            write_back.planned_writes, write_back.already_processed
+        );
+    } else {
+        println!(
+            "Write-back: marked {} documents in {} commits, {} already marked",
+            write_back.committed_writes, write_back.commits, write_back.already_processed
+        );
     }
-
-    None
+    Ok(())
 }
 
-fn normalize_repo_candidate_path(candidate: &str) -> Option<String> {
-    let trimmed = candidate.trim();
-    if trimmed.is_empty() {
-        return None;
-    }
-
-    if trimmed.contains("://") {
-        return None;
+fn print_archive_summary(options: &RunOptions, summary: &ArchiveSummary) -> Result<()> {
+    println!(
+        "Archive: {} of {} documents selected",
+        summary.selected.len(),
+        summary.fetched
+    );
+    let Some(archive_path) = &summary.archive_path else {
+        return Ok(());
+    };
+    if summary.dry_run {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "dryRun": true,
+                "archiveFile": archive_path.display().to_string(),
+                "documents": summary.selected,
+                "commits": summary.planned_commits
+            }))
+            .context("failed to render planned writes")?
+        );
+        return Ok(());
     }
 
-    let normalized = trimmed.replace('\\', "/");
-    if Path::new(&normalized).is_absolute() {
-        return Some(normalized);
+    println!(
+        "Archive: wrote and verified {} documents in {}",
+        summary.archived,
+        archive_path.display()
+    );
+    if let Some((collection, commits)) = &summary.copied_to {
+        println!(
+            "Archive: copied {} documents to {} in {} commits",
+            summary.archived, collection, commits
+        );
     }
-
-    Some(normalized.trim_start_matches('/').to_string())
-}
-
-fn find_learning_child_directory(dir: &Path) -> Option<PathBuf> {
-    let entries = fs::read_dir(dir).ok()?;
-    let mut learning_dirs: Vec<PathBuf> = entries
-        .filter_map(|entry| entry.ok())
-        .map(|entry| entry.path())
-        .filter(|path| {
-            path.is_dir()
-                && path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .map(|name| name.starts_with("__04_lernings_"))
-                    .unwrap_or(false)
-        })
-        .collect();
-
-    learning_dirs.sort();
-    learning_dirs.into_iter().next()
-}
-
-fn write_feedback_protocol_file(repo_root: &Path, written_paths: &[PathBuf]) -> Result<()> {
-    let protocol_path = repo_root.join(PROTOCOL_RELATIVE_PATH);
-
-    if let Some(parent) = protocol_path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("failed to create protocol directory {}", parent.display()))?;
+    match summary.delete_commits {
+        Some(commits) => println!(
+            "Archive: deleted {} documents from {} in {} commits",
+            summary.archived, options.settings.collection, commits
+        ),
+        None => println!(
+            "Nothing deleted from {}; rerun with --confirm-delete to remove the archived documents",
+            options.settings.collection
+        ),
     }
-
-    let mut lines: Vec<String> = written_paths
-        .iter()
-        .map(|p| path_to_repo_relative(repo_root, p))
-        .collect();
-    lines.sort();
-
-    let content = if lines.is_empty() {
-        String::new()
-    } else {
-        format!("{}\n", lines.join("\n"))
-    };
-
-    fs::write(&protocol_path, content)
-        .with_context(|| format!("failed to write protocol file {}", protocol_path.display()))?;
-
     Ok(())
 }
 
-fn path_to_repo_relative(repo_root: &Path, absolute: &Path) -> String {
-    absolute
-        .strip_prefix(repo_root)
-        .map(|p| p.to_string_lossy().replace('\\', "/"))
-        .unwrap_or_else(|_| absolute.to_string_lossy().replace('\\', "/"))
-}
-
-fn sanitize_file_component(input: &str) -> String {
-    let mut out = String::with_capacity(input.len());
-    for ch in input.chars() {
-        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
-            out.push(ch);
-        } else {
-            out.push('_');
-        }
-    }
-
-    let collapsed = out.trim_matches('_').to_string();
-    if collapsed.is_empty() {
-        "unknown".to_string()
-    } else {
-        collapsed
-    }
-}
-
-fn extract_document_id(full_name: &str) -> String {
-    full_name
-        .rsplit('/')
-        .next()
-        .map(|v| v.to_string())
-        .unwrap_or_default()
-}
-
-fn decode_firestore_fields(fields: &Value) -> Value {
-    let Some(object) = fields.as_object() else {
-        return Value::Object(Map::new());
-    };
-
-    let mut decoded = Map::new();
-    for (key, value) in object {
-        decoded.insert(key.clone(), decode_firestore_value(value));
-    }
-
-    Value::Object(decoded)
+fn settings_layer_from_args(args: &CommonArgs) -> SettingsLayer {
+    let path_string = |p: &Option<PathBuf>| p.as_ref().map(|p| p.to_string_lossy().to_string());
+
+    SettingsLayer {
+        collection: args.collection.clone().filter(|c| !c.trim().is_empty()),
+        service_account_file: path_string(&args.service_account),
+        output_path: path_string(&args.output),
+        protocol_path: path_string(&args.protocol),
+        review_path: path_string(&args.review),
+        quarantine_path: path_string(&args.quarantine),
+        page_size: args.page_size,
+        learning_export_subdir: args.learning_export_subdir.clone(),
+        learning_folder_pattern: args.learning_folder_pattern.clone(),
+        site_path_prefix: args.site_path_prefix.clone(),
+        export_roots: (!args.export_roots.is_empty()).then(|| args.export_roots.clone()),
+        comment_max_chars: args.comment_max_chars,
+        quarantine_score_threshold: args.quarantine_score_threshold,
+        block_score_threshold: args.block_score_threshold,
+        firestore_base_url: args.firestore_base_url.clone(),
+        emulator_host: args.emulator_host.clone(),
+        project_id: args.project_id.clone(),
+        rule_pack: path_string(&args.rule_pack),
+        incremental: args.incremental.then_some(true),
+        state_path: path_string(&args.state),
+        watermark_field: None,
+        full_sync_interval_hours: args.full_sync_interval_hours,
+        lossless_decoding: args.lossless_decoding.then_some(true),
+        write_back: args.write_back.then_some(true),
+        processed_by: args.processed_by.clone(),
+        archive_dir: path_string(&args.archive_dir),
+        archive_collection: args.archive_collection.clone().filter(|c| !c.trim().is_empty()),
+    }
+}
+
+fn run_config_show(options: &RunOptions) -> Result<()> {
+    let rendered = toml::to_string_pretty(&options.settings).context("failed to render merged config")?;
+    println!(
+        "# config file: {} ({})",
+        options.config_path.display(),
+        if options.config_loaded { "loaded" } else { "not found, defaults used" }
+    );
+    println!("# repo root: {}", options.repo_root.display());
+    let sanitizer = RuleSanitizer::from_options(options)?;
+    println!(
+        "# rule pack: {} ({})",
+        options
+            .rule_pack_path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "built-in".to_string()),
+        sanitizer.version()
+    );
+    print!("{}", rendered);
+    Ok(())
 }
 
-fn decode_firestore_value(value: &Value) -> Value {
-    let Some(object) = value.as_object() else {
-        return Value::Null;
+fn run_test_rules(options: &RunOptions, corpus_path: Option<PathBuf>) -> Result<()> {
+    let corpus = match corpus_path {
+        Some(path) => Corpus::load(&path)?,
+        None => Corpus::builtin(),
     };
+    let sanitizer = RuleSanitizer::from_options(options)?;
+    let evaluation = evaluate(&corpus, &sanitizer)?;
+    let percent = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| format!("{:.0}%", v * 100.0));
 
-    if object.contains_key("nullValue") {
-        return Value::Null;
-    }
-    if let Some(v) = object.get("booleanValue").and_then(Value::as_bool) {
-        return Value::Bool(v);
-    }
-    if let Some(v) = object.get("stringValue").and_then(Value::as_str) {
-        return Value::String(v.to_string());
-    }
-    if let Some(v) = object.get("timestampValue").and_then(Value::as_str) {
-        return Value::String(v.to_string());
-    }
-    if let Some(v) = object.get("referenceValue").and_then(Value::as_str) {
-        return Value::String(v.to_string());
-    }
-    if let Some(v) = object.get("bytesValue").and_then(Value::as_str) {
-        return Value::String(v.to_string());
-    }
-    if let Some(v) = object.get("integerValue").and_then(Value::as_str) {
-        if let Ok(parsed) = v.parse::<i64>() {
-            return Value::Number(Number::from(parsed));
-        }
-        return Value::String(v.to_string());
-    }
-    if let Some(raw_double) = object.get("doubleValue") {
-        if let Some(parsed) = parse_firestore_number(raw_double) {
-            if let Some(num) = Number::from_f64(parsed) {
-                return Value::Number(num);
-            }
-        }
-    }
-    if let Some(geo) = object.get("geoPointValue").and_then(Value::as_object) {
-        let mut decoded_geo = Map::new();
-        if let Some(lat) = geo.get("latitude").and_then(parse_firestore_number) {
-            if let Some(n) = Number::from_f64(lat) {
-                decoded_geo.insert("latitude".to_string(), Value::Number(n));
-            }
-        }
-        if let Some(lng) = geo.get("longitude").and_then(parse_firestore_number) {
-            if let Some(n) = Number::from_f64(lng) {
-                decoded_geo.insert("longitude".to_string(), Value::Number(n));
-            }
-        }
-        if !decoded_geo.is_empty() {
-            return Value::Object(decoded_geo);
-        }
-        return Value::Object(geo.clone());
-    }
-    if let Some(array_obj) = object.get("arrayValue").and_then(Value::as_object) {
-        if let Some(values) = array_obj.get("values").and_then(Value::as_array) {
-            return Value::Array(values.iter().map(decode_firestore_value).collect());
-        }
-        return Value::Array(Vec::new());
-    }
-    if let Some(map_obj) = object.get("mapValue").and_then(Value::as_object) {
-        let fields = map_obj
-            .get("fields")
-            .cloned()
-            .unwrap_or_else(|| Value::Object(Map::new()));
-        return decode_firestore_fields(&fields);
+    println!("# rule pack: {}", sanitizer.version());
+    println!("# corpus: {} cases", evaluation.cases.len());
+    println!(
+        "{:<34} {:<10} {:>4} {:>4} {:>4} {:>9} {:>7}",
+        "rule", "action", "tp", "fp", "fn", "precision", "recall"
+    );
+    let rows = evaluation
+        .rules
+        .iter()
+        .map(|rule| (rule.rule_id.as_str(), rule.action.as_str(), &rule.score))
+        .chain(std::iter::once(("(withhold decision)", "", &evaluation.decision)));
+    for (rule_id, action, score) in rows {
+        println!(
+            "{:<34} {:<10} {:>4} {:>4} {:>4} {:>9} {:>7}",
+            rule_id,
+            action,
+            score.true_positives,
+            score.false_positives,
+            score.false_negatives,
+            percent(score.precision()),
+            percent(score.recall())
+        );
     }
 
-    Value::Object(object.clone())
-}
-
-fn parse_firestore_number(value: &Value) -> Option<f64> {
-    if let Some(v) = value.as_f64() {
-        return Some(v);
-    }
-    if let Some(v) = value.as_i64() {
-        return Some(v as f64);
-    }
-    if let Some(v) = value.as_u64() {
-        return Some(v as f64);
+    for case in evaluation.cases.iter().filter(|case| !case.passed()) {
+        println!(
+            "{} {}: label {}, blocked {}, missing [{}], unexpected [{}]{}",
+            if case.known_issue.is_some() { "KNOWN" } else { "FAIL " },
+            case.id,
+            case.label.as_str(),
+            case.blocked,
+            case.missing_rules().join(", "),
+            case.unexpected_rules().join(", "),
+            case.known_issue.as_deref().map(|issue| format!(" ({})", issue)).unwrap_or_default()
+        );
     }
-    if let Some(v) = value.as_str() {
-        return v.parse::<f64>().ok();
+    let failures = evaluation.failures().count();
+    if failures > 0 {
+        bail!("{} corpus cases disagree with the rule pack", failures);
     }
-    None
-}
-
-fn sanitize_comment_fields(value: &mut Value) -> Vec<CommentSanitizationReport> {
-    let mut reports = Vec::new();
-    walk_and_sanitize_comments(value, "data", false, &mut reports);
-    reports
+    Ok(())
 }
 
-fn walk_and_sanitize_comments(
-    value: &mut Value,
-    path: &str,
-    in_comment_context: bool,
-    reports: &mut Vec<CommentSanitizationReport>,
-) {
-    match value {
-        Value::Object(map) => {
-            for (key, child) in map.iter_mut() {
-                let child_path = join_path(path, key);
-                let comment_context = in_comment_context || is_comment_field(key);
-                walk_and_sanitize_comments(child, &child_path, comment_context, reports);
+fn run_review(options: &RunOptions, action: ReviewAction) -> Result<()> {
+    let mut store = QuarantineStore::open(&options.quarantine_path, &options.repo_root)?;
+    match action {
+        ReviewAction::List { status } => {
+            let wanted = match status.as_str() {
+                "all" => None,
+                "pending" => Some(ReviewStatus::Pending),
+                "approved" => Some(ReviewStatus::Approved),
+                "rejected" => Some(ReviewStatus::Rejected),
+                "blocked" => Some(ReviewStatus::Blocked),
+                other => bail!("unknown status {:?} (pending, approved, rejected, blocked, all)", other),
+            };
+            let entries: Vec<_> = store
+                .entries()
+                .iter()
+                .filter(|entry| wanted.is_none_or(|status| entry.status == status))
+                .collect();
+            println!(
+                "# {} ({}, {} entries)",
+                store.path().display(),
+                if store.is_encrypted() { "encrypted" } else { "plain" },
+                entries.len()
+            );
+            for entry in entries {
+                let preview: String = entry.original.chars().take(60).collect::<String>().replace('\n', " ");
+                println!(
+                    "{}\t{}\tscore {}\t{}\t{}",
+                    entry.id,
+                    entry.status.as_str(),
+                    entry.score,
+                    entry
+                        .reasons
+                        .iter()
+                        .filter(|reason| reason.starts_with("detected:"))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    preview
+                );
             }
+            Ok(())
         }
-        Value::Array(items) => {
-            for (index, child) in items.iter_mut().enumerate() {
-                let child_path = format!("{}[{}]", path, index);
-                walk_and_sanitize_comments(child, &child_path, in_comment_context, reports);
-            }
+        ReviewAction::Show { id } => {
+            let Some(entry) = store.get(&id) else {
+                bail!("no quarantined comment with id {:?}", id);
+            };
+            println!("{}", serde_json::to_string_pretty(entry).context("failed to render entry")?);
+            Ok(())
         }
-        Value::String(text) => {
-            if !in_comment_context {
-                return;
-            }
-
-            let outcome = sanitize_comment_text(text);
-            *text = outcome.sanitized.clone();
-            reports.push(CommentSanitizationReport {
-                field_path: path.to_string(),
-                blocked: outcome.blocked,
-                changed: outcome.changed,
-                score: outcome.score,
-                reasons: outcome.reasons,
-                original_length: outcome.original_length,
-                sanitized_length: outcome.sanitized_length,
-            });
-        }
-        _ => {}
-    }
-}
-
-fn join_path(path: &str, key: &str) -> String {
-    if path.is_empty() {
-        key.to_string()
-    } else {
-        format!("{}.{}", path, key)
+        ReviewAction::Approve { id, decision } => decide(&mut store, &id, ReviewStatus::Approved, decision),
+        ReviewAction::Reject { id, decision } => decide(&mut store, &id, ReviewStatus::Rejected, decision),
     }
 }
 
-fn is_comment_field(key: &str) -> bool {
-    let folded = key
-        .to_ascii_lowercase()
-        .replace('-', "")
-        .replace('_', "")
-        .replace(' ', "");
-
-    folded.contains("comment")
-        || folded.contains("kommentar")
-        || folded.contains("feedbacktext")
-        || folded == "feedback"
-        || folded == "message"
-        || folded == "nachricht"
-}
-
-fn sanitize_comment_text(input: &str) -> SanitizationOutcome {
-    let mut reasons = BTreeSet::<String>::new();
-    let mut score = 0u32;
-    let mut changed = false;
-    let mut matched_injection_rule = false;
-
-    let original_length = input.chars().count();
-    let mut sanitized = input.nfkc().collect::<String>();
-
-    if sanitized != input {
-        changed = true;
-        score += 1;
-        reasons.insert("unicode_normalized_nfkc".to_string());
-    }
-
-    let without_control = CONTROL_CHAR_RE.replace_all(&sanitized, "").to_string();
-    if without_control != sanitized {
-        changed = true;
-        score += 1;
-        reasons.insert("control_chars_removed".to_string());
-        sanitized = without_control;
-    }
-
-    let without_zero_width = ZERO_WIDTH_RE.replace_all(&sanitized, "").to_string();
-    if without_zero_width != sanitized {
-        changed = true;
-        score += 1;
-        reasons.insert("zero_width_removed".to_string());
-        sanitized = without_zero_width;
-    }
-
-    let compact_whitespace = MULTI_SPACE_RE.replace_all(&sanitized, " ").to_string();
-    if compact_whitespace != sanitized {
-        changed = true;
-        reasons.insert("whitespace_compacted".to_string());
-        sanitized = compact_whitespace;
-    }
-
-    let trimmed = sanitized.trim().to_string();
-    if trimmed != sanitized {
-        changed = true;
-        reasons.insert("trimmed".to_string());
-        sanitized = trimmed;
-    }
-
-    let char_count = sanitized.chars().count();
-    if char_count > COMMENT_MAX_CHARS {
-        changed = true;
-        score += 2;
-        reasons.insert("max_length_truncated".to_string());
-        sanitized = sanitized.chars().take(COMMENT_MAX_CHARS).collect::<String>();
-    }
-
-    for rule in DETECTION_RULES.iter() {
-        if rule.regex.is_match(&sanitized) {
-            matched_injection_rule = true;
-            score += rule.weight;
-            reasons.insert(format!("detected:{}", rule.id));
-        }
-    }
-
-    for rewrite in REWRITE_RULES.iter() {
-        let updated = rewrite
-            .regex
-            .replace_all(&sanitized, rewrite.replacement)
-            .to_string();
-        if updated != sanitized {
-            changed = true;
-            sanitized = updated;
-        }
-    }
-
-    if sanitized.is_empty() {
-        changed = true;
-        score += 1;
-        reasons.insert("empty_after_scrub".to_string());
-        sanitized = EMPTY_COMMENT_TOKEN.to_string();
-    }
-
-    let blocked = matched_injection_rule || score >= BLOCK_SCORE_THRESHOLD;
-    if blocked {
-        changed = true;
-        if matched_injection_rule {
-            reasons.insert("blocked_by_detected_injection_rule".to_string());
-        } else {
-            reasons.insert("blocked_by_score_threshold".to_string());
-        }
-        sanitized = BLOCKED_COMMENT_TOKEN.to_string();
-    }
-
-    let sanitized_length = sanitized.chars().count();
-
-    SanitizationOutcome {
-        sanitized,
-        blocked,
-        changed,
-        score,
-        reasons: reasons.into_iter().collect(),
-        original_length,
-        sanitized_length,
-    }
-}
-
-fn rule(id: &'static str, weight: u32, pattern: &'static str) -> DetectionRule {
-    DetectionRule {
-        id,
-        weight,
-        regex: Regex::new(pattern).expect("valid detection regex"),
-    }
-}
-
-fn rewrite(pattern: &'static str, replacement: &'static str) -> RewriteRule {
-    RewriteRule {
-        regex: Regex::new(pattern).expect("valid rewrite regex"),
-        replacement,
+fn decide(store: &mut QuarantineStore, id: &str, status: ReviewStatus, decision: DecisionArgs) -> Result<()> {
+    let reviewer = match decision.reviewer {
+        Some(reviewer) => reviewer,
+        None => std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .context("no reviewer name; pass --reviewer")?,
+    };
+    let entry = store.decide(id, status, &reviewer, decision.note)?;
+    println!("{} {} by {}", entry.id, entry.status.as_str(), reviewer);
+    store.save()?;
+    if status == ReviewStatus::Approved {
+        println!("The comment is exported on the next `all` or `export-learnings` run.");
     }
+    Ok(())
 }
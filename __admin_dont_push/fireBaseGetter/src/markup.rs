@@ -0,0 +1,447 @@
+//! Structural cleanup of Markdown and HTML in comments, so exported comments read as plain text.
+//!
+//! Entities are decoded, HTML tags are removed with their visible text kept, links become
+//! `text (domain)`, images and active content (scripts, styles, frames) are dropped. Code spans and
+//! fences stay untouched, and so do the prompt-role tags that `xml_prompt_tag` looks for.
+
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use reqwest::Url;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::sanitize::{RuleMatch, CONTROL_CHAR_RE, ZERO_WIDTH_RE};
+
+/// Removals recorded per kind and pass, like the rule spans in [`crate::sanitize`].
+const MAX_MATCHES_PER_KIND: usize = 10;
+
+static CODE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)```.*?```|`[^`\n]+`").expect("valid regex"));
+static ENTITY_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"&(?:#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[a-zA-Z]{2,8});").expect("valid regex")
+});
+static HTML_TOKEN_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?sx)
+        <!--.*?-->
+        | <(/?)([A-Za-z][A-Za-z0-9-]*)
+          ((?:\s+[^\s"'>/=]+(?:\s*=\s*(?:"[^"]*"|'[^']*'|[^\s"'=<>`]+))?)*)
+          \s*/?>"#,
+    )
+    .expect("valid regex")
+});
+static HREF_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)(?:^|\s)href\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+))"#)
+        .expect("valid regex")
+});
+static MD_IMAGE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"!\[[^\]\n]*\]\(\s*<?[^\s()<>]*(?:\([^\s()]*\)[^\s()<>]*)*>?(?:\s+(?:"[^"\n]*"|'[^'\n]*'))?\s*\)"#)
+        .expect("valid regex")
+});
+/// `[text](target "title")`, or an autolink `<scheme:target>` in group 3.
+static MD_LINK_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"\[([^\[\]\n]*)\]\(\s*<?([^\s()<>]*(?:\([^\s()]*\)[^\s()<>]*)*)>?(?:\s+(?:"[^"\n]*"|'[^'\n]*'))?\s*\)|<([A-Za-z][A-Za-z0-9+.-]{1,31}:[^\s<>]*)>"#,
+    )
+    .expect("valid regex")
+});
+
+/// Elements that are dropped together with everything inside them.
+const ACTIVE_CONTENT_TAGS: &[&str] = &[
+    "applet", "embed", "frame", "frameset", "iframe", "math", "noscript", "object", "script",
+    "style", "svg", "template",
+];
+const IMAGE_TAGS: &[&str] = &["audio", "img", "picture", "source", "track", "video"];
+/// Removed like any tag, but replaced by a space so the words around them stay apart.
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+/// Other HTML elements; anything else in angle brackets (`List<String>`, `<3`) is plain text.
+const INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "base", "bdi", "bdo", "big", "body", "button", "canvas", "caption", "center",
+    "cite", "code", "col", "colgroup", "data", "del", "dfn", "dialog", "em", "fieldset", "font",
+    "form", "head", "html", "i", "input", "ins", "kbd", "label", "legend", "link", "mark",
+    "marquee", "meta", "meter", "optgroup", "option", "output", "param", "progress", "q", "rp",
+    "rt", "ruby", "s", "samp", "select", "small", "span", "strike", "strong", "sub", "sup",
+    "tbody", "textarea", "tfoot", "thead", "time", "title", "tt", "u", "var", "wbr",
+];
+/// Tags of the `xml_prompt_tag` rule; they stay so the rule pack decides about them.
+const PROMPT_TAGS: &[&str] = &[
+    "assistant",
+    "developer",
+    "instruction",
+    "instructions",
+    "prompt",
+    "system",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkupKind {
+    Entity,
+    HtmlComment,
+    HtmlTag,
+    /// A link target, replaced by its domain.
+    Link,
+    Image,
+    /// Scripts, styles, frames and other active content, with their content.
+    Script,
+}
+
+impl MarkupKind {
+    /// Used in the reason code `markup_removed:<kind>`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MarkupKind::Entity => "entity",
+            MarkupKind::HtmlComment => "html_comment",
+            MarkupKind::HtmlTag => "html_tag",
+            MarkupKind::Link => "link",
+            MarkupKind::Image => "image",
+            MarkupKind::Script => "script",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MarkupCleanup {
+    pub text: String,
+    /// In the order they were first removed, each once.
+    pub kinds: Vec<MarkupKind>,
+    /// Offsets refer to the text before the pass that removed them.
+    pub matches: Vec<RuleMatch>,
+}
+
+/// Removes Markdown and HTML structure from `text`, keeping what a reader would see.
+pub fn strip_markup(text: &str) -> MarkupCleanup {
+    let mut cleanup = Cleanup::default();
+    let decoded = cleanup.pass(text, Cleanup::decode_entities);
+    let without_html = cleanup.pass(&decoded, Cleanup::strip_html);
+    let without_images = cleanup.pass(&without_html, Cleanup::strip_markdown_images);
+    let plain = cleanup.pass(&without_images, Cleanup::strip_markdown_links);
+    MarkupCleanup {
+        text: plain,
+        kinds: cleanup.kinds,
+        matches: cleanup.matches,
+    }
+}
+
+#[derive(Default)]
+struct Cleanup {
+    kinds: Vec<MarkupKind>,
+    matches: Vec<RuleMatch>,
+    /// Matches per kind in the current pass.
+    recorded: Vec<(MarkupKind, usize)>,
+}
+
+impl Cleanup {
+    /// Runs `step` on every stretch of `text` outside code spans; it gets the stretch and its offset.
+    fn pass(&mut self, text: &str, step: fn(&mut Self, &str, &str, usize) -> String) -> String {
+        self.recorded.clear();
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for code in CODE_RE.find_iter(text) {
+            out.push_str(&step(self, text, &text[last..code.start()], last));
+            out.push_str(code.as_str());
+            last = code.end();
+        }
+        out.push_str(&step(self, text, &text[last..], last));
+        out
+    }
+
+    fn record(&mut self, kind: MarkupKind, whole: &str, range: Range<usize>) {
+        if !self.kinds.contains(&kind) {
+            self.kinds.push(kind);
+        }
+        let count = match self.recorded.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, count)) => count,
+            None => {
+                self.recorded.push((kind, 0));
+                &mut self.recorded.last_mut().expect("just pushed").1
+            }
+        };
+        if *count < MAX_MATCHES_PER_KIND {
+            *count += 1;
+            self.matches.push(RuleMatch::locate(
+                whole,
+                range,
+                kind.as_str(),
+                "markup_removed",
+                "text",
+            ));
+        }
+    }
+
+    fn decode_entities(&mut self, whole: &str, prose: &str, offset: usize) -> String {
+        self.replace_each(
+            whole,
+            prose,
+            offset,
+            &ENTITY_RE,
+            MarkupKind::Entity,
+            |caps| decode_entity(&caps[0]).unwrap_or_else(|| caps[0].to_string()),
+        )
+    }
+
+    fn strip_html(&mut self, whole: &str, prose: &str, offset: usize) -> String {
+        let mut out = String::with_capacity(prose.len());
+        let mut last = 0;
+        // Closing tag that ends the active content being dropped, with where it started.
+        let mut dropping: Option<(String, usize)> = None;
+        // Domain of every open `<a>`, appended when it closes.
+        let mut open_links: Vec<Option<String>> = Vec::new();
+
+        for caps in HTML_TOKEN_RE.captures_iter(prose) {
+            let token = caps.get(0).expect("whole match");
+            let closing = caps.get(1).is_some_and(|m| !m.as_str().is_empty());
+            let name = caps.get(2).map(|m| m.as_str().to_ascii_lowercase());
+
+            if let Some((until, start)) = &dropping {
+                if closing && name.as_deref() == Some(until.as_str()) {
+                    self.record(
+                        MarkupKind::Script,
+                        whole,
+                        offset + start..offset + token.end(),
+                    );
+                    dropping = None;
+                    last = token.end();
+                }
+                continue;
+            }
+
+            let Some(name) = name else {
+                out.push_str(&prose[last..token.start()]);
+                last = token.end();
+                self.record(
+                    MarkupKind::HtmlComment,
+                    whole,
+                    offset + token.start()..offset + token.end(),
+                );
+                continue;
+            };
+            let name = name.as_str();
+            let kind = if PROMPT_TAGS.contains(&name) {
+                continue;
+            } else if ACTIVE_CONTENT_TAGS.contains(&name) {
+                MarkupKind::Script
+            } else if IMAGE_TAGS.contains(&name) {
+                MarkupKind::Image
+            } else if name == "a" {
+                MarkupKind::Link
+            } else if BLOCK_TAGS.contains(&name) || INLINE_TAGS.contains(&name) {
+                MarkupKind::HtmlTag
+            } else {
+                continue;
+            };
+
+            out.push_str(&prose[last..token.start()]);
+            last = token.end();
+            let self_closing = token.as_str().ends_with("/>");
+            match kind {
+                MarkupKind::Script if !closing && !self_closing && name != "embed" => {
+                    dropping = Some((name.to_string(), token.start()));
+                    continue;
+                }
+                MarkupKind::Link if closing => {
+                    if let Some(Some(domain)) = open_links.pop() {
+                        out.push_str(&format!(" ({})", domain));
+                    }
+                    self.record(
+                        MarkupKind::HtmlTag,
+                        whole,
+                        offset + token.start()..offset + token.end(),
+                    );
+                    continue;
+                }
+                MarkupKind::Link => {
+                    let href = caps
+                        .get(3)
+                        .and_then(|attrs| HREF_RE.captures(attrs.as_str()))
+                        .and_then(|href| {
+                            href.get(1)
+                                .or_else(|| href.get(2))
+                                .or_else(|| href.get(3))
+                                .map(|m| m.as_str().to_string())
+                        });
+                    open_links.push(href.as_deref().and_then(link_domain));
+                    let kind = if href.is_some() {
+                        MarkupKind::Link
+                    } else {
+                        MarkupKind::HtmlTag
+                    };
+                    self.record(kind, whole, offset + token.start()..offset + token.end());
+                    continue;
+                }
+                _ => {}
+            }
+            if BLOCK_TAGS.contains(&name) {
+                out.push(' ');
+            }
+            self.record(kind, whole, offset + token.start()..offset + token.end());
+        }
+
+        match dropping {
+            // Unclosed active content takes the rest of the stretch with it.
+            Some((_, start)) => self.record(
+                MarkupKind::Script,
+                whole,
+                offset + start..offset + prose.len(),
+            ),
+            None => out.push_str(&prose[last..]),
+        }
+        out
+    }
+
+    fn strip_markdown_images(&mut self, whole: &str, prose: &str, offset: usize) -> String {
+        self.replace_each(
+            whole,
+            prose,
+            offset,
+            &MD_IMAGE_RE,
+            MarkupKind::Image,
+            |_| String::new(),
+        )
+    }
+
+    fn strip_markdown_links(&mut self, whole: &str, prose: &str, offset: usize) -> String {
+        self.replace_each(
+            whole,
+            prose,
+            offset,
+            &MD_LINK_RE,
+            MarkupKind::Link,
+            |caps| {
+                match caps.get(1) {
+                    Some(text) => link_text(text.as_str().trim(), link_domain(&caps[2])),
+                    // `<scheme:...>` autolink
+                    None => link_domain(&caps[3]).unwrap_or_default(),
+                }
+            },
+        )
+    }
+
+    fn replace_each(
+        &mut self,
+        whole: &str,
+        prose: &str,
+        offset: usize,
+        regex: &Regex,
+        kind: MarkupKind,
+        replacement: impl Fn(&Captures) -> String,
+    ) -> String {
+        let mut hits = Vec::new();
+        let replaced = regex
+            .replace_all(prose, |caps: &Captures| {
+                let found = caps.get(0).expect("whole match");
+                let replaced = replacement(caps);
+                if replaced != found.as_str() {
+                    hits.push(found.range());
+                }
+                replaced
+            })
+            .to_string();
+        for range in hits {
+            self.record(kind, whole, offset + range.start..offset + range.end);
+        }
+        replaced
+    }
+}
+
+/// `&amp;`, `&#60;`, `&#x3C;` and the other common entities; `None` keeps the entity as written.
+fn decode_entity(entity: &str) -> Option<String> {
+    let name = &entity[1..entity.len() - 1];
+    let c = if let Some(number) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        char::from_u32(u32::from_str_radix(number, 16).ok()?)?
+    } else if let Some(number) = name.strip_prefix('#') {
+        char::from_u32(number.parse().ok()?)?
+    } else {
+        match name {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            "nbsp" | "ensp" | "emsp" | "thinsp" => ' ',
+            "shy" | "zwj" | "zwnj" => '\u{200B}',
+            "ndash" => '–',
+            "mdash" => '—',
+            "hellip" => '…',
+            "laquo" => '«',
+            "raquo" => '»',
+            "bdquo" => '„',
+            "ldquo" => '“',
+            "rdquo" => '”',
+            "lsquo" => '‘',
+            "rsquo" => '’',
+            "euro" => '€',
+            "copy" => '©',
+            "auml" => 'ä',
+            "ouml" => 'ö',
+            "uuml" => 'ü',
+            "Auml" => 'Ä',
+            "Ouml" => 'Ö',
+            "Uuml" => 'Ü',
+            "szlig" => 'ß',
+            _ => return None,
+        }
+    };
+    // The cleanup steps before this stage already ran; do their work for the decoded character.
+    let decoded: String = c.to_string().nfkc().collect();
+    let decoded = CONTROL_CHAR_RE.replace_all(&decoded, "");
+    Some(
+        ZERO_WIDTH_RE
+            .replace_all(&decoded, "")
+            .replace('\u{00AD}', ""),
+    )
+}
+
+/// The host of an absolute web link, for `text (domain)`; `None` for other schemes and relative links.
+fn link_domain(target: &str) -> Option<String> {
+    let url = Url::parse(target.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https" | "ftp") {
+        return None;
+    }
+    url.host_str().map(|host| host.to_ascii_lowercase())
+}
+
+/// Link text with the domain in brackets; a text that is itself a URL gives way to the domain.
+fn link_text(text: &str, domain: Option<String>) -> String {
+    let text = if text.contains("://") { "" } else { text };
+    match (text.is_empty(), domain) {
+        (true, Some(domain)) => domain,
+        (false, Some(domain)) => format!("{} ({})", text, domain),
+        (_, None) => text.to_string(),
+    }
+}
@@ -0,0 +1,317 @@
+//! Typed model of the feedback payload the web clients write (`generic_page.js`,
+//! `firebase-feedback-client.js`), with per-field schema validation of decoded documents.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::fields::{clean_field, FieldProfile};
+use crate::lossless::{typed_tag, TIMESTAMP_TAG};
+
+/// `createdAtIso` from `Date.toISOString()` and decoded Firestore timestamps.
+static TIMESTAMP_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d{1,9})?(Z|[+-]\d{2}:\d{2})$")
+        .expect("valid regex")
+});
+
+const TOP_LEVEL_TEXT_FIELDS: &[&str] = &[
+    "comment",
+    "source",
+    "createdAtIso",
+    "createdAt",
+    "processedAt",
+    "processedBy",
+    "sanitizerVersion",
+    "exportPath",
+];
+/// Repo paths in the order the export and the rule pack policies look at them.
+const REPO_PATH_FIELDS: &[&str] = &["folderPath", "gamePath", "jsonPath"];
+
+/// One feedback document. Fields that failed validation are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedbackDocument {
+    pub comment: Option<String>,
+    /// `game_page` or `generic_page`.
+    pub source: Option<String>,
+    pub created_at_iso: Option<String>,
+    /// Server timestamp as an RFC 3339 string, also from lossless `$timestamp` values.
+    pub created_at: Option<String>,
+    pub context: Option<FeedbackContext>,
+    /// Set by `write_back` once the document was exported.
+    pub processed_at: Option<String>,
+    pub processed_by: Option<String>,
+    pub sanitizer_version: Option<String>,
+    pub export_path: Option<String>,
+    /// Context fields older clients wrote at the top level.
+    #[serde(flatten)]
+    pub legacy: FeedbackContext,
+}
+
+/// `buildFeedbackContext()` plus the page location.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedbackContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    /// `game` or `generic`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feedback_origin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location_href: Option<String>,
+}
+
+const CONTEXT_FIELDS: &[&str] = &[
+    "gameId",
+    "folder",
+    "folderPath",
+    "gameTitle",
+    "nodeId",
+    "feedbackOrigin",
+    "jsonPath",
+    "gamePath",
+    "jsonUrl",
+    "gameUrl",
+    "frameUrl",
+    "locationPath",
+    "locationHref",
+];
+
+impl FeedbackContext {
+    /// A field by its JSON name; `None` for unknown names.
+    pub fn field(&self, key: &str) -> Option<&str> {
+        let value = match key {
+            "gameId" => &self.game_id,
+            "folder" => &self.folder,
+            "folderPath" => &self.folder_path,
+            "gameTitle" => &self.game_title,
+            "nodeId" => &self.node_id,
+            "feedbackOrigin" => &self.feedback_origin,
+            "jsonPath" => &self.json_path,
+            "gamePath" => &self.game_path,
+            "jsonUrl" => &self.json_url,
+            "gameUrl" => &self.game_url,
+            "frameUrl" => &self.frame_url,
+            "locationPath" => &self.location_path,
+            "locationHref" => &self.location_href,
+            _ => return None,
+        };
+        value.as_deref()
+    }
+}
+
+impl FeedbackDocument {
+    /// The typed view of decoded document data; invalid fields are left out.
+    pub fn from_data(data: &Value) -> Self {
+        parse_feedback(data).0
+    }
+
+    /// `context` first, then the legacy top-level fields.
+    pub fn contexts(&self) -> [(&'static str, Option<&FeedbackContext>); 2] {
+        [
+            ("context.", self.context.as_ref()),
+            ("", Some(&self.legacy)),
+        ]
+    }
+
+    /// Trimmed, non-empty value of a context field, `context` before the top level.
+    pub fn context_field(&self, key: &str) -> Option<&str> {
+        self.contexts()
+            .into_iter()
+            .filter_map(|(_, context)| context?.field(key).map(str::trim))
+            .find(|value| !value.is_empty())
+    }
+
+    /// The first repo path (`folderPath`, `gamePath`, `jsonPath`).
+    pub fn repo_path(&self) -> Option<&str> {
+        REPO_PATH_FIELDS
+            .iter()
+            .find_map(|key| self.context_field(key))
+    }
+}
+
+/// Schema problems of one document. Field paths start at `data`, like the sanitizer reports.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaReport {
+    pub valid: bool,
+    pub errors: Vec<FieldError>,
+    /// Fields outside the model; their values are withheld by the field policy.
+    pub unknown_fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field_path: String,
+    /// `missing`, `empty`, `expected_string`, `expected_object` or `invalid_timestamp`.
+    pub problem: &'static str,
+    /// JSON type of the rejected value, for type errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found: Option<&'static str>,
+}
+
+impl FieldError {
+    fn new(field_path: String, problem: &'static str) -> Self {
+        FieldError {
+            field_path,
+            problem,
+            found: None,
+        }
+    }
+
+    fn wrong_type(field_path: String, problem: &'static str, value: &Value) -> Self {
+        FieldError {
+            found: Some(json_type(value)),
+            ..FieldError::new(field_path, problem)
+        }
+    }
+}
+
+/// Validates decoded document data and builds the typed document from the fields that passed.
+/// Type errors drop the field; `empty` and `invalid_timestamp` keep it.
+pub fn parse_feedback(data: &Value) -> (FeedbackDocument, SchemaReport) {
+    let mut report = SchemaReport::default();
+    let Some(object) = data.as_object() else {
+        report.errors.push(FieldError::wrong_type(
+            "data".to_string(),
+            "expected_object",
+            data,
+        ));
+        return (FeedbackDocument::default(), report);
+    };
+
+    let mut accepted = Map::new();
+    for (key, value) in object {
+        let path = format!("data.{}", report_key(key));
+        if key == "context" {
+            match value {
+                Value::Null => {}
+                Value::Object(context) => {
+                    let context = accept_text_fields(context, &path, CONTEXT_FIELDS, &mut report);
+                    accepted.insert(key.clone(), Value::Object(context));
+                }
+                other => report
+                    .errors
+                    .push(FieldError::wrong_type(path, "expected_object", other)),
+            }
+        } else if TOP_LEVEL_TEXT_FIELDS.contains(&key.as_str())
+            || CONTEXT_FIELDS.contains(&key.as_str())
+        {
+            let value = untyped_timestamp(value);
+            if accept_text(value, &path, &mut report) {
+                accepted.insert(key.clone(), value.clone());
+            }
+        } else {
+            report.unknown_fields.push(path);
+        }
+    }
+
+    match accepted.get("comment").and_then(Value::as_str) {
+        Some(comment) if comment.trim().is_empty() => {
+            report
+                .errors
+                .push(FieldError::new("data.comment".to_string(), "empty"));
+        }
+        Some(_) => {}
+        None if object.get("comment").is_none_or(Value::is_null) => {
+            report
+                .errors
+                .push(FieldError::new("data.comment".to_string(), "missing"));
+        }
+        None => {}
+    }
+    for key in ["createdAtIso", "createdAt", "processedAt"] {
+        if let Some(timestamp) = accepted.get(key).and_then(Value::as_str) {
+            if !TIMESTAMP_RE.is_match(timestamp) {
+                report.errors.push(FieldError::new(
+                    format!("data.{}", key),
+                    "invalid_timestamp",
+                ));
+            }
+        }
+    }
+
+    report.valid = report.errors.is_empty();
+    let document = serde_json::from_value(Value::Object(accepted)).unwrap_or_default();
+    (document, report)
+}
+
+fn accept_text_fields(
+    object: &Map<String, Value>,
+    path: &str,
+    known: &[&str],
+    report: &mut SchemaReport,
+) -> Map<String, Value> {
+    let mut accepted = Map::new();
+    for (key, value) in object {
+        let field_path = format!("{}.{}", path, report_key(key));
+        if !known.contains(&key.as_str()) {
+            report.unknown_fields.push(field_path);
+        } else if accept_text(value, &field_path, report) {
+            accepted.insert(key.clone(), value.clone());
+        }
+    }
+    accepted
+}
+
+/// The timestamp string of a lossless `{"$timestamp": ...}` value, anything else unchanged.
+fn untyped_timestamp(value: &Value) -> &Value {
+    match value.as_object() {
+        Some(map) if typed_tag(map) == Some(TIMESTAMP_TAG) => &map[TIMESTAMP_TAG],
+        _ => value,
+    }
+}
+
+/// `true` for strings; `null` counts as absent.
+fn accept_text(value: &Value, path: &str, report: &mut SchemaReport) -> bool {
+    match value {
+        Value::String(_) => true,
+        Value::Null => false,
+        other => {
+            report.errors.push(FieldError::wrong_type(
+                path.to_string(),
+                "expected_string",
+                other,
+            ));
+            false
+        }
+    }
+}
+
+/// Key names come from the client too; anything but an identifier is not repeated in reports.
+pub(crate) fn report_key(key: &str) -> String {
+    clean_field(FieldProfile::Identifier, key).value
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
@@ -0,0 +1,157 @@
+//! Offline mode: documents from a saved Firestore dump or an earlier output JSON.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+
+use crate::config::Settings;
+use crate::decode::{decode_raw_document_with, decoded_documents_from_output, string_field};
+use crate::report::{assemble_output_payload, BuildOutputResult};
+use crate::sanitize::CommentSanitizer;
+
+/// Documents loaded from disk instead of Firestore.
+#[derive(Debug)]
+pub enum OfflineInput {
+    /// Raw Firestore REST documents (`name`, `fields`, ...), e.g. a saved list page.
+    Raw {
+        project_id: String,
+        collection: Option<String>,
+        documents: Vec<Value>,
+    },
+    /// A previously written `feedback_all_games.json`.
+    Output(Value),
+}
+
+/// Loads documents from a file written by an earlier run.
+///
+/// Accepted shapes:
+/// - a Firestore list response page (`{"documents": [...], "nextPageToken": ...}`),
+///   also the `--save-raw` dump which adds `projectId` and `collection`
+/// - an array of such pages, or a plain array of raw Firestore documents
+/// - an output JSON written by this tool (documents carry `data` instead of `fields`)
+pub fn load_offline_input(path: &Path) -> Result<OfflineInput> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed to read input file {}", path.display()))?;
+    let parsed: Value = serde_json::from_str(&raw)
+        .with_context(|| format!("failed to parse input JSON from {}", path.display()))?;
+
+    let (documents, meta) = match &parsed {
+        Value::Object(root) => {
+            let Some(documents) = root.get("documents").and_then(Value::as_array) else {
+                bail!("{} has no `documents` array", path.display());
+            };
+            if is_output_payload(root, documents) {
+                return Ok(OfflineInput::Output(parsed));
+            }
+            (documents.clone(), Some(root))
+        }
+        Value::Array(items) => {
+            let mut documents = Vec::new();
+            for item in items {
+                match item.get("documents").and_then(Value::as_array) {
+                    Some(page_documents) => documents.extend(page_documents.iter().cloned()),
+                    None if item.get("name").is_some() || item.get("fields").is_some() => {
+                        documents.push(item.clone())
+                    }
+                    None => {}
+                }
+            }
+            (documents, None)
+        }
+        _ => bail!(
+            "{} is neither a Firestore dump nor an output JSON",
+            path.display()
+        ),
+    };
+
+    let project_id = meta
+        .and_then(|root| root.get("projectId"))
+        .and_then(Value::as_str)
+        .map(|id| id.to_string())
+        .or_else(|| {
+            documents
+                .iter()
+                .filter_map(|doc| doc.get("name").and_then(Value::as_str))
+                .find_map(project_id_from_document_name)
+        })
+        .unwrap_or_default();
+    let collection = meta
+        .and_then(|root| root.get("collection"))
+        .and_then(Value::as_str)
+        .map(|c| c.to_string());
+
+    Ok(OfflineInput::Raw {
+        project_id,
+        collection,
+        documents,
+    })
+}
+
+fn is_output_payload(root: &Map<String, Value>, documents: &[Value]) -> bool {
+    match documents.first() {
+        Some(first) => first.get("data").is_some() && first.get("fields").is_none(),
+        None => root.contains_key("security"),
+    }
+}
+
+fn project_id_from_document_name(name: &str) -> Option<String> {
+    let mut parts = name.split('/');
+    while let Some(part) = parts.next() {
+        if part == "projects" {
+            return parts
+                .next()
+                .filter(|id| !id.is_empty())
+                .map(|id| id.to_string());
+        }
+    }
+    None
+}
+
+pub fn build_from_offline_input(
+    input: OfflineInput,
+    settings: &Settings,
+    sanitizer: &dyn CommentSanitizer,
+) -> BuildOutputResult {
+    match input {
+        OfflineInput::Raw {
+            project_id,
+            collection,
+            documents,
+        } => {
+            let decoded = documents
+                .iter()
+                .map(|doc| decode_raw_document_with(doc, settings.decode_mode()))
+                .collect();
+            assemble_output_payload(
+                &project_id,
+                collection.as_deref().unwrap_or(&settings.collection),
+                decoded,
+                settings,
+                sanitizer,
+            )
+        }
+        OfflineInput::Output(existing) => {
+            let project_id = string_field(&existing, "projectId");
+            let collection = existing
+                .get("collection")
+                .and_then(Value::as_str)
+                .unwrap_or(&settings.collection)
+                .to_string();
+
+            let documents = decoded_documents_from_output(&existing);
+            let mut build_result =
+                assemble_output_payload(&project_id, &collection, documents, settings, sanitizer);
+            // Re-sanitizing sanitized text finds nothing worth reviewing or quarantining.
+            build_result.reviews = None;
+            build_result.withheld = None;
+            if let Some(learning_export) = existing.get("learningExport") {
+                if let Some(root_obj) = build_result.payload.as_object_mut() {
+                    root_obj.insert("learningExport".to_string(), learning_export.clone());
+                }
+            }
+            build_result
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Redaction of personal data in comments before they reach the learning folders.
+//!
+//! Each category has its own token; IBANs (mod-97), card numbers (Luhn) and IP addresses are
+//! validated, so numbers that only look similar stay.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::sanitize::RuleMatch;
+
+static EMAIL_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9](?:[a-z0-9-]*[a-z0-9])?(?:\.[a-z0-9](?:[a-z0-9-]*[a-z0-9])?)*\.[a-z]{2,}\b")
+        .expect("valid regex")
+});
+static IBAN_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b").expect("valid regex")
+});
+/// The optional `+` prefix only lets [`is_valid_card_number`] skip international phone numbers.
+static CARD_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:\+ ?)?\b\d(?:[ -]?\d){12,18}\b").expect("valid regex"));
+/// Octets are bounded to 0-255. Longer dotted runs (`1.2.0.1.5`) and a leading `v`/`Version`/`Build`
+/// fall into the match, so the address check rejects version strings as a whole.
+static IPV4_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?x)
+        (^|[^\w.])
+        (?i:(?:v|version|build)\ ?:?\ ?)?
+        (?:(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)\.){3}(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)
+        (?:\.\d+)*\b",
+    )
+    .expect("valid regex")
+});
+static IPV6_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)(?:[0-9a-f]{0,4}:){2,7}[0-9a-f]{0,4}").expect("valid regex"));
+static PHONE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:(?:\+|\b00)\d{1,3}[ /-]?(?:\(0\) ?)?|\b0)\d{2,5}(?:[ /-]?\d{2,}){1,4}\b")
+        .expect("valid regex")
+});
+static ADDRESS_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?x)
+        \b(?:
+            \p{Lu}[\p{L}-]*?(?:stra(?:ß|ss)e|str\.|weg|gasse|platz|allee|ring|damm|ufer|chaussee)
+            # `Berliner Allee`; the `-er` keeps `Auf Platz 3` out
+            | \p{Lu}\p{Ll}{3,}er\ (?:Stra(?:ß|ss)e|Str\.|Weg|Gasse|Platz|Allee|Ring|Damm|Ufer|Chaussee)
+        )
+        \ +\d{1,4}\ ?[a-z]?\b
+        (?:,?\ +\d{5}\ +\p{Lu}\p{Ll}+(?:[ -]\p{Lu}\p{Ll}+)?)?",
+    )
+    .expect("valid regex")
+});
+static NAME_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"((?i:\b(?:ich hei(?:ß|ss)e|mein name ist|my name is)\b|\b(?:sch(?:ü|ue)ler(?:in)?|name)\ ?:)\ +)\p{Lu}\p{Ll}+(?:[ -]\p{Lu}\p{Ll}+)?",
+    )
+    .expect("valid regex")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PiiCategory {
+    Email,
+    Iban,
+    PaymentCard,
+    IpAddress,
+    Phone,
+    Address,
+    Name,
+}
+
+impl PiiCategory {
+    /// Used in the reason code `pii_redacted:<category>`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PiiCategory::Email => "email",
+            PiiCategory::Iban => "iban",
+            PiiCategory::PaymentCard => "payment_card",
+            PiiCategory::IpAddress => "ip_address",
+            PiiCategory::Phone => "phone",
+            PiiCategory::Address => "address",
+            PiiCategory::Name => "name",
+        }
+    }
+
+    pub fn token(self) -> &'static str {
+        match self {
+            PiiCategory::Email => "[email-redacted]",
+            PiiCategory::Iban => "[iban-redacted]",
+            PiiCategory::PaymentCard => "[card-redacted]",
+            PiiCategory::IpAddress => "[ip-redacted]",
+            PiiCategory::Phone => "[phone-redacted]",
+            PiiCategory::Address => "[address-redacted]",
+            PiiCategory::Name => "[name-redacted]",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PiiRedaction {
+    pub text: String,
+    /// In the order they were applied, each once.
+    pub categories: Vec<PiiCategory>,
+    /// Offsets refer to the text before the category's own pass.
+    pub matches: Vec<RuleMatch>,
+}
+
+/// One redaction pass: a category, its pattern and the check a match has to pass.
+struct PiiPass {
+    category: PiiCategory,
+    regex: &'static Lazy<Regex>,
+    is_valid: fn(&str) -> bool,
+}
+
+/// Emails and IBANs go first because they contain digit runs of their own.
+static PASSES: [PiiPass; 8] = [
+    PiiPass {
+        category: PiiCategory::Email,
+        regex: &EMAIL_RE,
+        is_valid: |_| true,
+    },
+    PiiPass {
+        category: PiiCategory::Iban,
+        regex: &IBAN_RE,
+        is_valid: is_valid_iban,
+    },
+    PiiPass {
+        category: PiiCategory::PaymentCard,
+        regex: &CARD_RE,
+        is_valid: is_valid_card_number,
+    },
+    PiiPass {
+        category: PiiCategory::IpAddress,
+        regex: &IPV4_RE,
+        is_valid: |m| m.parse::<Ipv4Addr>().is_ok(),
+    },
+    PiiPass {
+        category: PiiCategory::IpAddress,
+        regex: &IPV6_RE,
+        is_valid: |m| {
+            m.chars().filter(char::is_ascii_hexdigit).count() >= 4 && m.parse::<Ipv6Addr>().is_ok()
+        },
+    },
+    PiiPass {
+        category: PiiCategory::Phone,
+        regex: &PHONE_RE,
+        is_valid: |m| (7..=15).contains(&m.chars().filter(char::is_ascii_digit).count()),
+    },
+    PiiPass {
+        category: PiiCategory::Address,
+        regex: &ADDRESS_RE,
+        is_valid: |_| true,
+    },
+    PiiPass {
+        category: PiiCategory::Name,
+        regex: &NAME_RE,
+        is_valid: |_| true,
+    },
+];
+
+impl PiiPass {
+    /// Byte ranges of the valid matches, without the lead-in (group 1) of names and IPv4 addresses.
+    fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex
+            .captures_iter(text)
+            .map(|caps| {
+                let whole = caps.get(0).expect("whole match");
+                let start = caps.get(1).map_or(whole.start(), |lead_in| lead_in.end());
+                start..whole.end()
+            })
+            .filter(|range| (self.is_valid)(&text[range.clone()]))
+            .collect()
+    }
+}
+
+/// Replaces personal data in `text`, one category after the other (see [`PASSES`]).
+pub fn redact_pii(text: &str) -> PiiRedaction {
+    let mut found = Vec::new();
+    let mut matches = Vec::new();
+    let mut redacted = text.to_string();
+
+    for pass in &PASSES {
+        let ranges = pass.find(&redacted);
+        if ranges.is_empty() {
+            continue;
+        }
+        let mut replaced = String::with_capacity(redacted.len());
+        let mut last = 0;
+        for range in ranges {
+            matches.push(RuleMatch::locate(
+                &redacted,
+                range.clone(),
+                pass.category.as_str(),
+                "pii_redacted",
+                "text",
+            ));
+            replaced.push_str(&redacted[last..range.start]);
+            replaced.push_str(pass.category.token());
+            last = range.end;
+        }
+        replaced.push_str(&redacted[last..]);
+        redacted = replaced;
+        if !found.contains(&pass.category) {
+            found.push(pass.category);
+        }
+    }
+
+    PiiRedaction {
+        text: redacted,
+        categories: found,
+        matches,
+    }
+}
+
+/// Where [`redact_pii`] would redact, as byte ranges of `text` itself, in text order.
+pub fn find_pii(text: &str) -> Vec<(Range<usize>, PiiCategory)> {
+    // Found data is blanked with `#`, which no pattern matches, so offsets stay valid and later
+    // passes see the same word boundaries as next to a token.
+    let mut blanked = text.to_string();
+    let mut spans = Vec::new();
+    for pass in &PASSES {
+        for range in pass.find(&blanked) {
+            blanked.replace_range(range.clone(), &"#".repeat(range.len()));
+            spans.push((range, pass.category));
+        }
+    }
+    spans.sort_by_key(|(range, _)| range.start);
+    spans
+}
+
+/// `text` with every datum [`find_pii`] finds replaced by its token, and `range` moved onto the
+/// result. A range boundary inside a datum moves to the edge of its token, so the datum is either
+/// fully inside or fully outside the range.
+pub fn mask_pii(text: &str, range: Range<usize>) -> (String, Range<usize>) {
+    let mut masked = String::with_capacity(text.len());
+    let (mut start, mut end) = (None, None);
+    let mut last = 0;
+    for (span, category) in find_pii(text) {
+        if start.is_none() && range.start < span.end {
+            start = Some(masked.len() + range.start.saturating_sub(last).min(span.start - last));
+        }
+        if end.is_none() && range.end <= span.start {
+            end = Some(masked.len() + (range.end - last));
+        }
+        masked.push_str(&text[last..span.start]);
+        masked.push_str(category.token());
+        if end.is_none() && range.end < span.end {
+            end = Some(masked.len());
+        }
+        last = span.end;
+    }
+    let start = start.unwrap_or(masked.len() + range.start.saturating_sub(last));
+    let end = end.unwrap_or(masked.len() + range.end.saturating_sub(last));
+    masked.push_str(&text[last..]);
+    (masked, start..end.max(start))
+}
+
+/// ISO 13616: move the first four characters to the end, letters to 10..35, remainder 1 mod 97.
+fn is_valid_iban(candidate: &str) -> bool {
+    let compact: String = candidate.chars().filter(|c| !c.is_whitespace()).collect();
+    if !(15..=34).contains(&compact.len()) {
+        return false;
+    }
+
+    let (head, tail) = compact.split_at(4);
+    let mut remainder = 0u32;
+    for c in tail.chars().chain(head.chars()) {
+        let Some(value) = c.to_digit(36) else {
+            return false;
+        };
+        remainder = if value < 10 {
+            (remainder * 10 + value) % 97
+        } else {
+            (remainder * 100 + value) % 97
+        };
+    }
+    remainder == 1
+}
+
+fn is_valid_card_number(candidate: &str) -> bool {
+    if candidate.starts_with('+') {
+        return false;
+    }
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) || digits[0] == 0 {
+        return false;
+    }
+
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, &digit)| {
+            if index % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
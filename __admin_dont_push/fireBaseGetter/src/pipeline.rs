@@ -0,0 +1,538 @@
+//! The subcommands as one pipeline: source -> sanitizer -> sink, plus output JSON and protocol.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::archive::{
+    archive_bytes, archive_file_name, plan_archive_copies, plan_archive_deletes, verify_archive,
+    verify_archive_copies, ArchiveRequest, ArchiveSummary, ArchivedDocument,
+};
+use crate::config::{resolve_against, RunOptions};
+use crate::export::{
+    FeedbackSink, LearningExportSummary, LearningFolderResolver, LearningFolderSink,
+};
+use crate::firestore::{
+    commit_in_batches, write_document_name, DocumentWriter, FeedbackSource, FirestoreSource,
+    FirestoreWriter, RawDocuments, MAX_WRITES_PER_COMMIT,
+};
+use crate::offline::{build_from_offline_input, load_offline_input, OfflineInput};
+use crate::quarantine::{
+    ensure_git_ignored, is_inside, key_from_env, QuarantineStore, ReviewStatus, QUARANTINE_KEY_ENV,
+};
+use crate::report::{
+    attach_learning_export_summary, build_output_payload, finish_output_payload,
+    read_output_payload, read_review_documents, replace_output_documents,
+    write_feedback_protocol_file, write_output_payload, write_private_file, write_review_file,
+    BuildOutputResult,
+};
+use crate::sanitize::{CommentSanitizer, RuleSanitizer};
+use crate::sync::{
+    incremental_base, merge_mapped_documents, read_sync_state, write_sync_state, SyncState,
+};
+use crate::writeback::{plan_processed_writes, WriteBackSummary};
+
+/// Runs the stages for one set of [`RunOptions`].
+///
+/// `Pipeline::new` uses the default stages (Firestore, rule pack, learning folders); the
+/// `with_*` methods swap single stages, e.g. a fixed [`crate::RawDocuments`] source in tests.
+pub struct Pipeline<'a> {
+    options: &'a RunOptions,
+    source: Box<dyn FeedbackSource + 'a>,
+    sanitizer: Box<dyn CommentSanitizer + 'a>,
+    sink: Box<dyn FeedbackSink + 'a>,
+    /// Only used with `write_back`.
+    writer: Box<dyn DocumentWriter + 'a>,
+}
+
+/// What `run_all`, `run_fetch`, `run_sanitize` and `run_export_learnings` did, for the caller to report.
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    /// Documents in the output JSON.
+    pub documents: usize,
+    /// The file read instead of Firestore (`--input` or the previous output JSON).
+    pub source_path: Option<PathBuf>,
+    pub incremental: Option<IncrementalSummary>,
+    pub quarantine: QuarantineSummary,
+    pub export: Option<LearningExportSummary>,
+    /// Only with `write_back`.
+    pub write_back: Option<WriteBackSummary>,
+}
+
+/// Documents fetched by an incremental run and merged into the previous output.
+#[derive(Debug)]
+pub struct IncrementalSummary {
+    pub fetched: usize,
+    pub since: String,
+}
+
+#[derive(Debug, Default)]
+pub struct QuarantineSummary {
+    pub added: usize,
+    pub released: usize,
+    /// Entries still waiting for a review decision.
+    pub pending: usize,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Fails when the configured rule pack cannot be loaded or the export roots cannot be resolved.
+    pub fn new(options: &'a RunOptions) -> Result<Self> {
+        Ok(Pipeline {
+            options,
+            source: Box::new(FirestoreSource::from_options(options)),
+            sanitizer: Box::new(RuleSanitizer::from_options(options)?.with_learning_folders(
+                LearningFolderResolver::new(&options.repo_root, &options.settings)?,
+            )),
+            sink: Box::new(LearningFolderSink::from_options(options)),
+            writer: Box::new(FirestoreWriter::from_options(options)),
+        })
+    }
+
+    pub fn with_source(mut self, source: impl FeedbackSource + 'a) -> Self {
+        self.source = Box::new(source);
+        self
+    }
+
+    pub fn with_sanitizer(mut self, sanitizer: impl CommentSanitizer + 'a) -> Self {
+        self.sanitizer = Box::new(sanitizer);
+        self
+    }
+
+    pub fn with_sink(mut self, sink: impl FeedbackSink + 'a) -> Self {
+        self.sink = Box::new(sink);
+        self
+    }
+
+    pub fn with_writer(mut self, writer: impl DocumentWriter + 'a) -> Self {
+        self.writer = Box::new(writer);
+        self
+    }
+
+    pub fn run_all(&mut self) -> Result<RunSummary> {
+        let options = self.options;
+        let (mut build_result, sync_state, incremental) = self.acquire_output_payload()?;
+        let quarantine = self.quarantine(&mut build_result)?;
+        self.write_review(&build_result)?;
+        let export_summary = self.sink.export(&build_result.mapped_documents)?;
+        write_feedback_protocol_file(
+            &options.protocol_path,
+            &options.repo_root,
+            &export_summary.written_paths,
+        )?;
+        attach_learning_export_summary(&mut build_result.payload, options, &export_summary);
+        let write_back = self.write_back(
+            &build_result.mapped_documents,
+            &export_summary,
+            &mut build_result.payload,
+        )?;
+        write_output_payload(&options.output_path, &build_result.payload)?;
+        if let Some(state) = sync_state {
+            write_sync_state(&options.state_path, &state)?;
+        }
+
+        Ok(RunSummary {
+            documents: build_result.mapped_documents.len(),
+            source_path: options.input_path.clone(),
+            incremental,
+            quarantine,
+            export: Some(export_summary),
+            write_back,
+        })
+    }
+
+    pub fn run_fetch(&mut self) -> Result<RunSummary> {
+        let options = self.options;
+        let (mut build_result, sync_state, incremental) = self.acquire_output_payload()?;
+        let quarantine = self.quarantine(&mut build_result)?;
+        self.write_review(&build_result)?;
+        write_output_payload(&options.output_path, &build_result.payload)?;
+        if let Some(state) = sync_state {
+            write_sync_state(&options.state_path, &state)?;
+        }
+
+        Ok(RunSummary {
+            documents: build_result.mapped_documents.len(),
+            source_path: options.input_path.clone(),
+            incremental,
+            quarantine,
+            ..RunSummary::default()
+        })
+    }
+
+    pub fn run_sanitize(&mut self) -> Result<RunSummary> {
+        let options = self.options;
+        let source_path = options
+            .input_path
+            .as_deref()
+            .unwrap_or(&options.output_path);
+        let input = load_offline_input(source_path)?;
+        let mut build_result =
+            build_from_offline_input(input, &options.settings, self.sanitizer.as_ref());
+        let quarantine = self.quarantine(&mut build_result)?;
+        self.write_review(&build_result)?;
+        write_output_payload(&options.output_path, &build_result.payload)?;
+
+        Ok(RunSummary {
+            documents: build_result.mapped_documents.len(),
+            source_path: Some(source_path.to_path_buf()),
+            quarantine,
+            ..RunSummary::default()
+        })
+    }
+
+    pub fn run_export_learnings(&mut self) -> Result<RunSummary> {
+        let options = self.options;
+        let source_path = options
+            .input_path
+            .as_deref()
+            .unwrap_or(&options.output_path);
+        let mut quarantine = QuarantineSummary::default();
+        let (mut payload, mapped_documents) = match load_offline_input(source_path)? {
+            OfflineInput::Output(mut payload) => {
+                let mut mapped_documents = payload
+                    .get("documents")
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default();
+                if self
+                    .open_quarantine()?
+                    .release_approved(&mut mapped_documents)
+                    > 0
+                {
+                    replace_output_documents(&mut payload, &mapped_documents);
+                }
+                (payload, mapped_documents)
+            }
+            raw @ OfflineInput::Raw { .. } => {
+                let mut build_result =
+                    build_from_offline_input(raw, &options.settings, self.sanitizer.as_ref());
+                quarantine = self.quarantine(&mut build_result)?;
+                self.write_review(&build_result)?;
+                (build_result.payload, build_result.mapped_documents)
+            }
+        };
+
+        let export_summary = self.sink.export(&mapped_documents)?;
+        attach_learning_export_summary(&mut payload, options, &export_summary);
+        let write_back = self.write_back(&mapped_documents, &export_summary, &mut payload)?;
+        write_output_payload(&options.output_path, &payload)?;
+
+        Ok(RunSummary {
+            documents: mapped_documents.len(),
+            source_path: Some(source_path.to_path_buf()),
+            quarantine,
+            export: Some(export_summary),
+            write_back,
+            ..RunSummary::default()
+        })
+    }
+
+    /// Returns the number of protocol entries written.
+    pub fn run_protocol(&mut self) -> Result<usize> {
+        let options = self.options;
+        let payload = read_output_payload(&options.output_path)?;
+        let Some(written) = payload
+            .get("learningExport")
+            .and_then(|e| e.get("writtenPaths"))
+            .and_then(Value::as_array)
+        else {
+            bail!(
+                "{} has no learningExport section; run `export-learnings` first",
+                options.output_path.display()
+            );
+        };
+
+        let written_paths: Vec<PathBuf> = written
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|p| resolve_against(&options.repo_root, PathBuf::from(p)))
+            .collect();
+        write_feedback_protocol_file(&options.protocol_path, &options.repo_root, &written_paths)?;
+        Ok(written_paths.len())
+    }
+
+    /// Archives the selected documents to a local file, verifies it, then copies them to
+    /// `archive_collection` and, with `confirm_delete`, deletes them from the live collection.
+    pub fn run_archive(&mut self, request: &ArchiveRequest) -> Result<ArchiveSummary> {
+        let options = self.options;
+        if !request.processed && request.older_than_days.is_none() {
+            bail!("archive needs a selection: --processed and/or --older-than-days <n>");
+        }
+        let fetched = match options.input_path.as_deref() {
+            Some(input_path) => match load_offline_input(input_path)? {
+                OfflineInput::Raw {
+                    project_id, documents, ..
+                } => RawDocuments { project_id, documents },
+                OfflineInput::Output(_) => bail!(
+                    "{} is an output JSON; archive needs raw Firestore documents (a `--save-raw` dump)",
+                    input_path.display()
+                ),
+            },
+            None => self.source.fetch(None)?,
+        };
+
+        let now_unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let documents: Vec<ArchivedDocument> = request
+            .select(&fetched.documents, now_unix)
+            .into_iter()
+            .map(ArchivedDocument::from_raw)
+            .collect();
+        let mut summary = ArchiveSummary {
+            fetched: fetched.documents.len(),
+            selected: documents
+                .iter()
+                .map(|document| document.name.clone())
+                .collect(),
+            dry_run: options.dry_run,
+            ..ArchiveSummary::default()
+        };
+        if documents.is_empty() {
+            return Ok(summary);
+        }
+        let key = key_from_env()?;
+        let archive_path = options.archive_dir.join(archive_file_name(
+            &options.settings.collection,
+            now_unix,
+            key.is_some(),
+        ));
+        if key.is_none() && is_inside(&archive_path, &options.repo_root) {
+            bail!(
+                "archive {} would lie inside the repo unencrypted; move `archive_dir` outside or set {} to encrypt it",
+                archive_path.display(),
+                QUARANTINE_KEY_ENV
+            );
+        }
+        summary.archive_path = Some(archive_path.clone());
+        let archive_collection = options.settings.archive_collection.as_deref();
+
+        if options.dry_run {
+            let mut writes = match archive_collection {
+                Some(collection) => plan_archive_copies(&documents, collection)?,
+                None => Vec::new(),
+            };
+            if request.confirm_delete {
+                writes.extend(plan_archive_deletes(&documents));
+            }
+            summary.planned_commits = writes
+                .chunks(MAX_WRITES_PER_COMMIT)
+                .map(|chunk| json!({ "writes": chunk }))
+                .collect();
+            return Ok(summary);
+        }
+
+        write_private_file(&archive_path, &archive_bytes(&documents, key.as_ref())?)
+            .with_context(|| format!("failed to write archive {}", archive_path.display()))?;
+        let archived = verify_archive(&archive_path, &documents, key.as_ref())
+            .context("archive verification failed; nothing was copied or deleted")?;
+        summary.archived = archived.len();
+
+        if let Some(collection) = archive_collection {
+            let copies = plan_archive_copies(&archived, collection)?;
+            let commits = commit_in_batches(self.writer.as_ref(), &copies)
+                .context("copying to the archive collection failed; nothing was deleted")?;
+            summary.copied_to = Some((collection.to_string(), commits));
+            let names: Vec<String> = copies
+                .iter()
+                .filter_map(write_document_name)
+                .map(str::to_string)
+                .collect();
+            let found = self
+                .writer
+                .get_documents(&names)
+                .context("reading the archive copies back failed; nothing was deleted")?;
+            verify_archive_copies(&archived, collection, &found)
+                .context("archive copies do not match the archive; nothing was deleted")?;
+        }
+
+        if !request.confirm_delete {
+            return Ok(summary);
+        }
+        let deletes = plan_archive_deletes(&archived);
+        let commits = commit_in_batches(self.writer.as_ref(), &deletes).with_context(|| {
+            format!(
+                "deleting failed; {} still holds every document",
+                archive_path.display()
+            )
+        })?;
+        summary.delete_commits = Some(commits);
+        Ok(summary)
+    }
+
+    /// With `write_back`, marks every exported document as processed, or only plans the writes on a dry run.
+    fn write_back(
+        &self,
+        mapped_documents: &[Value],
+        export_summary: &LearningExportSummary,
+        payload: &mut Value,
+    ) -> Result<Option<WriteBackSummary>> {
+        let options = self.options;
+        if !options.settings.write_back {
+            return Ok(None);
+        }
+        let plan = plan_processed_writes(
+            mapped_documents,
+            &export_summary.routes,
+            &options.settings.processed_by,
+        )?;
+        let mut summary = WriteBackSummary {
+            dry_run: options.dry_run,
+            planned_writes: plan.writes.len(),
+            already_processed: plan.already_processed,
+            ..WriteBackSummary::default()
+        };
+
+        if options.dry_run {
+            summary.planned_commits = plan
+                .writes
+                .chunks(MAX_WRITES_PER_COMMIT)
+                .map(|chunk| json!({ "writes": chunk }))
+                .collect();
+        } else {
+            summary.commits = commit_in_batches(self.writer.as_ref(), &plan.writes)?;
+            summary.committed_writes = plan.writes.len();
+        }
+
+        if let Some(root_obj) = payload.as_object_mut() {
+            root_obj.insert("writeBack".to_string(), json!(summary));
+        }
+        Ok(Some(summary))
+    }
+
+    fn open_quarantine(&self) -> Result<QuarantineStore> {
+        QuarantineStore::open(&self.options.quarantine_path, &self.options.repo_root)
+    }
+
+    /// Stores newly blocked comments in the quarantine and puts approved ones back into the
+    /// documents, so they are exported like any other feedback.
+    fn quarantine(&self, build_result: &mut BuildOutputResult) -> Result<QuarantineSummary> {
+        let mut store = self.open_quarantine()?;
+        let added = match &build_result.withheld {
+            Some(withheld) if !withheld.is_empty() => {
+                let added = store.quarantine(withheld, self.sanitizer.version());
+                store.save()?;
+                added
+            }
+            _ => 0,
+        };
+        let released = store.release_approved(&mut build_result.mapped_documents);
+        if released > 0 {
+            replace_output_documents(&mut build_result.payload, &build_result.mapped_documents);
+        }
+
+        let pending = store
+            .entries()
+            .iter()
+            .filter(|entry| entry.status == ReviewStatus::Pending)
+            .count();
+        Ok(QuarantineSummary {
+            added,
+            released,
+            pending,
+        })
+    }
+
+    /// Writes the restricted review output when the comments were sanitized from source text.
+    /// Refuses a review file inside the repo that git would not ignore.
+    fn write_review(&self, build_result: &BuildOutputResult) -> Result<()> {
+        let Some(reviews) = &build_result.reviews else {
+            return Ok(());
+        };
+        let options = self.options;
+        ensure_git_ignored(&options.review_path, &options.repo_root, "review_path")?;
+        write_review_file(&options.review_path, self.sanitizer.version(), reviews)
+    }
+
+    /// The output payload, the sync state to store after the run and, for an incremental run,
+    /// what was merged.
+    fn acquire_output_payload(
+        &self,
+    ) -> Result<(
+        BuildOutputResult,
+        Option<SyncState>,
+        Option<IncrementalSummary>,
+    )> {
+        let options = self.options;
+        let sanitizer = self.sanitizer.as_ref();
+        if let Some(input_path) = options.input_path.as_deref() {
+            let input = load_offline_input(input_path)?;
+            return Ok((
+                build_from_offline_input(input, &options.settings, sanitizer),
+                None,
+                None,
+            ));
+        }
+
+        let previous_state = if options.settings.incremental {
+            read_sync_state(&options.state_path)?
+        } else {
+            None
+        };
+        let incremental_base = previous_state
+            .as_ref()
+            .and_then(|state| incremental_base(options, state));
+
+        let full_sync = incremental_base.is_none();
+        let since = incremental_base
+            .as_ref()
+            .map(|(state, _)| state.newest_watermark.as_str());
+        let fetched = self.source.fetch(since)?;
+        let project_id = fetched.project_id;
+        let documents = fetched.documents;
+        if let Some(save_raw_path) = options.save_raw_path.as_deref() {
+            // The dump holds every comment unsanitized, like the review file.
+            ensure_git_ignored(save_raw_path, &options.repo_root, "--save-raw")?;
+            write_output_payload(
+                save_raw_path,
+                &json!({
+                    "projectId": project_id,
+                    "collection": options.settings.collection,
+                    "documents": documents
+                }),
+            )?;
+        }
+
+        let mut incremental = None;
+        let build_result = match incremental_base {
+            Some((_, existing)) => {
+                incremental = Some(IncrementalSummary {
+                    fetched: documents.len(),
+                    since: since.unwrap_or_default().to_string(),
+                });
+                let existing_reviews = read_review_documents(&options.review_path)?;
+                let merged = merge_mapped_documents(
+                    existing,
+                    existing_reviews,
+                    &documents,
+                    options.settings.decode_mode(),
+                    sanitizer,
+                );
+                let mut build_result = finish_output_payload(
+                    &project_id,
+                    &options.settings.collection,
+                    merged.documents,
+                    &options.settings,
+                    sanitizer,
+                );
+                build_result.reviews = Some(merged.reviews);
+                build_result.withheld = Some(merged.withheld);
+                build_result
+            }
+            None => build_output_payload(&project_id, &documents, &options.settings, sanitizer),
+        };
+
+        let next_state = SyncState::from_fetched_documents(
+            &project_id,
+            &options.settings.collection,
+            &options.settings.watermark_field,
+            &documents,
+            previous_state.as_ref(),
+            full_sync,
+        );
+        Ok((build_result, Some(next_state), incremental))
+    }
+}
@@ -0,0 +1,467 @@
+//! Quarantine for blocked comments: the originals are kept outside the repo (or encrypted),
+//! reviewed by hand with the `review` subcommand, and approved ones are released into the
+//! export on the next run.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::report::write_private_file;
+use crate::sanitize::{Disposition, BLOCKED_COMMENT_TOKEN};
+
+/// 64 hex characters (AES-256-GCM key). Only read from the environment, never from the config file.
+pub const QUARANTINE_KEY_ENV: &str = "FIREBASE_GETTER_QUARANTINE_KEY";
+const ENCRYPTED_MAGIC: &[u8] = b"FBGQ1\n";
+const ENCRYPTION_AAD: &[u8] = b"fireBaseGetter quarantine v1";
+/// Binds encrypted `archive` files to their purpose; same key and file layout as the quarantine.
+pub(crate) const ARCHIVE_ENCRYPTION_AAD: &[u8] = b"fireBaseGetter archive v1";
+const QUARANTINE_FORMAT: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    Pending,
+    Approved,
+    Rejected,
+    /// Hard-blocked by a `block` rule or the block score: kept for inspection, never released.
+    Blocked,
+}
+
+impl ReviewStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Rejected => "rejected",
+            ReviewStatus::Blocked => "blocked",
+        }
+    }
+}
+
+/// A quarantined or blocked comment field as the sanitizer saw it.
+#[derive(Debug, Clone)]
+pub struct WithheldComment {
+    pub document_id: String,
+    pub document_name: String,
+    pub field_path: String,
+    /// The comment as stored in Firestore.
+    pub original: String,
+    /// What the block token replaced, i.e. what an approval releases.
+    pub withheld: String,
+    pub reasons: Vec<String>,
+    pub score: u32,
+    /// `Quarantine` or `Block`; blocked entries cannot be approved.
+    pub disposition: Disposition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantineEntry {
+    /// `<document id>/<field path>`, e.g. `abc123/data.comment`.
+    pub id: String,
+    pub document_id: String,
+    pub document_name: String,
+    pub field_path: String,
+    pub original: String,
+    pub withheld: String,
+    pub reasons: Vec<String>,
+    pub score: u32,
+    pub sanitizer_version: String,
+    pub quarantined_at_unix: u64,
+    pub status: ReviewStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewed_at_unix: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QuarantineFile {
+    format: u32,
+    entries: Vec<QuarantineEntry>,
+}
+
+pub struct QuarantineStore {
+    path: PathBuf,
+    /// The store may only be written unencrypted when it lies outside the repo.
+    inside_repo: bool,
+    key: Option<LessSafeKey>,
+    entries: Vec<QuarantineEntry>,
+}
+
+impl QuarantineStore {
+    /// Opens the store at `path`; a missing file is an empty store.
+    pub fn open(path: &Path, repo_root: &Path) -> Result<Self> {
+        let key = key_from_env()?;
+        let inside_repo = is_inside(path, repo_root);
+
+        let entries = if path.is_file() {
+            let raw = fs::read(path)
+                .with_context(|| format!("failed to read quarantine {}", path.display()))?;
+            let plain = if is_encrypted(&raw) {
+                let Some(key) = key.as_ref() else {
+                    bail!(
+                        "quarantine {} is encrypted; set {} to read it",
+                        path.display(),
+                        QUARANTINE_KEY_ENV
+                    );
+                };
+                decrypt(key, ENCRYPTION_AAD, &raw)
+                    .with_context(|| format!("failed to decrypt quarantine {}", path.display()))?
+            } else {
+                raw
+            };
+            let file: QuarantineFile = serde_json::from_slice(&plain)
+                .with_context(|| format!("failed to parse quarantine {}", path.display()))?;
+            if file.format != QUARANTINE_FORMAT {
+                bail!(
+                    "quarantine {} has format {}, supported is {}",
+                    path.display(),
+                    file.format,
+                    QUARANTINE_FORMAT
+                );
+            }
+            file.entries
+        } else {
+            Vec::new()
+        };
+
+        Ok(QuarantineStore {
+            path: path.to_path_buf(),
+            inside_repo,
+            key,
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn entries(&self) -> &[QuarantineEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, id: &str) -> Option<&QuarantineEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Adds new withheld comments, quarantined ones as `pending` and hard-blocked ones as `blocked`.
+    /// An entry whose original text changed starts over; unchanged ones keep their decision unless
+    /// the disposition moved between quarantine and block. Returns the number of new or reset entries.
+    pub fn quarantine(&mut self, withheld: &[WithheldComment], sanitizer_version: &str) -> usize {
+        let now = now_unix();
+        let mut added = 0usize;
+        for comment in withheld {
+            let id = format!("{}/{}", comment.document_id, comment.field_path);
+            let status = if comment.disposition == Disposition::Block {
+                ReviewStatus::Blocked
+            } else {
+                ReviewStatus::Pending
+            };
+            let entry = QuarantineEntry {
+                id: id.clone(),
+                document_id: comment.document_id.clone(),
+                document_name: comment.document_name.clone(),
+                field_path: comment.field_path.clone(),
+                original: comment.original.clone(),
+                withheld: comment.withheld.clone(),
+                reasons: comment.reasons.clone(),
+                score: comment.score,
+                sanitizer_version: sanitizer_version.to_string(),
+                quarantined_at_unix: now,
+                status,
+                reviewer: None,
+                reviewed_at_unix: None,
+                note: None,
+            };
+            match self.entries.iter_mut().find(|existing| existing.id == id) {
+                Some(existing) if existing.original == comment.original => {
+                    existing.withheld = entry.withheld;
+                    existing.reasons = entry.reasons;
+                    existing.score = entry.score;
+                    existing.sanitizer_version = entry.sanitizer_version;
+                    if (existing.status == ReviewStatus::Blocked)
+                        != (status == ReviewStatus::Blocked)
+                    {
+                        existing.status = status;
+                        existing.reviewer = None;
+                        existing.reviewed_at_unix = None;
+                        existing.note = None;
+                    }
+                }
+                Some(existing) => {
+                    *existing = entry;
+                    added += 1;
+                }
+                None => {
+                    self.entries.push(entry);
+                    added += 1;
+                }
+            }
+        }
+        added
+    }
+
+    /// Records a review decision.
+    pub fn decide(
+        &mut self,
+        id: &str,
+        status: ReviewStatus,
+        reviewer: &str,
+        note: Option<String>,
+    ) -> Result<&QuarantineEntry> {
+        if reviewer.trim().is_empty() {
+            bail!("a reviewer name is required");
+        }
+        let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) else {
+            bail!("no quarantined comment with id {:?}", id);
+        };
+        if entry.status == ReviewStatus::Blocked {
+            bail!("{:?} was blocked by a block rule or the block score; it can be inspected but is never released", id);
+        }
+        if status == ReviewStatus::Blocked {
+            bail!("only the sanitizer blocks comments; approve or reject instead");
+        }
+        entry.status = status;
+        entry.reviewer = Some(reviewer.trim().to_string());
+        entry.reviewed_at_unix = Some(now_unix());
+        entry.note = note.filter(|n| !n.trim().is_empty());
+        Ok(entry)
+    }
+
+    /// Puts approved comments back into the mapped documents in place of the block token and
+    /// marks the field report with the reviewer and time. Returns the number of released fields.
+    pub fn release_approved(&self, mapped_docs: &mut [Value]) -> usize {
+        let mut released = 0usize;
+        for entry in self
+            .entries
+            .iter()
+            .filter(|e| e.status == ReviewStatus::Approved)
+        {
+            let Some(doc) = mapped_docs.iter_mut().find(|doc| {
+                doc.get("id").and_then(Value::as_str) == Some(entry.document_id.as_str())
+            }) else {
+                continue;
+            };
+            let Some(field) = value_at_path_mut(doc, &entry.field_path) else {
+                continue;
+            };
+            if field.as_str() != Some(BLOCKED_COMMENT_TOKEN) {
+                continue;
+            }
+            *field = Value::String(entry.withheld.clone());
+            mark_released(doc, entry);
+            released += 1;
+        }
+        released
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let serialized = serde_json::to_vec_pretty(&QuarantineFile {
+            format: QUARANTINE_FORMAT,
+            entries: self.entries.clone(),
+        })
+        .context("failed to serialize quarantine")?;
+
+        let bytes = match self.key.as_ref() {
+            Some(key) => encrypt(key, ENCRYPTION_AAD, serialized)?,
+            None if self.inside_repo => bail!(
+                "quarantine {} lies inside the repo; move `quarantine_path` outside or set {} to encrypt it",
+                self.path.display(),
+                QUARANTINE_KEY_ENV
+            ),
+            None => serialized,
+        };
+        write_private_file(&self.path, &bytes)
+            .with_context(|| format!("failed to write quarantine {}", self.path.display()))
+    }
+}
+
+fn mark_released(doc: &mut Value, entry: &QuarantineEntry) {
+    let Some(security) = doc
+        .get_mut("commentSecurity")
+        .and_then(Value::as_object_mut)
+    else {
+        return;
+    };
+    for key in ["blockedFields", "quarantinedFields"] {
+        if let Some(count) = security.get(key).and_then(Value::as_u64) {
+            security.insert(key.to_string(), json!(count.saturating_sub(1)));
+        }
+    }
+    let released = security
+        .get("releasedFields")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    security.insert("releasedFields".to_string(), json!(released + 1));
+
+    let report = security
+        .get_mut("reports")
+        .and_then(Value::as_array_mut)
+        .and_then(|reports| {
+            reports.iter_mut().find(|r| {
+                r.get("field_path").and_then(Value::as_str) == Some(entry.field_path.as_str())
+            })
+        })
+        .and_then(Value::as_object_mut);
+    if let Some(report) = report {
+        report.insert("blocked".to_string(), json!(false));
+        report.insert("disposition".to_string(), json!(Disposition::Keep));
+        report.insert(
+            "sanitized_length".to_string(),
+            json!(entry.withheld.chars().count()),
+        );
+        report.insert(
+            "review".to_string(),
+            json!({
+                "decision": ReviewStatus::Approved.as_str(),
+                "reviewer": entry.reviewer,
+                "reviewedAtUnix": entry.reviewed_at_unix
+            }),
+        );
+    }
+}
+
+/// Resolves a report field path like `data.context.notes[1]` inside a mapped document.
+fn value_at_path_mut<'v>(doc: &'v mut Value, field_path: &str) -> Option<&'v mut Value> {
+    let mut current = doc;
+    for segment in field_path.split('.') {
+        let (key, indices) = match segment.find('[') {
+            Some(start) => (&segment[..start], &segment[start..]),
+            None => (segment, ""),
+        };
+        current = current.get_mut(key)?;
+        for index in indices.split('[').filter(|i| !i.is_empty()) {
+            let index: usize = index.strip_suffix(']')?.parse().ok()?;
+            current = current.get_mut(index)?;
+        }
+    }
+    Some(current)
+}
+
+pub(crate) fn is_inside(path: &Path, repo_root: &Path) -> bool {
+    let canonical_root = repo_root
+        .canonicalize()
+        .unwrap_or_else(|_| repo_root.to_path_buf());
+    let canonical_path = path
+        .parent()
+        .and_then(|parent| parent.canonicalize().ok())
+        .and_then(|parent| path.file_name().map(|name| parent.join(name)))
+        .unwrap_or_else(|| path.to_path_buf());
+    canonical_path.starts_with(&canonical_root) || path.starts_with(repo_root)
+}
+
+/// Fails when `path` lies inside the repo and git does not ignore it, so restricted output cannot be
+/// committed by accident. Outside a git work tree, or without git, there is nothing to commit it to.
+pub(crate) fn ensure_git_ignored(path: &Path, repo_root: &Path, setting: &str) -> Result<()> {
+    if !is_inside(path, repo_root) {
+        return Ok(());
+    }
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(repo_root)
+        .args(["check-ignore", "-q"])
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    // 0: ignored, 1: not ignored (or tracked), anything else: not a work tree.
+    if matches!(status.map(|status| status.code()), Ok(Some(1))) {
+        bail!(
+            "{} lies inside the repo and is not git-ignored; add it to .gitignore or move `{}` outside",
+            path.display(),
+            setting
+        );
+    }
+    Ok(())
+}
+
+/// The key from [`QUARANTINE_KEY_ENV`]; unset or blank means no encryption.
+pub fn key_from_env() -> Result<Option<LessSafeKey>> {
+    match std::env::var(QUARANTINE_KEY_ENV) {
+        Ok(raw) if !raw.trim().is_empty() => parse_key(raw.trim()).map(Some),
+        _ => Ok(None),
+    }
+}
+
+pub fn parse_key(hex: &str) -> Result<LessSafeKey> {
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!(
+            "{} must be 64 hex characters (32 bytes)",
+            QUARANTINE_KEY_ENV
+        );
+    }
+    let bytes: Vec<u8> = (0..32)
+        .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).expect("checked hex"))
+        .collect();
+    let key = UnboundKey::new(&AES_256_GCM, &bytes)
+        .map_err(|_| anyhow::anyhow!("invalid quarantine key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Whether `bytes` were written by [`encrypt`].
+pub(crate) fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(ENCRYPTED_MAGIC)
+}
+
+/// AES-256-GCM with a random nonce: magic, nonce, ciphertext and tag.
+pub(crate) fn encrypt(
+    key: &LessSafeKey,
+    aad: &'static [u8],
+    mut plain: Vec<u8>,
+) -> Result<Vec<u8>> {
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce_bytes)
+        .map_err(|_| anyhow::anyhow!("failed to generate a nonce"))?;
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce_bytes),
+        Aad::from(aad),
+        &mut plain,
+    )
+    .map_err(|_| anyhow::anyhow!("failed to encrypt"))?;
+
+    let mut sealed = Vec::with_capacity(ENCRYPTED_MAGIC.len() + NONCE_LEN + plain.len());
+    sealed.extend_from_slice(ENCRYPTED_MAGIC);
+    sealed.extend_from_slice(&nonce_bytes);
+    sealed.extend_from_slice(&plain);
+    Ok(sealed)
+}
+
+pub(crate) fn decrypt(key: &LessSafeKey, aad: &'static [u8], bytes: &[u8]) -> Result<Vec<u8>> {
+    let Some(sealed) = bytes.strip_prefix(ENCRYPTED_MAGIC) else {
+        bail!("not an encrypted fireBaseGetter file");
+    };
+    if sealed.len() < NONCE_LEN {
+        bail!("encrypted file is truncated");
+    }
+    let (nonce_bytes, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce_bytes)
+        .map_err(|_| anyhow::anyhow!("invalid nonce"))?;
+    let mut buffer = ciphertext.to_vec();
+    let plain = key
+        .open_in_place(nonce, Aad::from(aad), &mut buffer)
+        .map_err(|_| anyhow::anyhow!("wrong key or corrupted file"))?;
+    Ok(plain.to_vec())
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
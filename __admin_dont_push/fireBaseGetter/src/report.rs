@@ -0,0 +1,470 @@
+//! The output JSON (`feedback_all_games.json`) and the protocol file.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::config::{RunOptions, Settings};
+use crate::decode::{decode_raw_document_with, extract_document_id, DecodedDocument};
+use crate::export::{path_to_repo_relative, LearningExportSummary};
+use crate::fields::FieldPolicy;
+use crate::model::parse_feedback;
+use crate::quarantine::WithheldComment;
+use crate::sanitize::{sanitize_document_fields, CommentSanitizer, Disposition, RuleMatch};
+
+#[derive(Debug)]
+pub struct BuildOutputResult {
+    pub payload: Value,
+    pub mapped_documents: Vec<Value>,
+    /// Match spans per document for the review output; `None` when the comments were already
+    /// sanitized (input was an output JSON), so the existing review output stays as it is.
+    pub reviews: Option<Vec<DocumentReview>>,
+    /// Blocked comments for the quarantine; `None` under the same condition as `reviews`.
+    pub withheld: Option<Vec<WithheldComment>>,
+}
+
+/// One sanitized document with what it contributes to the review output and the quarantine.
+#[derive(Debug)]
+pub struct MappedDocument {
+    pub value: Value,
+    /// `None` when no rule or PII pattern matched.
+    pub review: Option<DocumentReview>,
+    pub withheld: Vec<WithheldComment>,
+}
+
+/// Everything that triggered in one document, for the restricted review output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentReview {
+    pub id: String,
+    pub name: String,
+    pub fields: Vec<FieldReview>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldReview {
+    pub field_path: String,
+    pub blocked: bool,
+    /// Missing in review outputs written before per-rule actions.
+    #[serde(default)]
+    pub disposition: Disposition,
+    pub score: u32,
+    pub reasons: Vec<String>,
+    pub matches: Vec<RuleMatch>,
+}
+
+pub fn attach_learning_export_summary(
+    payload: &mut Value,
+    options: &RunOptions,
+    export_summary: &LearningExportSummary,
+) {
+    if let Some(root_obj) = payload.as_object_mut() {
+        root_obj.insert(
+            "learningExport".to_string(),
+            json!({
+                "checkedDocuments": export_summary.checked_documents,
+                "exportedFeedbacks": export_summary.exported_feedbacks,
+                "filteredFeedbacks": export_summary.filtered_feedbacks,
+                "unresolvedFolderFeedbacks": export_summary.unresolved_folder_feedbacks,
+                "rejectedCandidates": export_summary.rejected_candidates,
+                "learningFolderPattern": export_summary.learning_folder_pattern,
+                "exportRoots": export_summary.export_roots,
+                "learningFoldersFound": export_summary.learning_folders_found,
+                "gameFoldersIndexed": export_summary.game_folders_indexed,
+                "exportSubdir": options.settings.learning_export_subdir,
+                "protocolFile": path_to_repo_relative(&options.repo_root, &options.protocol_path),
+                "writtenPaths": export_summary
+                    .written_paths
+                    .iter()
+                    .map(|p| path_to_repo_relative(&options.repo_root, p))
+                    .collect::<Vec<String>>(),
+                "routes": export_summary.routes
+            }),
+        );
+    }
+}
+
+pub fn read_output_payload(path: &Path) -> Result<Value> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed to read output file {}", path.display()))?;
+    serde_json::from_str(&raw)
+        .with_context(|| format!("failed to parse output JSON from {}", path.display()))
+}
+
+pub fn write_output_payload(path: &Path, payload: &Value) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create output directory for {}", path.display()))?;
+    }
+
+    let serialized =
+        serde_json::to_vec_pretty(payload).context("failed to serialize output JSON")?;
+    fs::write(path, serialized)
+        .with_context(|| format!("failed to write output file {}", path.display()))
+}
+
+pub fn build_output_payload(
+    project_id: &str,
+    documents: &[Value],
+    settings: &Settings,
+    sanitizer: &dyn CommentSanitizer,
+) -> BuildOutputResult {
+    let decoded = documents
+        .iter()
+        .map(|doc| decode_raw_document_with(doc, settings.decode_mode()))
+        .collect();
+    assemble_output_payload(
+        project_id,
+        &settings.collection,
+        decoded,
+        settings,
+        sanitizer,
+    )
+}
+
+pub fn assemble_output_payload(
+    project_id: &str,
+    collection: &str,
+    documents: Vec<DecodedDocument>,
+    settings: &Settings,
+    sanitizer: &dyn CommentSanitizer,
+) -> BuildOutputResult {
+    let mut reviews = Vec::new();
+    let mut withheld = Vec::new();
+    let mapped_docs = documents
+        .into_iter()
+        .map(|doc| {
+            let mapped = map_decoded_document(doc, sanitizer);
+            reviews.extend(mapped.review);
+            withheld.extend(mapped.withheld);
+            mapped.value
+        })
+        .collect();
+    let mut build_result =
+        finish_output_payload(project_id, collection, mapped_docs, settings, sanitizer);
+    build_result.reviews = Some(reviews);
+    build_result.withheld = Some(withheld);
+    build_result
+}
+
+/// Sanitizes one document.
+pub fn map_decoded_document(
+    doc: DecodedDocument,
+    sanitizer: &dyn CommentSanitizer,
+) -> MappedDocument {
+    let mut data = doc.data;
+    // Validated before sanitizing, so replacement tokens do not count as values.
+    let (_, schema) = parse_feedback(&data);
+    let sanitization = sanitize_document_fields(&mut data, sanitizer, FieldPolicy::builtin());
+    let mut reports = sanitization.comments;
+
+    let comment_field_count = reports.len();
+    let changed_count = reports.iter().filter(|r| r.changed).count();
+    let blocked_count = reports.iter().filter(|r| r.blocked).count();
+    let quarantined_count = reports
+        .iter()
+        .filter(|r| r.disposition == Disposition::Quarantine)
+        .count();
+
+    let id = extract_document_id(&doc.name);
+    let review_fields: Vec<FieldReview> = reports
+        .iter_mut()
+        .filter(|r| !r.matches.is_empty())
+        .map(|r| FieldReview {
+            field_path: r.field_path.clone(),
+            blocked: r.blocked,
+            disposition: r.disposition,
+            score: r.score,
+            reasons: r.reasons.clone(),
+            matches: std::mem::take(&mut r.matches),
+        })
+        .collect();
+    let review = (!review_fields.is_empty()).then(|| DocumentReview {
+        id: id.clone(),
+        name: doc.name.clone(),
+        fields: review_fields,
+    });
+    let withheld = reports
+        .iter_mut()
+        .filter_map(|r| {
+            let text = r.withheld.take()?;
+            Some(WithheldComment {
+                document_id: id.clone(),
+                document_name: doc.name.clone(),
+                field_path: r.field_path.clone(),
+                original: text.original,
+                withheld: text.text,
+                reasons: r.reasons.clone(),
+                score: r.score,
+                disposition: r.disposition,
+            })
+        })
+        .collect();
+
+    let mapped = json!({
+        "id": id,
+        "name": doc.name,
+        "createTime": doc.create_time,
+        "updateTime": doc.update_time,
+        "data": data,
+        "schema": schema,
+        "commentSecurity": {
+            "sanitizerVersion": sanitizer.version(),
+            "commentFieldsChecked": comment_field_count,
+            "changedFields": changed_count,
+            "blockedFields": blocked_count,
+            "quarantinedFields": quarantined_count,
+            "reports": reports,
+            "otherFieldsChanged": sanitization.fields.len(),
+            "fieldReports": sanitization.fields
+        }
+    });
+    MappedDocument {
+        value: mapped,
+        review,
+        withheld,
+    }
+}
+
+/// Wraps already mapped documents into the output payload and sums up their security reports.
+pub fn finish_output_payload(
+    project_id: &str,
+    collection: &str,
+    mapped_docs: Vec<Value>,
+    settings: &Settings,
+    sanitizer: &dyn CommentSanitizer,
+) -> BuildOutputResult {
+    let now_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut security = document_security_totals(&mapped_docs);
+    security.insert("sanitizerVersion".to_string(), json!(sanitizer.version()));
+    security.insert(
+        "quarantineScoreThreshold".to_string(),
+        json!(settings.quarantine_score_threshold),
+    );
+    security.insert(
+        "blockScoreThreshold".to_string(),
+        json!(settings.block_score_threshold),
+    );
+    security.insert(
+        "commentMaxChars".to_string(),
+        json!(settings.comment_max_chars),
+    );
+
+    let payload = json!({
+        "projectId": project_id,
+        "collection": collection,
+        "downloadedAtUnix": now_unix,
+        "documentCount": mapped_docs.len(),
+        "decodeMode": settings.decode_mode().as_str(),
+        "schema": document_schema_totals(&mapped_docs),
+        "security": security,
+        "documents": mapped_docs.clone()
+    });
+
+    BuildOutputResult {
+        payload,
+        mapped_documents: mapped_docs,
+        reviews: None,
+        withheld: None,
+    }
+}
+
+/// Puts changed documents back into an output payload and recounts its security totals.
+pub fn replace_output_documents(payload: &mut Value, mapped_docs: &[Value]) {
+    let totals = document_security_totals(mapped_docs);
+    if let Some(security) = payload.get_mut("security").and_then(Value::as_object_mut) {
+        security.extend(totals);
+    }
+    if let Some(root_obj) = payload.as_object_mut() {
+        root_obj.insert("documents".to_string(), Value::Array(mapped_docs.to_vec()));
+    }
+}
+
+/// Sums the `commentSecurity` counters of the mapped documents.
+fn document_security_totals(mapped_docs: &[Value]) -> Map<String, Value> {
+    let mut total_comment_fields = 0u64;
+    let mut total_changed_fields = 0u64;
+    let mut total_blocked_fields = 0u64;
+    let mut total_quarantined_fields = 0u64;
+    let mut total_released_fields = 0u64;
+    let mut total_other_fields_changed = 0u64;
+    let mut docs_with_blocked_comments = 0usize;
+
+    for doc in mapped_docs {
+        let security_count = |key: &str| {
+            doc.get("commentSecurity")
+                .and_then(|s| s.get(key))
+                .and_then(Value::as_u64)
+                .unwrap_or(0)
+        };
+        total_comment_fields += security_count("commentFieldsChecked");
+        total_changed_fields += security_count("changedFields");
+        let blocked_count = security_count("blockedFields");
+        total_blocked_fields += blocked_count;
+        total_quarantined_fields += security_count("quarantinedFields");
+        total_released_fields += security_count("releasedFields");
+        total_other_fields_changed += security_count("otherFieldsChanged");
+        if blocked_count > 0 {
+            docs_with_blocked_comments += 1;
+        }
+    }
+
+    let mut totals = Map::new();
+    totals.insert(
+        "commentFieldsChecked".to_string(),
+        json!(total_comment_fields),
+    );
+    totals.insert("changedFields".to_string(), json!(total_changed_fields));
+    totals.insert("blockedFields".to_string(), json!(total_blocked_fields));
+    totals.insert(
+        "quarantinedFields".to_string(),
+        json!(total_quarantined_fields),
+    );
+    totals.insert("releasedFields".to_string(), json!(total_released_fields));
+    totals.insert(
+        "documentsWithBlockedComments".to_string(),
+        json!(docs_with_blocked_comments),
+    );
+    totals.insert(
+        "otherFieldsChanged".to_string(),
+        json!(total_other_fields_changed),
+    );
+    totals
+}
+
+/// Counts the documents whose `schema` report has errors or unknown fields.
+fn document_schema_totals(mapped_docs: &[Value]) -> Value {
+    let mut invalid_documents = 0usize;
+    let mut field_errors = 0usize;
+    let mut documents_with_unknown_fields = 0usize;
+    for doc in mapped_docs {
+        let list_len = |key: &str| {
+            doc.get("schema")
+                .and_then(|s| s.get(key))
+                .and_then(Value::as_array)
+                .map_or(0, Vec::len)
+        };
+        let errors = list_len("errors");
+        field_errors += errors;
+        if errors > 0 {
+            invalid_documents += 1;
+        }
+        if list_len("unknownFields") > 0 {
+            documents_with_unknown_fields += 1;
+        }
+    }
+    json!({
+        "invalidDocuments": invalid_documents,
+        "fieldErrors": field_errors,
+        "documentsWithUnknownFields": documents_with_unknown_fields
+    })
+}
+
+/// Reviews from an earlier review output; empty when the file does not exist.
+pub fn read_review_documents(path: &Path) -> Result<Vec<DocumentReview>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed to read review output {}", path.display()))?;
+    let payload: Value = serde_json::from_str(&raw)
+        .with_context(|| format!("failed to parse review output {}", path.display()))?;
+    let documents = payload
+        .get("documents")
+        .cloned()
+        .unwrap_or_else(|| json!([]));
+    serde_json::from_value(documents)
+        .with_context(|| format!("unexpected review output format in {}", path.display()))
+}
+
+/// Writes the review output. It quotes raw comment text, so on Unix only the owner may read it.
+pub fn write_review_file(
+    path: &Path,
+    sanitizer_version: &str,
+    reviews: &[DocumentReview],
+) -> Result<()> {
+    let now_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let payload = json!({
+        "restricted": true,
+        "notice": "Contains excerpts of raw user comments. Do not commit or share.",
+        "sanitizerVersion": sanitizer_version,
+        "generatedAtUnix": now_unix,
+        "documentCount": reviews.len(),
+        "documents": reviews
+    });
+    let serialized =
+        serde_json::to_vec_pretty(&payload).context("failed to serialize review output")?;
+    write_private_file(path, &serialized)
+        .with_context(|| format!("failed to write review output {}", path.display()))
+}
+
+/// Writes `bytes` through a temporary file next to `path`; on Unix only the owner may read it.
+pub(crate) fn write_private_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {}", parent.display()))?;
+    }
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp_path)
+        .with_context(|| format!("failed to open {}", temp_path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .with_context(|| {
+                format!("failed to restrict permissions of {}", temp_path.display())
+            })?;
+    }
+    file.write_all(bytes)
+        .with_context(|| format!("failed to write {}", temp_path.display()))?;
+    drop(file);
+    fs::rename(&temp_path, path).with_context(|| format!("failed to replace {}", path.display()))
+}
+
+pub fn write_feedback_protocol_file(
+    protocol_path: &Path,
+    repo_root: &Path,
+    written_paths: &[PathBuf],
+) -> Result<()> {
+    if let Some(parent) = protocol_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create protocol directory {}", parent.display()))?;
+    }
+
+    let mut lines: Vec<String> = written_paths
+        .iter()
+        .map(|p| path_to_repo_relative(repo_root, p))
+        .collect();
+    lines.sort();
+
+    let content = if lines.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", lines.join("\n"))
+    };
+
+    fs::write(protocol_path, content)
+        .with_context(|| format!("failed to write protocol file {}", protocol_path.display()))?;
+
+    Ok(())
+}
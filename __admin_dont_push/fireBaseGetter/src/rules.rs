@@ -0,0 +1,399 @@
+//! Versioned rule packs for [`crate::RuleSanitizer`], loaded from TOML and validated up front.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+
+/// The pack shipped in `rules/prompt_injection.toml`, used when no `rule_pack` is configured.
+const BUILTIN_RULE_PACK: &str = include_str!("../rules/prompt_injection.toml");
+const BUILTIN_RULE_PACK_ORIGIN: &str = "built-in rules/prompt_injection.toml";
+const SUPPORTED_FORMAT: u32 = 1;
+const ALLOWED_FLAGS: &str = "imsxU";
+const DEFAULT_REPLACEMENT: &str = "[redacted]";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulePackFile {
+    format: u32,
+    name: String,
+    version: String,
+    rules: Vec<RuleSpec>,
+    #[serde(default)]
+    policies: Vec<PolicySpec>,
+}
+
+/// One rule as written in the pack.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSpec {
+    pub id: String,
+    pub description: String,
+    pub action: RuleAction,
+    #[serde(default)]
+    pub weight: u32,
+    pub pattern: String,
+    #[serde(default)]
+    pub flags: String,
+    #[serde(default)]
+    pub replacement: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// A match only adds the rule's weight to the score.
+    Flag,
+    /// A match is replaced by the rule's `replacement`.
+    Redact,
+    /// A match withholds the comment and queues it for review.
+    Quarantine,
+    /// A match withholds the comment for good.
+    Block,
+}
+
+impl RuleAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RuleAction::Flag => "flag",
+            RuleAction::Redact => "redact",
+            RuleAction::Quarantine => "quarantine",
+            RuleAction::Block => "block",
+        }
+    }
+
+    /// Every action but `redact` looks for injection and leaves the text alone.
+    pub fn is_detection(self) -> bool {
+        self != RuleAction::Redact
+    }
+}
+
+/// Thresholds and rule actions for comments from one `source` and/or topic area.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicySpec {
+    pub id: String,
+    pub description: String,
+    /// Matches the document's `source` field (`game_page`, `generic_page`).
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Regex on the repo-relative learning folder the export resolved for the document.
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub quarantine_score: Option<u32>,
+    #[serde(default)]
+    pub block_score: Option<u32>,
+    /// Rule id -> action used instead of the rule's own.
+    #[serde(default)]
+    pub actions: BTreeMap<String, RuleAction>,
+}
+
+#[derive(Debug)]
+pub struct CompiledPolicy {
+    pub spec: PolicySpec,
+    pub topic: Option<Regex>,
+}
+
+/// Where a comment comes from, for picking the policies.
+#[derive(Debug, Clone, Default)]
+pub struct CommentContext {
+    pub source: Option<String>,
+    /// The confined learning folder of the document, never a raw context field: submitters
+    /// control those and could pick a lenient topic.
+    pub learning_folder: Option<String>,
+}
+
+/// The matching policies merged in file order (later ones win).
+#[derive(Debug, Default)]
+pub struct EffectivePolicy<'p> {
+    pub policy_ids: Vec<&'p str>,
+    pub quarantine_score: Option<u32>,
+    pub block_score: Option<u32>,
+    actions: HashMap<&'p str, RuleAction>,
+}
+
+impl EffectivePolicy<'_> {
+    pub fn action_of(&self, rule: &CompiledRule) -> RuleAction {
+        self.actions
+            .get(rule.spec.id.as_str())
+            .copied()
+            .unwrap_or(rule.spec.action)
+    }
+}
+
+#[derive(Debug)]
+pub struct CompiledRule {
+    pub spec: RuleSpec,
+    pub regex: Regex,
+    pub replacement: String,
+}
+
+#[derive(Debug)]
+pub struct RulePack {
+    pub name: String,
+    pub version: String,
+    /// SHA-256 of the pack file, lowercase hex.
+    pub content_hash: String,
+    pub rules: Vec<CompiledRule>,
+    pub policies: Vec<CompiledPolicy>,
+}
+
+impl RulePack {
+    pub fn builtin() -> Self {
+        RulePack::parse(BUILTIN_RULE_PACK, BUILTIN_RULE_PACK_ORIGIN)
+            .expect("built-in rule pack is valid")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("failed to read rule pack {}", path.display()))?;
+        RulePack::parse(&raw, &path.display().to_string())
+    }
+
+    /// Parses and validates a pack; `origin` only appears in error messages.
+    pub fn parse(raw: &str, origin: &str) -> Result<Self> {
+        let file: RulePackFile =
+            toml::from_str(raw).with_context(|| format!("failed to parse rule pack {}", origin))?;
+        if file.format != SUPPORTED_FORMAT {
+            bail!(
+                "rule pack {} has format {}, supported is {}",
+                origin,
+                file.format,
+                SUPPORTED_FORMAT
+            );
+        }
+        if file.name.trim().is_empty() || file.version.trim().is_empty() {
+            bail!("rule pack {} needs a `name` and a `version`", origin);
+        }
+        if file.rules.is_empty() {
+            bail!("rule pack {} has no rules", origin);
+        }
+
+        let mut seen_ids = HashSet::new();
+        let mut rules = Vec::with_capacity(file.rules.len());
+        for spec in file.rules {
+            let compiled = compile_rule(spec)
+                .with_context(|| format!("invalid rule in rule pack {}", origin))?;
+            if !seen_ids.insert(compiled.spec.id.clone()) {
+                bail!(
+                    "rule pack {} defines rule `{}` twice",
+                    origin,
+                    compiled.spec.id
+                );
+            }
+            rules.push(compiled);
+        }
+
+        let detection_ids: HashSet<String> = rules
+            .iter()
+            .filter(|rule| rule.spec.action.is_detection())
+            .map(|rule| rule.spec.id.clone())
+            .collect();
+        let mut seen_policy_ids = HashSet::new();
+        let mut policies = Vec::with_capacity(file.policies.len());
+        for spec in file.policies {
+            let compiled = compile_policy(spec, &detection_ids)
+                .with_context(|| format!("invalid policy in rule pack {}", origin))?;
+            if !seen_policy_ids.insert(compiled.spec.id.clone()) {
+                bail!(
+                    "rule pack {} defines policy `{}` twice",
+                    origin,
+                    compiled.spec.id
+                );
+            }
+            policies.push(compiled);
+        }
+
+        Ok(RulePack {
+            name: file.name.trim().to_string(),
+            version: file.version.trim().to_string(),
+            content_hash: sha256_hex(raw.as_bytes()),
+            rules,
+            policies,
+        })
+    }
+
+    /// `<name>@<version>+sha256:<hash>`, written as `sanitizerVersion`.
+    pub fn sanitizer_version(&self) -> String {
+        format!(
+            "{}@{}+sha256:{}",
+            self.name, self.version, self.content_hash
+        )
+    }
+
+    /// Merges every policy whose `source` and `topic` match the comment's context.
+    pub fn policy_for(&self, context: &CommentContext) -> EffectivePolicy<'_> {
+        let mut effective = EffectivePolicy::default();
+        for policy in self
+            .policies
+            .iter()
+            .filter(|policy| policy.applies_to(context))
+        {
+            effective.policy_ids.push(&policy.spec.id);
+            effective.quarantine_score =
+                policy.spec.quarantine_score.or(effective.quarantine_score);
+            effective.block_score = policy.spec.block_score.or(effective.block_score);
+            for (rule_id, action) in &policy.spec.actions {
+                effective.actions.insert(rule_id, *action);
+            }
+        }
+        effective
+    }
+}
+
+impl CompiledPolicy {
+    fn applies_to(&self, context: &CommentContext) -> bool {
+        let source_matches = match &self.spec.source {
+            Some(source) => context.source.as_deref() == Some(source.as_str()),
+            None => true,
+        };
+        let topic_matches = match &self.topic {
+            Some(topic) => context
+                .learning_folder
+                .as_deref()
+                .is_some_and(|folder| topic.is_match(folder)),
+            None => true,
+        };
+        source_matches && topic_matches
+    }
+}
+
+/// `detection_ids` are the rules a policy may re-assign; redact rules keep their replacement.
+fn compile_policy(spec: PolicySpec, detection_ids: &HashSet<String>) -> Result<CompiledPolicy> {
+    let id = spec.id.trim();
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        bail!(
+            "policy id {:?} must be non-empty and use only [a-z0-9_]",
+            spec.id
+        );
+    }
+    if spec.description.trim().is_empty() {
+        bail!("policy `{}` needs a description", id);
+    }
+    if spec.source.is_none() && spec.topic.is_none() {
+        bail!("policy `{}` needs a `source` or a `topic`", id);
+    }
+    if spec.quarantine_score.is_none() && spec.block_score.is_none() && spec.actions.is_empty() {
+        bail!("policy `{}` changes nothing", id);
+    }
+    for (rule_id, action) in &spec.actions {
+        if !detection_ids.contains(rule_id) {
+            bail!(
+                "policy `{}` sets an action for `{}`, which is no detection rule",
+                id,
+                rule_id
+            );
+        }
+        if !action.is_detection() {
+            bail!(
+                "policy `{}` cannot turn rule `{}` into a redact rule",
+                id,
+                rule_id
+            );
+        }
+    }
+    let topic = spec
+        .topic
+        .as_deref()
+        .map(|topic| {
+            Regex::new(topic).with_context(|| format!("policy `{}` has an invalid topic", id))
+        })
+        .transpose()?;
+    Ok(CompiledPolicy {
+        spec: PolicySpec {
+            id: id.to_string(),
+            ..spec
+        },
+        topic,
+    })
+}
+
+fn compile_rule(spec: RuleSpec) -> Result<CompiledRule> {
+    let id = spec.id.trim();
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        bail!(
+            "rule id {:?} must be non-empty and use only [a-z0-9_]",
+            spec.id
+        );
+    }
+    if spec.description.trim().is_empty() {
+        bail!("rule `{}` needs a description", id);
+    }
+    if spec.pattern.is_empty() {
+        bail!("rule `{}` has an empty pattern", id);
+    }
+
+    let mut flags_seen = HashSet::new();
+    for flag in spec.flags.chars() {
+        if !ALLOWED_FLAGS.contains(flag) || !flags_seen.insert(flag) {
+            bail!(
+                "rule `{}` has invalid flags {:?} (allowed once each: {})",
+                id,
+                spec.flags,
+                ALLOWED_FLAGS
+            );
+        }
+    }
+
+    if spec.action.is_detection() {
+        if spec.weight == 0 {
+            bail!(
+                "{} rule `{}` needs a weight above 0",
+                spec.action.as_str(),
+                id
+            );
+        }
+        if spec.replacement.is_some() {
+            bail!(
+                "{} rule `{}` must not set a replacement",
+                spec.action.as_str(),
+                id
+            );
+        }
+    }
+
+    let source = if spec.flags.is_empty() {
+        spec.pattern.clone()
+    } else {
+        format!("(?{}){}", spec.flags, spec.pattern)
+    };
+    let regex =
+        Regex::new(&source).with_context(|| format!("rule `{}` has an invalid pattern", id))?;
+    if regex.is_match("") {
+        bail!("rule `{}` matches the empty string", id);
+    }
+
+    let replacement = spec
+        .replacement
+        .clone()
+        .unwrap_or_else(|| DEFAULT_REPLACEMENT.to_string());
+    Ok(CompiledRule {
+        spec: RuleSpec {
+            id: id.to_string(),
+            ..spec
+        },
+        regex,
+        replacement,
+    })
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    digest(&SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
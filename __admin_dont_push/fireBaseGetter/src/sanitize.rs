@@ -0,0 +1,754 @@
+//! Comment sanitizing: the [`CommentSanitizer`] stage and the rule-pack driven [`RuleSanitizer`].
+
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::{RunOptions, Settings};
+use crate::detection::detection_view;
+use crate::encoded::{decode_segments, Encoding, MAX_DECODE_DEPTH, MAX_SEGMENTS};
+use crate::export::LearningFolderResolver;
+use crate::fields::{clean_field, FieldPolicy, FieldProfile, FieldReport, WITHHELD_FIELD_TOKEN};
+use crate::lossless::typed_tag;
+use crate::markup::{strip_markup, MarkupKind};
+use crate::model::FeedbackDocument;
+use crate::pii::{mask_pii, redact_pii};
+use crate::rules::{CommentContext, CompiledRule, EffectivePolicy, RuleAction, RulePack};
+
+pub const DEFAULT_COMMENT_MAX_CHARS: usize = 4000;
+pub const DEFAULT_QUARANTINE_SCORE_THRESHOLD: u32 = 8;
+pub const DEFAULT_BLOCK_SCORE_THRESHOLD: u32 = 14;
+pub const BLOCKED_COMMENT_TOKEN: &str = "[blocked-by-fireBaseGetter-security]";
+pub const EMPTY_COMMENT_TOKEN: &str = "[empty-after-sanitization]";
+
+pub(crate) static CONTROL_CHAR_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[\x00-\x08\x0B\x0C\x0E-\x1F\x7F]").expect("valid regex"));
+pub(crate) static ZERO_WIDTH_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"[\u{200B}-\u{200F}\u{202A}-\u{202E}\u{2060}-\u{2064}\u{FEFF}]")
+        .expect("valid regex")
+});
+static MULTI_SPACE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").expect("valid regex"));
+
+/// How much context [`RuleMatch::excerpt`] keeps on each side, and how much of the match itself.
+const EXCERPT_CONTEXT_CHARS: usize = 40;
+const EXCERPT_MATCH_CHARS: usize = 160;
+/// Spans recorded per rule and text; a rule matching all over a comment adds nothing new after that.
+const MAX_SPANS_PER_RULE: usize = 10;
+
+/// What happens to a comment after sanitizing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Disposition {
+    #[default]
+    Keep,
+    /// Withheld and queued for review.
+    Quarantine,
+    /// Withheld for good.
+    Block,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommentSanitizationReport {
+    pub field_path: String,
+    /// True for both withholding dispositions.
+    pub blocked: bool,
+    pub disposition: Disposition,
+    pub changed: bool,
+    pub score: u32,
+    pub reasons: Vec<String>,
+    pub original_length: usize,
+    pub sanitized_length: usize,
+    /// Only written to the restricted review output, never to the public export.
+    #[serde(skip)]
+    pub matches: Vec<RuleMatch>,
+    /// Original and withheld text of a quarantined or blocked field.
+    #[serde(skip)]
+    pub withheld: Option<WithheldText>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WithheldText {
+    pub original: String,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub struct SanitizationOutcome {
+    pub sanitized: String,
+    pub blocked: bool,
+    pub disposition: Disposition,
+    pub changed: bool,
+    pub score: u32,
+    pub reasons: Vec<String>,
+    pub original_length: usize,
+    pub sanitized_length: usize,
+    pub matches: Vec<RuleMatch>,
+    /// What the block token replaced; set when quarantined or blocked.
+    pub withheld: Option<String>,
+}
+
+/// Where a detection or rewrite hit, for triage in the review output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleMatch {
+    pub rule_id: String,
+    /// `detected` (quarantine and block rules), `flagged`, `redacted` or `pii_redacted`.
+    pub action: String,
+    /// The text the offsets refer to: `text` (the normalized comment; for rewrites after the
+    /// earlier rewrites), `normalized` (the detection view) or `decoded:<encodings>`.
+    pub source: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+    /// The match in `[[...]]` with up to 40 characters of context on each side; personal data in
+    /// it is replaced by the PII tokens.
+    pub excerpt: String,
+}
+
+impl RuleMatch {
+    /// The offsets refer to `text`; the excerpt is cut from `text` with personal data replaced by
+    /// the PII tokens, so the review output never repeats it.
+    pub fn locate(
+        text: &str,
+        range: Range<usize>,
+        rule_id: &str,
+        action: &str,
+        source: &str,
+    ) -> Self {
+        let char_start = text[..range.start].chars().count();
+        let char_end = char_start + text[range.clone()].chars().count();
+
+        let (masked, masked_range) = mask_pii(text, range.clone());
+        let before: Vec<char> = masked[..masked_range.start]
+            .chars()
+            .rev()
+            .take(EXCERPT_CONTEXT_CHARS + 1)
+            .collect();
+        let after: Vec<char> = masked[masked_range.end..]
+            .chars()
+            .take(EXCERPT_CONTEXT_CHARS + 1)
+            .collect();
+        let matched: Vec<char> = masked[masked_range].chars().collect();
+
+        let mut excerpt = String::new();
+        if before.len() > EXCERPT_CONTEXT_CHARS {
+            excerpt.push('…');
+        }
+        excerpt.extend(before.iter().take(EXCERPT_CONTEXT_CHARS).rev());
+        excerpt.push_str("[[");
+        if matched.len() > EXCERPT_MATCH_CHARS {
+            excerpt.extend(&matched[..EXCERPT_MATCH_CHARS / 2]);
+            excerpt.push('…');
+            excerpt.extend(&matched[matched.len() - EXCERPT_MATCH_CHARS / 2..]);
+        } else {
+            excerpt.extend(&matched);
+        }
+        excerpt.push_str("]]");
+        excerpt.extend(after.iter().take(EXCERPT_CONTEXT_CHARS));
+        if after.len() > EXCERPT_CONTEXT_CHARS {
+            excerpt.push('…');
+        }
+
+        RuleMatch {
+            rule_id: rule_id.to_string(),
+            action: action.to_string(),
+            source: source.to_string(),
+            byte_start: range.start,
+            byte_end: range.end,
+            char_start,
+            char_end,
+            excerpt,
+        }
+    }
+}
+
+/// Second pipeline stage: cleans one comment string.
+pub trait CommentSanitizer {
+    /// Written to `commentSecurity.sanitizerVersion` of every document.
+    fn version(&self) -> &str;
+
+    fn sanitize(&self, input: &str) -> SanitizationOutcome;
+
+    /// Sanitizes a comment of a document from `context`; sanitizers without policies ignore it.
+    fn sanitize_in_context(&self, input: &str, context: &CommentContext) -> SanitizationOutcome {
+        let _ = context;
+        self.sanitize(input)
+    }
+
+    /// The context of a document's comments. The default only knows `source`; a topic needs the
+    /// learning folder the sanitizer resolves itself.
+    fn comment_context(&self, data: &Value) -> CommentContext {
+        source_context(data)
+    }
+}
+
+/// The default sanitizer: Unicode/whitespace cleanup, length cap, then the rules of a
+/// [`RulePack`] with the policies that match the comment's context.
+#[derive(Debug, Clone)]
+pub struct RuleSanitizer {
+    pub comment_max_chars: usize,
+    pub quarantine_score_threshold: u32,
+    pub block_score_threshold: u32,
+    rules: Arc<RulePack>,
+    version: String,
+    /// Resolves the learning folder that topic policies match; without it they never apply.
+    learning_folders: Option<Arc<LearningFolderResolver>>,
+}
+
+impl RuleSanitizer {
+    pub fn new(settings: &Settings, rules: RulePack) -> Self {
+        RuleSanitizer {
+            comment_max_chars: settings.comment_max_chars,
+            quarantine_score_threshold: settings.quarantine_score_threshold,
+            block_score_threshold: settings.block_score_threshold,
+            version: rules.sanitizer_version(),
+            rules: Arc::new(rules),
+            learning_folders: None,
+        }
+    }
+
+    /// Lets topic policies match the learning folder `resolver` routes a document to.
+    pub fn with_learning_folders(mut self, resolver: LearningFolderResolver) -> Self {
+        self.learning_folders = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Loads the configured `rule_pack`, or the built-in pack when none is set.
+    pub fn from_options(options: &RunOptions) -> Result<Self> {
+        let rules = match options.rule_pack_path.as_deref() {
+            Some(path) => RulePack::load(path)?,
+            None => RulePack::builtin(),
+        };
+        Ok(RuleSanitizer::new(&options.settings, rules))
+    }
+
+    pub fn rules(&self) -> &RulePack {
+        &self.rules
+    }
+}
+
+impl Default for RuleSanitizer {
+    fn default() -> Self {
+        let rules = RulePack::builtin();
+        RuleSanitizer {
+            comment_max_chars: DEFAULT_COMMENT_MAX_CHARS,
+            quarantine_score_threshold: DEFAULT_QUARANTINE_SCORE_THRESHOLD,
+            block_score_threshold: DEFAULT_BLOCK_SCORE_THRESHOLD,
+            version: rules.sanitizer_version(),
+            rules: Arc::new(rules),
+            learning_folders: None,
+        }
+    }
+}
+
+impl CommentSanitizer for RuleSanitizer {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn sanitize(&self, input: &str) -> SanitizationOutcome {
+        self.sanitize_in_context(input, &CommentContext::default())
+    }
+
+    fn sanitize_in_context(&self, input: &str, context: &CommentContext) -> SanitizationOutcome {
+        let policy = self.rules.policy_for(context);
+        let thresholds = Thresholds {
+            quarantine: policy
+                .quarantine_score
+                .unwrap_or(self.quarantine_score_threshold),
+            block: policy.block_score.unwrap_or(self.block_score_threshold),
+        };
+        sanitize_comment_text(
+            input,
+            &self.rules,
+            &policy,
+            self.comment_max_chars,
+            thresholds,
+        )
+    }
+
+    fn comment_context(&self, data: &Value) -> CommentContext {
+        CommentContext {
+            learning_folder: self
+                .learning_folders
+                .as_ref()
+                .and_then(|resolver| resolver.learning_folder(data)),
+            ..source_context(data)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Thresholds {
+    quarantine: u32,
+    block: u32,
+}
+
+/// What [`sanitize_document_fields`] did to one document.
+#[derive(Debug, Default)]
+pub struct DocumentSanitization {
+    /// One per free-text field.
+    pub comments: Vec<CommentSanitizationReport>,
+    /// Structured and unknown string fields that were changed.
+    pub fields: Vec<FieldReport>,
+}
+
+/// Sanitizes every string of a decoded document: free text with `sanitizer`, the other fields of
+/// `policy` by their profile, and strings outside the schema are withheld.
+pub fn sanitize_document_fields(
+    value: &mut Value,
+    sanitizer: &dyn CommentSanitizer,
+    policy: &FieldPolicy,
+) -> DocumentSanitization {
+    let walker = FieldWalker {
+        sanitizer,
+        policy,
+        context: sanitizer.comment_context(value),
+    };
+    let mut result = DocumentSanitization::default();
+    walker.walk(value, "data", "", false, &mut result);
+    result
+}
+
+/// The `source` of the document data, for the rule pack policies.
+fn source_context(data: &Value) -> CommentContext {
+    let document = FeedbackDocument::from_data(data);
+    let source = document
+        .source
+        .as_deref()
+        .map(str::trim)
+        .filter(|source| !source.is_empty());
+    CommentContext {
+        source: source.map(str::to_string),
+        learning_folder: None,
+    }
+}
+
+struct FieldWalker<'a> {
+    sanitizer: &'a dyn CommentSanitizer,
+    policy: &'a FieldPolicy,
+    context: CommentContext,
+}
+
+impl FieldWalker<'_> {
+    /// `path` is the report path (`data.context.notes[1]`), `schema_path` the policy key (`context.notes`).
+    fn walk(
+        &self,
+        value: &mut Value,
+        path: &str,
+        schema_path: &str,
+        in_comment_context: bool,
+        result: &mut DocumentSanitization,
+    ) {
+        match value {
+            // A lossless typed value (`{"$timestamp": ...}`) is checked like the field holding it.
+            Value::Object(map) if typed_tag(map).is_some() => {
+                for (tag, child) in map.iter_mut() {
+                    let child_path = join_path(path, tag);
+                    self.walk(child, &child_path, schema_path, in_comment_context, result);
+                }
+            }
+            Value::Object(map) => {
+                for (key, child) in map.iter_mut() {
+                    let child_path = join_path(path, key);
+                    let child_schema_path = join_path(schema_path, key);
+                    let comment_context = in_comment_context || is_comment_field(key);
+                    self.walk(
+                        child,
+                        &child_path,
+                        &child_schema_path,
+                        comment_context,
+                        result,
+                    );
+                }
+            }
+            Value::Array(items) => {
+                for (index, child) in items.iter_mut().enumerate() {
+                    let child_path = format!("{}[{}]", path, index);
+                    self.walk(child, &child_path, schema_path, in_comment_context, result);
+                }
+            }
+            Value::String(text) => {
+                let profile = if in_comment_context {
+                    Some(FieldProfile::FreeText)
+                } else {
+                    self.policy.profile_of(schema_path)
+                };
+                match profile {
+                    Some(FieldProfile::FreeText) => {
+                        result.comments.push(self.sanitize_text(text, path))
+                    }
+                    Some(profile) => {
+                        let cleanup = clean_field(profile, text);
+                        if let Some(reason) = cleanup.reason {
+                            result.fields.push(FieldReport {
+                                field_path: path.to_string(),
+                                profile: profile.as_str(),
+                                reason,
+                                original_length: text.chars().count(),
+                            });
+                            *text = cleanup.value;
+                        }
+                    }
+                    None => {
+                        result.fields.push(FieldReport {
+                            field_path: path.to_string(),
+                            profile: "unknown",
+                            reason: "unknown_field",
+                            original_length: text.chars().count(),
+                        });
+                        *text = WITHHELD_FIELD_TOKEN.to_string();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sanitize_text(&self, text: &mut String, path: &str) -> CommentSanitizationReport {
+        let outcome = self.sanitizer.sanitize_in_context(text, &self.context);
+        let original = std::mem::replace(text, outcome.sanitized.clone());
+        let withheld = outcome.withheld.map(|withheld| WithheldText {
+            original,
+            text: withheld,
+        });
+        CommentSanitizationReport {
+            field_path: path.to_string(),
+            blocked: outcome.blocked,
+            disposition: outcome.disposition,
+            changed: outcome.changed,
+            score: outcome.score,
+            reasons: outcome.reasons,
+            original_length: outcome.original_length,
+            sanitized_length: outcome.sanitized_length,
+            matches: outcome.matches,
+            withheld,
+        }
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn is_comment_field(key: &str) -> bool {
+    let folded = key.to_ascii_lowercase().replace(['-', '_', ' '], "");
+
+    folded.contains("comment")
+        || folded.contains("kommentar")
+        || folded.contains("feedbacktext")
+        || folded == "feedback"
+        || folded == "message"
+        || folded == "nachricht"
+}
+
+fn sanitize_comment_text(
+    input: &str,
+    rules: &RulePack,
+    policy: &EffectivePolicy,
+    comment_max_chars: usize,
+    thresholds: Thresholds,
+) -> SanitizationOutcome {
+    let mut reasons = BTreeSet::<String>::new();
+    let mut score = 0u32;
+    let mut changed = false;
+    let mut matched_block_rule = false;
+    let mut matched_quarantine_rule = false;
+    let mut matches = Vec::new();
+
+    let original_length = input.chars().count();
+    let mut sanitized = input.nfkc().collect::<String>();
+
+    if sanitized != input {
+        changed = true;
+        score += 1;
+        reasons.insert("unicode_normalized_nfkc".to_string());
+    }
+
+    let without_control = CONTROL_CHAR_RE.replace_all(&sanitized, "").to_string();
+    if without_control != sanitized {
+        changed = true;
+        score += 1;
+        reasons.insert("control_chars_removed".to_string());
+        sanitized = without_control;
+    }
+
+    let without_zero_width = ZERO_WIDTH_RE.replace_all(&sanitized, "").to_string();
+    if without_zero_width != sanitized {
+        changed = true;
+        score += 1;
+        reasons.insert("zero_width_removed".to_string());
+        sanitized = without_zero_width;
+    }
+
+    let markup = strip_markup(&sanitized);
+    if !markup.kinds.is_empty() {
+        changed = true;
+        for kind in &markup.kinds {
+            reasons.insert(format!("markup_removed:{}", kind.as_str()));
+        }
+        if markup.kinds.contains(&MarkupKind::Script) {
+            score += 2;
+        }
+        matches.extend(markup.matches);
+        sanitized = markup.text;
+    }
+
+    // Built before whitespace compaction: wider gaps separate spaced-out words.
+    let view = detection_view(&sanitized);
+
+    let compact_whitespace = MULTI_SPACE_RE.replace_all(&sanitized, " ").to_string();
+    if compact_whitespace != sanitized {
+        changed = true;
+        reasons.insert("whitespace_compacted".to_string());
+        sanitized = compact_whitespace;
+    }
+
+    let trimmed = sanitized.trim().to_string();
+    if trimmed != sanitized {
+        changed = true;
+        reasons.insert("trimmed".to_string());
+        sanitized = trimmed;
+    }
+
+    let char_count = sanitized.chars().count();
+    if char_count > comment_max_chars {
+        changed = true;
+        score += 2;
+        reasons.insert("max_length_truncated".to_string());
+        sanitized = sanitized
+            .chars()
+            .take(comment_max_chars)
+            .collect::<String>();
+    }
+
+    let detection_rules: Vec<(&CompiledRule, RuleAction)> = rules
+        .rules
+        .iter()
+        .map(|rule| (rule, policy.action_of(rule)))
+        .filter(|(_, action)| action.is_detection())
+        .collect();
+    let mut scored_rules = HashSet::new();
+    let mut note_hit = |action: RuleAction| match action {
+        RuleAction::Block => matched_block_rule = true,
+        RuleAction::Quarantine => matched_quarantine_rule = true,
+        RuleAction::Flag | RuleAction::Redact => {}
+    };
+    for &(rule, action) in &detection_rules {
+        let label = hit_label(action);
+        let reason = if rule.regex.is_match(&sanitized) {
+            record_spans(&mut matches, rule, &sanitized, label, "text");
+            format!("{}:{}", label, rule.spec.id)
+        } else if rule.regex.is_match(&view) {
+            record_spans(&mut matches, rule, &view, label, "normalized");
+            format!("{}:{}@normalized", label, rule.spec.id)
+        } else {
+            continue;
+        };
+        note_hit(action);
+        score += rule.spec.weight;
+        scored_rules.insert(rule.spec.id.as_str());
+        reasons.insert(reason);
+    }
+
+    let mut decoded_scan = DecodedScan::new(&detection_rules);
+    decoded_scan.scan(&sanitized, "", 1, false);
+    matches.append(&mut decoded_scan.matches);
+    for (rule, action, encodings) in decoded_scan.hits {
+        note_hit(action);
+        if scored_rules.insert(rule.spec.id.as_str()) {
+            score += rule.spec.weight;
+        }
+        reasons.insert(format!(
+            "{}:{}@decoded:{}",
+            hit_label(action),
+            rule.spec.id,
+            encodings
+        ));
+    }
+    let matched_detection_rule = !scored_rules.is_empty();
+
+    for rule in rules
+        .rules
+        .iter()
+        .filter(|rule| policy.action_of(rule) == RuleAction::Redact)
+    {
+        record_spans(&mut matches, rule, &sanitized, "redacted", "text");
+        let updated = rule
+            .regex
+            .replace_all(&sanitized, rule.replacement.as_str())
+            .to_string();
+        if updated != sanitized {
+            changed = true;
+            score += rule.spec.weight;
+            reasons.insert(format!("redacted:{}", rule.spec.id));
+            sanitized = updated;
+        }
+    }
+
+    let pii = redact_pii(&sanitized);
+    if !pii.categories.is_empty() {
+        changed = true;
+        for category in pii.categories {
+            reasons.insert(format!("pii_redacted:{}", category.as_str()));
+        }
+        matches.extend(pii.matches);
+        sanitized = pii.text;
+    }
+
+    if sanitized.is_empty() {
+        changed = true;
+        score += 1;
+        reasons.insert("empty_after_scrub".to_string());
+        sanitized = EMPTY_COMMENT_TOKEN.to_string();
+    }
+
+    // A block rule outranks the block score, which outranks a quarantine rule.
+    let (disposition, decision) = if matched_block_rule {
+        (Disposition::Block, "blocked_by_detected_injection_rule")
+    } else if score >= thresholds.block {
+        (Disposition::Block, "blocked_by_score_threshold")
+    } else if matched_quarantine_rule {
+        (
+            Disposition::Quarantine,
+            "quarantined_by_detected_injection_rule",
+        )
+    } else if score >= thresholds.quarantine {
+        (Disposition::Quarantine, "quarantined_by_score_threshold")
+    } else {
+        (Disposition::Keep, "")
+    };
+    if matched_detection_rule || disposition != Disposition::Keep {
+        reasons.extend(policy.policy_ids.iter().map(|id| format!("policy:{}", id)));
+    }
+
+    let blocked = disposition != Disposition::Keep;
+    let mut withheld = None;
+    if blocked {
+        changed = true;
+        reasons.insert(decision.to_string());
+        withheld = Some(std::mem::replace(
+            &mut sanitized,
+            BLOCKED_COMMENT_TOKEN.to_string(),
+        ));
+    }
+
+    let sanitized_length = sanitized.chars().count();
+
+    SanitizationOutcome {
+        sanitized,
+        blocked,
+        disposition,
+        changed,
+        score,
+        reasons: reasons.into_iter().collect(),
+        original_length,
+        sanitized_length,
+        matches,
+        withheld,
+    }
+}
+
+/// Reason and match prefix of a detection rule hit.
+fn hit_label(action: RuleAction) -> &'static str {
+    if action == RuleAction::Flag {
+        "flagged"
+    } else {
+        "detected"
+    }
+}
+
+fn record_spans(
+    matches: &mut Vec<RuleMatch>,
+    rule: &CompiledRule,
+    text: &str,
+    action: &str,
+    source: &str,
+) {
+    for found in rule.regex.find_iter(text).take(MAX_SPANS_PER_RULE) {
+        matches.push(RuleMatch::locate(
+            text,
+            found.range(),
+            &rule.spec.id,
+            action,
+            source,
+        ));
+    }
+}
+
+/// State of one comment's walk through its embedded payloads.
+struct DecodedScan<'r> {
+    /// Detection rules with the action the policy gives them.
+    rules: &'r [(&'r CompiledRule, RuleAction)],
+    /// Segments that may still be decoded, see [`MAX_SEGMENTS`].
+    budget: usize,
+    /// Rules that matched, with the encoding path, e.g. `url+base64`.
+    hits: Vec<(&'r CompiledRule, RuleAction, String)>,
+    matches: Vec<RuleMatch>,
+}
+
+impl<'r> DecodedScan<'r> {
+    fn new(rules: &'r [(&'r CompiledRule, RuleAction)]) -> Self {
+        DecodedScan {
+            rules,
+            budget: MAX_SEGMENTS,
+            hits: Vec::new(),
+            matches: Vec::new(),
+        }
+    }
+
+    /// Decodes embedded payloads of `text` and runs the detection rules on each result and its
+    /// detection view, then recurses into the result. `chain` is the encoding path so far.
+    fn scan(&mut self, text: &str, chain: &str, depth: usize, skip_rot13: bool) {
+        if depth > MAX_DECODE_DEPTH || self.budget == 0 {
+            return;
+        }
+
+        let segments = decode_segments(text, skip_rot13, self.budget);
+        self.budget -= segments.len();
+        for segment in segments {
+            let encodings = if chain.is_empty() {
+                segment.encoding.to_string()
+            } else {
+                format!("{}+{}", chain, segment.encoding)
+            };
+            let decoded = clean_decoded_text(&segment.text);
+            let view = detection_view(&decoded);
+            let source = format!("decoded:{}", encodings);
+            for &(rule, action) in self.rules {
+                let label = hit_label(action);
+                if rule.regex.is_match(&decoded) {
+                    record_spans(&mut self.matches, rule, &decoded, label, &source);
+                } else if rule.regex.is_match(&view) {
+                    let view_source = format!("{}@normalized", source);
+                    record_spans(&mut self.matches, rule, &view, label, &view_source);
+                } else {
+                    continue;
+                }
+                self.hits.push((rule, action, encodings.clone()));
+            }
+            let rotated = skip_rot13 || segment.encoding == Encoding::Rot13;
+            self.scan(&decoded, &encodings, depth + 1, rotated);
+        }
+    }
+}
+
+/// The cleanup steps of [`sanitize_comment_text`] that matter for matching: NFKC, control and
+/// zero-width characters.
+fn clean_decoded_text(decoded: &str) -> String {
+    let normalized = decoded.nfkc().collect::<String>();
+    let without_control = CONTROL_CHAR_RE.replace_all(&normalized, "");
+    ZERO_WIDTH_RE.replace_all(&without_control, "").to_string()
+}
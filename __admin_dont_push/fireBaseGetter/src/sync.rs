@@ -0,0 +1,226 @@
+//! Incremental sync: the persisted watermark and merging of newly fetched documents.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::RunOptions;
+use crate::decode::{decode_raw_document_with, string_field, DecodeMode};
+use crate::offline::{load_offline_input, OfflineInput};
+use crate::quarantine::WithheldComment;
+use crate::report::{map_decoded_document, DocumentReview};
+use crate::sanitize::CommentSanitizer;
+
+/// Persisted between runs so `--incremental` only asks Firestore for newer documents.
+///
+/// The query only finds documents whose `watermark_field` is at or after `newest_watermark`, the
+/// newest value of that same field seen so far. Edited documents and documents without that field
+/// are picked up by the next full download, which `--incremental` starts by itself every
+/// `full_sync_interval_hours`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncState {
+    pub project_id: String,
+    pub collection: String,
+    /// Empty in state files whose watermark came from `createTime`, which forces a full download.
+    #[serde(default)]
+    pub watermark_field: String,
+    #[serde(alias = "newestCreateTime")]
+    pub newest_watermark: String,
+    /// `0` in state files written before full runs were tracked, which forces one.
+    #[serde(default)]
+    pub last_full_sync_unix: u64,
+    pub synced_at_unix: u64,
+}
+
+/// Returns the watermark state and the already mapped documents an incremental run can build on.
+/// Falls back to a full download (None) when the state belongs to another collection or watermark
+/// field, the last full download is older than `full_sync_interval_hours`, or the previous output
+/// JSON is missing, because a partial fetch alone would drop older feedback.
+pub fn incremental_base<'a>(
+    options: &RunOptions,
+    state: &'a SyncState,
+) -> Option<(&'a SyncState, Vec<Value>)> {
+    if state.collection != options.settings.collection
+        || state.watermark_field != options.settings.watermark_field
+        || state.newest_watermark.is_empty()
+    {
+        return None;
+    }
+    let interval_hours = u64::from(options.settings.full_sync_interval_hours);
+    if interval_hours > 0
+        && unix_now().saturating_sub(state.last_full_sync_unix) >= interval_hours * 3600
+    {
+        return None;
+    }
+    if let Some(project_id) = options.settings.project_id.as_deref() {
+        if project_id != state.project_id {
+            return None;
+        }
+    }
+
+    match load_offline_input(&options.output_path).ok()? {
+        OfflineInput::Output(payload) => {
+            if string_field(&payload, "projectId") != state.project_id {
+                return None;
+            }
+            let documents = payload
+                .get("documents")
+                .and_then(Value::as_array)
+                .cloned()?;
+            Some((state, documents))
+        }
+        OfflineInput::Raw { .. } => None,
+    }
+}
+
+/// Documents, reviews and blocked comments after an incremental merge.
+#[derive(Debug)]
+pub struct MergedDocuments {
+    pub documents: Vec<Value>,
+    pub reviews: Vec<DocumentReview>,
+    /// Only from the fetched documents; kept ones are already in the quarantine.
+    pub withheld: Vec<WithheldComment>,
+}
+
+/// Replaces existing documents by id with freshly fetched ones and keeps the rest. Reviews of
+/// kept documents come from `existing_reviews`, the fetched ones are reviewed anew.
+///
+/// The query cannot report deletions, so documents deleted in Firestore stay in the output until
+/// the next full download rebuilds it from the whole collection.
+pub fn merge_mapped_documents(
+    existing: Vec<Value>,
+    existing_reviews: Vec<DocumentReview>,
+    fetched: &[Value],
+    mode: DecodeMode,
+    sanitizer: &dyn CommentSanitizer,
+) -> MergedDocuments {
+    let mut by_id: BTreeMap<String, Value> = BTreeMap::new();
+    for doc in existing {
+        by_id.insert(string_field(&doc, "id"), doc);
+    }
+    let mut reviews_by_id: BTreeMap<String, DocumentReview> = existing_reviews
+        .into_iter()
+        .filter(|review| by_id.contains_key(&review.id))
+        .map(|review| (review.id.clone(), review))
+        .collect();
+    let mut withheld = Vec::new();
+    for raw_doc in fetched {
+        let mapped = map_decoded_document(decode_raw_document_with(raw_doc, mode), sanitizer);
+        let id = string_field(&mapped.value, "id");
+        match mapped.review {
+            Some(review) => reviews_by_id.insert(id.clone(), review),
+            None => reviews_by_id.remove(&id),
+        };
+        withheld.extend(mapped.withheld);
+        by_id.insert(id, mapped.value);
+    }
+
+    let mut merged: Vec<Value> = by_id.into_values().collect();
+    merged.sort_by(|a, b| {
+        timestamp_sort_key(&string_field(a, "createTime"))
+            .cmp(&timestamp_sort_key(&string_field(b, "createTime")))
+            .then_with(|| string_field(a, "id").cmp(&string_field(b, "id")))
+    });
+    MergedDocuments {
+        documents: merged,
+        reviews: reviews_by_id.into_values().collect(),
+        withheld,
+    }
+}
+
+impl SyncState {
+    /// The state after a run; `full_sync` is `true` when the whole collection was downloaded. The
+    /// watermark is the newest `watermark_field` timestamp of the fetched raw documents, the same
+    /// field the incremental query filters on. Raw documents, because the field policy may withhold
+    /// the field from the mapped ones.
+    pub fn from_fetched_documents(
+        project_id: &str,
+        collection: &str,
+        watermark_field: &str,
+        raw_docs: &[Value],
+        previous: Option<&SyncState>,
+        full_sync: bool,
+    ) -> Self {
+        let previous = previous.filter(|p| {
+            p.project_id == project_id
+                && p.collection == collection
+                && p.watermark_field == watermark_field
+        });
+        let newest_watermark = raw_docs
+            .iter()
+            .filter_map(|doc| watermark_value(doc, watermark_field))
+            .chain(previous.map(|p| p.newest_watermark.as_str()))
+            .filter(|v| !v.is_empty())
+            .max_by_key(|v| timestamp_sort_key(v))
+            .unwrap_or_default()
+            .to_string();
+
+        let now = unix_now();
+        SyncState {
+            project_id: project_id.to_string(),
+            collection: collection.to_string(),
+            watermark_field: watermark_field.to_string(),
+            newest_watermark,
+            last_full_sync_unix: if full_sync {
+                now
+            } else {
+                previous.map(|p| p.last_full_sync_unix).unwrap_or_default()
+            },
+            synced_at_unix: now,
+        }
+    }
+}
+
+/// The `timestampValue` at the dotted Firestore field path `field` of a raw REST document.
+fn watermark_value<'a>(raw_doc: &'a Value, field: &str) -> Option<&'a str> {
+    let mut keys = field.split('.');
+    let first = raw_doc.get("fields")?.get(keys.next()?)?;
+    keys.try_fold(first, |value, key| {
+        value.get("mapValue")?.get("fields")?.get(key)
+    })?
+    .get("timestampValue")?
+    .as_str()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn read_sync_state(path: &Path) -> Result<Option<SyncState>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed to read sync state {}", path.display()))?;
+    let state = serde_json::from_str(&raw)
+        .with_context(|| format!("failed to parse sync state {}", path.display()))?;
+    Ok(Some(state))
+}
+
+pub fn write_sync_state(path: &Path, state: &SyncState) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| {
+            format!("failed to create sync state directory {}", parent.display())
+        })?;
+    }
+    let serialized = serde_json::to_vec_pretty(state).context("failed to serialize sync state")?;
+    fs::write(path, serialized)
+        .with_context(|| format!("failed to write sync state {}", path.display()))
+}
+
+/// Makes RFC 3339 UTC timestamps comparable as strings by padding the fraction to nanoseconds
+/// (Firestore omits trailing zeros, so `...:05Z` would otherwise sort after `...:05.1Z`).
+pub(crate) fn timestamp_sort_key(timestamp: &str) -> String {
+    let trimmed = timestamp.trim().trim_end_matches('Z');
+    let (seconds, fraction) = trimmed.split_once('.').unwrap_or((trimmed, ""));
+    format!("{}.{:0<9}", seconds, fraction)
+}
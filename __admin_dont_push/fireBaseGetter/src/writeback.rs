@@ -0,0 +1,85 @@
+//! Opt-in write-back: marks exported feedback documents in Firestore as processed.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::decode::string_field;
+use crate::export::FeedbackRoute;
+use crate::lossless::encode_firestore_fields;
+
+/// Fields set from the export; `processedAt` is the server's commit time.
+pub const PROCESSED_FIELD_MASK: &[&str] = &["processedBy", "sanitizerVersion", "exportPath"];
+pub const PROCESSED_AT_FIELD: &str = "processedAt";
+
+/// The writes for one run, before they are committed.
+#[derive(Debug, Default)]
+pub struct WriteBackPlan {
+    pub writes: Vec<Value>,
+    /// Exported documents already marked with the same export path and sanitizer version.
+    pub already_processed: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteBackSummary {
+    pub dry_run: bool,
+    pub planned_writes: usize,
+    pub already_processed: usize,
+    pub committed_writes: usize,
+    pub commits: usize,
+    /// On a dry run, `{"writes": [...]}` per commit that would be sent; not stored in the output JSON.
+    #[serde(skip)]
+    pub planned_commits: Vec<Value>,
+}
+
+/// One masked update per exported document. The update requires the document to still exist.
+pub fn plan_processed_writes(
+    mapped_docs: &[Value],
+    routes: &[FeedbackRoute],
+    processed_by: &str,
+) -> Result<WriteBackPlan> {
+    let docs_by_id: HashMap<String, &Value> = mapped_docs
+        .iter()
+        .map(|doc| (string_field(doc, "id"), doc))
+        .collect();
+    let mut plan = WriteBackPlan::default();
+    for route in routes.iter().filter(|route| route.outcome == "exported") {
+        let (Some(doc), Some(export_path)) =
+            (docs_by_id.get(&route.id), route.written_path.as_deref())
+        else {
+            continue;
+        };
+        let name = string_field(doc, "name");
+        if name.is_empty() {
+            continue;
+        }
+        let sanitizer_version = doc
+            .get("commentSecurity")
+            .map(|security| string_field(security, "sanitizerVersion"))
+            .unwrap_or_default();
+        let data = doc.get("data").cloned().unwrap_or_default();
+        if string_field(&data, "exportPath") == export_path
+            && string_field(&data, "sanitizerVersion") == sanitizer_version
+        {
+            plan.already_processed += 1;
+            continue;
+        }
+
+        let fields = encode_firestore_fields(&json!({
+            "processedBy": processed_by,
+            "sanitizerVersion": sanitizer_version,
+            "exportPath": export_path,
+        }))
+        .with_context(|| format!("failed to encode write-back fields for {}", name))?;
+        plan.writes.push(json!({
+            "update": { "name": name, "fields": fields },
+            "updateMask": { "fieldPaths": PROCESSED_FIELD_MASK },
+            "updateTransforms": [{ "fieldPath": PROCESSED_AT_FIELD, "setToServerValue": "REQUEST_TIME" }],
+            "currentDocument": { "exists": true }
+        }));
+    }
+    Ok(plan)
+}
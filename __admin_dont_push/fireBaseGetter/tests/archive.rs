@@ -0,0 +1,406 @@
+//! Archive selection, the gzip NDJSON file and its verification, and the planned writes.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use firebase_getter::archive::{
+    archive_bytes, archive_file_name, format_utc_timestamp, gunzip, plan_archive_copies,
+    plan_archive_deletes, verify_archive, verify_archive_copies, ArchivedDocument,
+};
+use firebase_getter::quarantine::parse_key;
+use firebase_getter::{
+    resolve_run_options, ArchiveRequest, DocumentWriter, Invocation, Pipeline, RawDocuments,
+    SettingsLayer,
+};
+use serde_json::{json, Value};
+
+const PREFIX: &str = "projects/demo/databases/(default)/documents/feedback_all_games";
+/// 2026-03-01T10:15:00Z
+const NOW: u64 = 1_772_360_100;
+const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn raw(id: &str, create_time: &str, fields: Value) -> Value {
+    json!({
+        "name": format!("{}/{}", PREFIX, id),
+        "fields": fields,
+        "createTime": create_time,
+        "updateTime": "2026-03-01T09:00:00.123456Z"
+    })
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "fireBaseGetter_archive_{}_{}",
+        std::process::id(),
+        name
+    ));
+    fs::create_dir_all(&dir).expect("temp dir");
+    dir
+}
+
+/// Records the writes of every commit and reads updated documents back, unless `lose_updates`.
+struct RecordingWriter<'a> {
+    commits: &'a RefCell<Vec<Vec<Value>>>,
+    lose_updates: bool,
+}
+
+impl DocumentWriter for RecordingWriter<'_> {
+    fn project_id(&self) -> Result<String> {
+        Ok("demo".to_string())
+    }
+
+    fn commit(&self, writes: &[Value]) -> Result<()> {
+        self.commits.borrow_mut().push(writes.to_vec());
+        Ok(())
+    }
+
+    fn get_documents(&self, names: &[String]) -> Result<Vec<Value>> {
+        if self.lose_updates {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .commits
+            .borrow()
+            .iter()
+            .flatten()
+            .filter_map(|write| write.get("update"))
+            .filter(|update| names.iter().any(|name| update["name"] == **name))
+            .cloned()
+            .collect())
+    }
+}
+
+#[test]
+fn utc_timestamps_and_file_names() {
+    assert_eq!(format_utc_timestamp(0), "1970-01-01T00:00:00Z");
+    assert_eq!(format_utc_timestamp(951_868_799), "2000-02-29T23:59:59Z");
+    assert_eq!(format_utc_timestamp(NOW), "2026-03-01T10:15:00Z");
+    assert_eq!(
+        archive_file_name("feedback_all_games", NOW, false),
+        "feedback_all_games_20260301T101500Z.ndjson.gz"
+    );
+    assert_eq!(
+        archive_file_name("feedback_all_games", NOW, true),
+        "feedback_all_games_20260301T101500Z.ndjson.gz.enc"
+    );
+}
+
+#[test]
+fn gzip_round_trips_and_detects_damage() {
+    let documents: Vec<ArchivedDocument> = (0..2_000)
+        .map(|i| {
+            ArchivedDocument::from_raw(&raw(&format!("d{}", i), "2025-11-01T08:00:00Z", json!({})))
+        })
+        .collect();
+    for documents in [&documents[..0], &documents[..1], &documents] {
+        let text = String::from_utf8(
+            gunzip(&archive_bytes(documents, None).expect("compresses")).expect("reads back"),
+        )
+        .unwrap();
+        assert_eq!(text.lines().count(), documents.len());
+    }
+
+    let bytes = archive_bytes(&documents, None).expect("compresses");
+    let mut damaged = bytes.clone();
+    let crc_offset = damaged.len() - 8;
+    damaged[crc_offset] ^= 0x01;
+    assert!(gunzip(&damaged).is_err());
+    assert!(gunzip(&bytes[..bytes.len() - 1]).is_err());
+    assert!(gunzip(b"not gzip").is_err());
+}
+
+#[test]
+fn selection_by_processed_marker_and_age() {
+    let documents = vec![
+        raw(
+            "old",
+            "2025-11-01T08:00:00Z",
+            json!({ "comment": { "stringValue": "alt" } }),
+        ),
+        raw(
+            "processed",
+            "2026-02-28T08:00:00.5Z",
+            json!({ "processedAt": { "timestampValue": "2026-02-28T09:00:00Z" } }),
+        ),
+        raw(
+            "fresh",
+            "2026-02-28T08:00:00Z",
+            json!({ "processedAt": { "nullValue": null } }),
+        ),
+        raw("boundary", "2025-12-01T10:15:00.000001Z", json!({})),
+    ];
+    let ids = |request: ArchiveRequest| -> Vec<String> {
+        request
+            .select(&documents, NOW)
+            .iter()
+            .map(|doc| {
+                doc["name"]
+                    .as_str()
+                    .unwrap()
+                    .rsplit('/')
+                    .next()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    };
+
+    let processed = ArchiveRequest {
+        processed: true,
+        ..ArchiveRequest::default()
+    };
+    assert_eq!(ids(processed), vec!["processed"]);
+    let old = ArchiveRequest {
+        older_than_days: Some(90),
+        ..ArchiveRequest::default()
+    };
+    assert_eq!(ids(old), vec!["old"]);
+    let either = ArchiveRequest {
+        processed: true,
+        older_than_days: Some(90),
+        ..ArchiveRequest::default()
+    };
+    assert_eq!(ids(either), vec!["old", "processed"]);
+    assert!(ids(ArchiveRequest::default()).is_empty());
+}
+
+#[test]
+fn archive_is_verified_against_the_fetched_documents() {
+    let documents: Vec<ArchivedDocument> = [
+        raw(
+            "a",
+            "2025-11-01T08:00:00Z",
+            json!({
+                "comment": { "stringValue": "Level 3 ist zu schwer" },
+                "createdAt": { "timestampValue": "2025-11-01T08:00:00Z" },
+                "count": { "integerValue": "007" },
+                "context": { "mapValue": { "fields": { "gameId": { "stringValue": "game1" } } } },
+                "viewport": { "mapValue": { "fields": {} } }
+            }),
+        ),
+        json!({ "name": format!("{}/empty", PREFIX), "createTime": "2025-11-02T08:00:00Z" }),
+    ]
+    .iter()
+    .map(ArchivedDocument::from_raw)
+    .collect();
+
+    let path = temp_dir("verify").join("feedback.ndjson.gz");
+    fs::write(&path, archive_bytes(&documents, None).expect("compresses")).expect("writes");
+    let archived = verify_archive(&path, &documents, None).expect("verifies");
+    assert_eq!(archived.len(), 2);
+    assert_eq!(archived[0].fields["viewport"], json!({ "mapValue": {} }));
+    assert_eq!(archived[1], documents[1]);
+
+    let line = String::from_utf8(gunzip(&fs::read(&path).unwrap()).unwrap()).unwrap();
+    let first: Value = serde_json::from_str(line.lines().next().unwrap()).unwrap();
+    assert_eq!(
+        first["data"]["createdAt"],
+        json!({ "$timestamp": "2025-11-01T08:00:00Z" })
+    );
+
+    // Missing, reordered or changed documents fail the verification.
+    assert!(verify_archive(&path, &documents[..1], None).is_err());
+    let reordered = vec![documents[1].clone(), documents[0].clone()];
+    assert!(verify_archive(&path, &reordered, None).is_err());
+    let mut changed = documents.clone();
+    changed[0].update_time = "2026-03-01T10:00:00Z".to_string();
+    assert!(verify_archive(&path, &changed, None).is_err());
+
+    // An encrypted archive needs the key to be verified.
+    let key = parse_key(KEY).expect("key");
+    let sealed = archive_bytes(&documents, Some(&key)).expect("encrypts");
+    assert!(
+        gunzip(&sealed).is_err(),
+        "the archive is not readable without the key"
+    );
+    fs::write(&path, sealed).expect("writes");
+    assert_eq!(
+        verify_archive(&path, &documents, Some(&key)).expect("verifies"),
+        archived
+    );
+    let error = verify_archive(&path, &documents, None).expect_err("key required");
+    assert!(
+        error.to_string().contains("FIREBASE_GETTER_QUARANTINE_KEY"),
+        "{:#}",
+        error
+    );
+    let other_key = parse_key(&KEY.replace("1f", "ff")).expect("key");
+    assert!(verify_archive(&path, &documents, Some(&other_key)).is_err());
+
+    fs::remove_dir_all(path.parent().unwrap()).ok();
+}
+
+#[test]
+fn copies_keep_the_id_and_deletes_require_the_archived_version() {
+    let documents = vec![ArchivedDocument::from_raw(&raw(
+        "d1",
+        "2025-11-01T08:00:00Z",
+        json!({ "comment": { "stringValue": "Gut" } }),
+    ))];
+
+    let copies = plan_archive_copies(&documents, "feedback_archive").expect("plans");
+    assert_eq!(
+        copies,
+        vec![json!({ "update": {
+            "name": "projects/demo/databases/(default)/documents/feedback_archive/d1",
+            "fields": { "comment": { "stringValue": "Gut" } }
+        } })]
+    );
+
+    let deletes = plan_archive_deletes(&documents);
+    assert_eq!(
+        deletes,
+        vec![json!({
+            "delete": format!("{}/d1", PREFIX),
+            "currentDocument": { "updateTime": "2026-03-01T09:00:00.123456Z" }
+        })]
+    );
+
+    let unnamed = vec![ArchivedDocument::from_raw(&json!({ "fields": {} }))];
+    assert!(plan_archive_copies(&unnamed, "feedback_archive").is_err());
+
+    // Read back, a copy must exist with the same fields; Firestore adds its own metadata.
+    let stored = |fields: Value| {
+        json!({
+            "name": "projects/demo/databases/(default)/documents/feedback_archive/d1",
+            "fields": fields,
+            "createTime": "2026-03-01T10:15:01Z",
+            "updateTime": "2026-03-01T10:15:01Z"
+        })
+    };
+    verify_archive_copies(
+        &documents,
+        "feedback_archive",
+        &[stored(json!({ "comment": { "stringValue": "Gut" } }))],
+    )
+    .expect("matching copy");
+    assert!(verify_archive_copies(&documents, "feedback_archive", &[]).is_err());
+    let changed = stored(json!({ "comment": { "stringValue": "Schlecht" } }));
+    assert!(verify_archive_copies(&documents, "feedback_archive", &[changed]).is_err());
+    let elsewhere = stored(json!({ "comment": { "stringValue": "Gut" } }));
+    assert!(verify_archive_copies(&documents, "feedback_other", &[elsewhere]).is_err());
+}
+
+#[test]
+fn pipeline_deletes_only_with_confirmation() {
+    let repo_root = temp_dir("pipeline");
+    let archive_dir = temp_dir("pipeline_archive");
+    let options = resolve_run_options(Invocation {
+        repo_root: Some(repo_root.clone()),
+        overrides: SettingsLayer {
+            archive_dir: Some(archive_dir.to_string_lossy().to_string()),
+            archive_collection: Some("feedback_archive".to_string()),
+            ..SettingsLayer::default()
+        },
+        ..Invocation::default()
+    })
+    .expect("options");
+    let documents = vec![
+        raw(
+            "old",
+            "2025-11-01T08:00:00Z",
+            json!({ "comment": { "stringValue": "alt" } }),
+        ),
+        raw(
+            "new",
+            "2026-10-01T08:00:00Z",
+            json!({ "comment": { "stringValue": "neu" } }),
+        ),
+    ];
+    let run = |confirm_delete: bool, lose_updates: bool| -> (Result<()>, Vec<Vec<Value>>) {
+        let commits = RefCell::new(Vec::new());
+        let result = Pipeline::new(&options)
+            .expect("pipeline")
+            .with_source(RawDocuments {
+                project_id: "demo".to_string(),
+                documents: documents.clone(),
+            })
+            .with_writer(RecordingWriter {
+                commits: &commits,
+                lose_updates,
+            })
+            .run_archive(&ArchiveRequest {
+                older_than_days: Some(90),
+                confirm_delete,
+                ..ArchiveRequest::default()
+            })
+            .map(|_| ());
+        (result, commits.into_inner())
+    };
+
+    let (result, commits) = run(false, false);
+    result.expect("archives");
+    assert_eq!(commits.len(), 1);
+    assert_eq!(
+        commits[0][0]["update"]["name"],
+        "projects/demo/databases/(default)/documents/feedback_archive/old"
+    );
+
+    let (result, commits) = run(true, false);
+    result.expect("archives");
+    assert_eq!(commits.len(), 2);
+    assert_eq!(
+        commits[1],
+        plan_archive_deletes(&[ArchivedDocument::from_raw(&documents[0])])
+    );
+    assert!(
+        fs::read_dir(&options.archive_dir)
+            .expect("archive dir")
+            .count()
+            >= 1
+    );
+
+    // Copies that cannot be read back are unverified: nothing is deleted.
+    let (result, commits) = run(true, true);
+    let error = result.expect_err("unverified copies");
+    assert!(
+        error.to_string().contains("nothing was deleted"),
+        "{:#}",
+        error
+    );
+    assert_eq!(commits.len(), 1);
+
+    fs::remove_dir_all(repo_root).ok();
+    fs::remove_dir_all(archive_dir).ok();
+}
+
+#[test]
+fn unencrypted_archive_inside_the_repo_is_refused() {
+    let repo_root = temp_dir("inside");
+    let options = resolve_run_options(Invocation {
+        repo_root: Some(repo_root.clone()),
+        overrides: SettingsLayer {
+            archive_dir: Some("archive".to_string()),
+            ..SettingsLayer::default()
+        },
+        ..Invocation::default()
+    })
+    .expect("options");
+    assert_eq!(options.archive_dir, repo_root.join("archive"));
+
+    let commits = RefCell::new(Vec::new());
+    let error = Pipeline::new(&options)
+        .expect("pipeline")
+        .with_source(RawDocuments {
+            project_id: "demo".to_string(),
+            documents: vec![raw("old", "2025-11-01T08:00:00Z", json!({}))],
+        })
+        .with_writer(RecordingWriter {
+            commits: &commits,
+            lose_updates: false,
+        })
+        .run_archive(&ArchiveRequest {
+            older_than_days: Some(90),
+            confirm_delete: true,
+            ..ArchiveRequest::default()
+        })
+        .expect_err("unencrypted archive inside the repo");
+    assert!(error.to_string().contains("inside the repo"), "{:#}", error);
+    assert!(commits.borrow().is_empty());
+    assert!(!options.archive_dir.exists());
+
+    fs::remove_dir_all(repo_root).ok();
+}
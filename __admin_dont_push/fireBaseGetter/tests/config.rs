@@ -0,0 +1,203 @@
+//! Settings layers (defaults < TOML < `FIREBASE_GETTER_*` < CLI) and the resolved run paths.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use firebase_getter::{resolve_run_options, Invocation, Settings, SettingsLayer};
+
+/// The environment is shared by all tests of this file.
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+const CONFIG_FILE: &str = "__admin_dont_push/fireBaseGetter/fireBaseGetter.toml";
+
+fn temp_repo(name: &str, config: Option<&str>) -> PathBuf {
+    let root = std::env::temp_dir().join(format!(
+        "fireBaseGetter_config_{}_{}",
+        std::process::id(),
+        name
+    ));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join(".git")).expect("temp repo");
+    if let Some(config) = config {
+        let path = root.join(CONFIG_FILE);
+        fs::create_dir_all(path.parent().unwrap()).expect("config dir");
+        fs::write(path, config).expect("config file");
+    }
+    root
+}
+
+fn with_env<T>(vars: &[(&str, &str)], run: impl FnOnce() -> T) -> T {
+    let _guard = ENV_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (key, value) in vars {
+        std::env::set_var(key, value);
+    }
+    let result = run();
+    for (key, _) in vars {
+        std::env::remove_var(key);
+    }
+    result
+}
+
+fn resolve(root: &Path, overrides: SettingsLayer) -> anyhow::Result<firebase_getter::RunOptions> {
+    resolve_run_options(Invocation {
+        repo_root: Some(root.to_path_buf()),
+        overrides,
+        ..Invocation::default()
+    })
+}
+
+#[test]
+fn later_layers_win_field_by_field() {
+    let mut settings = Settings::default();
+    settings.apply(SettingsLayer {
+        page_size: Some(200),
+        collection: Some("from_toml".to_string()),
+        ..SettingsLayer::default()
+    });
+    settings.apply(SettingsLayer {
+        collection: Some("from_env".to_string()),
+        ..SettingsLayer::default()
+    });
+    settings.apply(SettingsLayer::default());
+    assert_eq!(settings.page_size, 200);
+    assert_eq!(settings.collection, "from_env");
+    assert_eq!(
+        settings.comment_max_chars,
+        Settings::default().comment_max_chars
+    );
+}
+
+#[test]
+fn toml_env_and_cli_are_merged_in_order() {
+    let root = temp_repo(
+        "order",
+        Some("collection = \"toml\"\npage_size = 10\ncomment_max_chars = 300\n"),
+    );
+    let options = with_env(
+        &[
+            ("FIREBASE_GETTER_PAGE_SIZE", "20"),
+            ("FIREBASE_GETTER_COLLECTION", "env"),
+        ],
+        || {
+            resolve(
+                &root,
+                SettingsLayer {
+                    collection: Some("cli".to_string()),
+                    ..SettingsLayer::default()
+                },
+            )
+        },
+    )
+    .expect("resolves");
+    assert!(options.config_loaded);
+    assert_eq!(options.settings.collection, "cli");
+    assert_eq!(options.settings.page_size, 20);
+    assert_eq!(options.settings.comment_max_chars, 300);
+    assert_eq!(
+        options.settings.learning_export_subdir,
+        "firebase_feedback_import"
+    );
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn relative_paths_resolve_against_the_repo_root() {
+    let root = temp_repo(
+        "paths",
+        Some("output_path = \"out/feedback.json\"\nstate_path = \"/tmp/state.json\"\n"),
+    );
+    let options = with_env(&[], || {
+        resolve(
+            &root,
+            SettingsLayer {
+                rule_pack: Some("rules/custom.toml".to_string()),
+                ..SettingsLayer::default()
+            },
+        )
+    })
+    .expect("resolves");
+    assert_eq!(options.output_path, root.join("out/feedback.json"));
+    assert_eq!(options.state_path, PathBuf::from("/tmp/state.json"));
+    assert_eq!(options.rule_pack_path, Some(root.join("rules/custom.toml")));
+    assert_eq!(options.config_path, root.join(CONFIG_FILE));
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn blank_archive_collection_means_none() {
+    let mut settings = Settings::default();
+    settings.apply(SettingsLayer {
+        archive_collection: Some(" feedback_archive ".to_string()),
+        ..SettingsLayer::default()
+    });
+    assert_eq!(
+        settings.archive_collection.as_deref(),
+        Some("feedback_archive")
+    );
+    for blank in ["", "  "] {
+        settings.apply(SettingsLayer {
+            archive_collection: Some(blank.to_string()),
+            ..SettingsLayer::default()
+        });
+        assert_eq!(settings.archive_collection, None, "{:?}", blank);
+        settings.validate().expect("no archive collection is valid");
+    }
+}
+
+#[test]
+fn missing_default_config_falls_back_to_defaults() {
+    let root = temp_repo("missing", None);
+    let options = with_env(&[], || resolve(&root, SettingsLayer::default())).expect("resolves");
+    assert!(!options.config_loaded);
+    assert_eq!(options.settings.collection, "feedback_all_games");
+
+    let explicit = with_env(&[], || {
+        resolve_run_options(Invocation {
+            repo_root: Some(root.clone()),
+            config: Some(PathBuf::from("nowhere.toml")),
+            ..Invocation::default()
+        })
+    });
+    assert!(
+        explicit.is_err(),
+        "an explicitly requested config file must exist"
+    );
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn invalid_toml_and_env_values_are_errors() {
+    for (name, config) in [
+        ("syntax", "page_size = \n"),
+        ("unknown_key", "colection = \"typo\"\n"),
+        ("wrong_type", "page_size = \"many\"\n"),
+        ("invalid_value", "page_size = 0\n"),
+        (
+            "thresholds",
+            "quarantine_score_threshold = 20\nblock_score_threshold = 10\n",
+        ),
+        ("export_root", "export_roots = [\"../outside\"]\n"),
+    ] {
+        let root = temp_repo(name, Some(config));
+        assert!(
+            with_env(&[], || resolve(&root, SettingsLayer::default())).is_err(),
+            "{}",
+            name
+        );
+        fs::remove_dir_all(root).ok();
+    }
+
+    let root = temp_repo("env", None);
+    for (key, value) in [
+        ("FIREBASE_GETTER_PAGE_SIZE", "viele"),
+        ("FIREBASE_GETTER_INCREMENTAL", "yes"),
+        ("FIREBASE_GETTER_FIRESTORE_BASE_URL", "ftp://example.org"),
+    ] {
+        let result = with_env(&[(key, value)], || resolve(&root, SettingsLayer::default()));
+        assert!(result.is_err(), "{}={}", key, value);
+    }
+    fs::remove_dir_all(root).ok();
+}
@@ -0,0 +1,368 @@
+//! Learning export routing: the learning folder pattern, candidate paths from context URLs, the
+//! `gameId`/`nodeId` index and the confinement to the export roots.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use firebase_getter::export::{
+    build_game_index, candidate_repo_paths, normalize_url_path, FeedbackRoute,
+    LearningFolderMatcher,
+};
+use firebase_getter::{FeedbackSink, LearningFolderSink, Settings};
+use serde_json::{json, Value};
+
+const GAME: &str = "databases/A/game1";
+const LEARNINGS: &str = "__dokumentation/__04_lernings";
+
+fn temp_repo(name: &str, files: &[&str]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!(
+        "fireBaseGetter_export_{}_{}",
+        std::process::id(),
+        name
+    ));
+    let _ = fs::remove_dir_all(&root);
+    for file in files {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).expect("temp dir");
+        fs::write(path, "{}").expect("temp file");
+    }
+    root
+}
+
+fn feedback(id: &str, folder_path: &str) -> Value {
+    json!({ "id": id, "data": { "comment": "Level 3 ist zu schwer", "context": { "folderPath": folder_path } } })
+}
+
+/// Exports the documents and returns the route of every id.
+fn export(root: &Path, docs: &[Value]) -> Vec<FeedbackRoute> {
+    LearningFolderSink::new(root.to_path_buf(), Settings::default())
+        .export(docs)
+        .expect("exports")
+        .routes
+}
+
+fn assert_rejected(route: &FeedbackRoute, candidate: &str, reason: &str) {
+    assert_eq!(route.outcome, "unresolved", "{}", route.id);
+    assert_eq!(
+        route.reason.as_deref(),
+        Some("all_candidates_rejected"),
+        "{}",
+        route.id
+    );
+    assert_eq!(route.rejected_candidates.len(), 1, "{}", route.id);
+    let rejection = &route.rejected_candidates[0];
+    assert_eq!(
+        (
+            rejection.field.as_str(),
+            rejection.candidate.as_str(),
+            rejection.reason
+        ),
+        ("context.folderPath", candidate, reason),
+        "{}",
+        route.id
+    );
+}
+
+#[test]
+fn learning_folder_patterns() {
+    // (pattern, path relative to the game folder, learning folder of the game, found by discovery)
+    let table: &[(&str, &str, bool, bool)] = &[
+        (
+            "__dokumentation/__04_lernings",
+            "__dokumentation/__04_lernings",
+            true,
+            true,
+        ),
+        (
+            "/__dokumentation/__04_lernings/",
+            "__dokumentation/__04_lernings",
+            true,
+            true,
+        ),
+        (
+            "__dokumentation/__04_lernings",
+            "__dokumentation/__04_lernings/sub",
+            false,
+            false,
+        ),
+        (
+            "__dokumentation/__04_lernings",
+            "x__dokumentation/__04_lernings",
+            false,
+            false,
+        ),
+        (
+            "__dokumentation/__04_lernings",
+            "__dokumentation/__04_lerningsX",
+            false,
+            false,
+        ),
+        (
+            "__dokumentation/__04_lernings",
+            "sub/__dokumentation/__04_lernings",
+            false,
+            true,
+        ),
+        ("*/__04_lernings", "__doku/__04_lernings", true, true),
+        ("*/__04_lernings", "a/b/__04_lernings", false, true),
+        ("**/__04_lernings", "__04_lernings", true, true),
+        ("**/__04_lernings", "a/b/__04_lernings", true, true),
+        (
+            "__doku?entation/__04_lernings",
+            "__dokumentation/__04_lernings",
+            true,
+            true,
+        ),
+        (
+            "__doku?entation/__04_lernings",
+            "__doku/entation/__04_lernings",
+            false,
+            false,
+        ),
+        ("lern.ings", "lernXings", false, false),
+        (
+            "regex:__dokumentation/__0[34]_lern(ing)?s",
+            "__dokumentation/__03_lerns",
+            true,
+            true,
+        ),
+        (
+            "regex:__dokumentation/__0[34]_lern(ing)?s",
+            "__dokumentation/__05_lernings",
+            false,
+            false,
+        ),
+        (
+            "regex:  [^/]+/__04_lernings",
+            "__doku/__04_lernings",
+            true,
+            true,
+        ),
+    ];
+
+    let repo_root = Path::new("/repo");
+    let game_folder = repo_root.join(GAME);
+    for (pattern, relative, of_game, discovered) in table {
+        let matcher = LearningFolderMatcher::from_pattern(pattern).expect(pattern);
+        let path = game_folder.join(relative);
+        assert_eq!(
+            matcher.is_learning_folder_of(&game_folder, &path),
+            *of_game,
+            "{} of the game: {}",
+            pattern,
+            relative
+        );
+        assert_eq!(
+            matcher.is_learning_folder(repo_root, &path),
+            *discovered,
+            "{} during discovery: {}",
+            pattern,
+            relative
+        );
+    }
+}
+
+#[test]
+fn invalid_learning_folder_patterns_are_errors() {
+    for pattern in ["", "  ", "regex:", "regex:(unclosed", "/"] {
+        assert!(
+            LearningFolderMatcher::from_pattern(pattern).is_err(),
+            "{:?}",
+            pattern
+        );
+    }
+}
+
+#[test]
+fn url_paths_lose_the_site_prefix() {
+    let table: &[(&str, &str, Option<&str>)] = &[
+        (
+            "/easyPV/databases/A/game1/index.html",
+            "easyPV",
+            Some("databases/A/game1/index.html"),
+        ),
+        ("/EASYPV/databases/A", "easyPV", Some("databases/A")),
+        ("/easyPV/databases/A", "/easyPV/", Some("databases/A")),
+        ("/easyPV/databases/A", "", Some("easyPV/databases/A")),
+        (
+            "/other/easyPV/databases/A",
+            "easyPV",
+            Some("other/easyPV/databases/A"),
+        ),
+        // A lone prefix segment is a path of its own.
+        ("/easyPV", "easyPV", Some("easyPV")),
+        ("databases/A/", "easyPV", Some("databases/A")),
+        ("../../databases/A", "easyPV", Some("databases/A")),
+        ("./databases\\A", "easyPV", Some("databases/A")),
+        // Inner `..` segments stay for the confinement check.
+        (
+            "/databases/../secret",
+            "easyPV",
+            Some("databases/../secret"),
+        ),
+        ("/", "easyPV", None),
+        ("  ", "easyPV", None),
+    ];
+    for (path, prefix, expected) in table {
+        assert_eq!(
+            normalize_url_path(path, prefix).as_deref(),
+            *expected,
+            "{:?} with prefix {:?}",
+            path,
+            prefix
+        );
+    }
+}
+
+#[test]
+fn context_values_become_candidate_paths() {
+    // (value, URL field, candidates in order)
+    let table: &[(&str, bool, &[&str])] = &[
+        (
+            "https://user.github.io/easyPV/generic_pages/generic_page.html?json=databases/A/game1/_data/_gg01_a.json&game=databases/A/game1/_ghtml01.html",
+            true,
+            &[
+                "databases/A/game1/_ghtml01.html",
+                "databases/A/game1/_data/_gg01_a.json",
+                "generic_pages/generic_page.html",
+            ],
+        ),
+        ("?gameRel=databases%2FA%2Fspiel%20eins", true, &["databases/A/spiel eins"]),
+        (
+            "https://x.io/easyPV/p.html?json=https%3A%2F%2Fx.io%2FeasyPV%2Fdatabases%2FB%2Fg%2Fa.json%3Fgame%3Ddatabases%2FB%2Fg2",
+            true,
+            &["databases/B/g2", "databases/B/g/a.json", "p.html"],
+        ),
+        ("?foo=databases/A/game1", true, &[]),
+        ("/easyPV/databases/A%20B/game1", true, &["databases/A B/game1"]),
+        // Path fields keep absolute paths so that confinement rejects them.
+        ("/etc/passwd", false, &["/etc/passwd"]),
+        ("./databases//A/./game1", false, &["databases/A/game1"]),
+        ("  ", true, &[]),
+    ];
+    for (value, is_url_field, expected) in table {
+        assert_eq!(
+            candidate_repo_paths(value, *is_url_field, "easyPV", 0),
+            expected.to_vec(),
+            "{}",
+            value
+        );
+    }
+}
+
+#[test]
+fn game_index_resolves_unique_ids_only() {
+    let root = temp_repo(
+        "index",
+        &[
+            "databases/A/game1/_ghtml01_quiz.html",
+            "databases/B/\u{dc}bung Eins/_data/_gg01_config.json",
+            "databases/C/x/_ghtml_same.html",
+            "databases/D/x/_ghtml_same.html",
+            "databases/E/a-b/_gjs01.js",
+            "databases/E/a_b/_gjs01.js",
+            "databases/F/plain/readme.txt",
+        ],
+    );
+    let index = build_game_index(&root, &[root.join("databases")]).expect("indexes");
+    assert_eq!(index.game_folders, 6);
+
+    let folder = |relative: &str| Some(root.join("databases").join(relative));
+    // (gameId or nodeId, folder)
+    let table: &[(&str, Option<PathBuf>)] = &[
+        ("databases/A/game1", folder("A/game1")),
+        ("databases_a_game1", folder("A/game1")),
+        ("  databases/A/game1 ", folder("A/game1")),
+        ("folder_databases_a_game1", folder("A/game1")),
+        ("databases/A/game1/_ghtml01_quiz", folder("A/game1")),
+        // A game moved to another folder still ends in its file name.
+        ("databases_old_place_ghtml01_quiz", folder("A/game1")),
+        ("ghtml01_quiz", folder("A/game1")),
+        ("folder_databases_b_ubung_eins", folder("B/\u{dc}bung Eins")),
+        (
+            "databases/B/\u{dc}bung Eins/_data/_gg01_config",
+            folder("B/\u{dc}bung Eins"),
+        ),
+        // Shared by two folders: never guessed.
+        ("ghtml_same", None),
+        ("databases_z_ghtml_same", None),
+        ("databases/E/a_b", None),
+        // The node id is exact; its slug is shared like the folder slugs.
+        ("folder_databases_e_a-b", folder("E/a-b")),
+        ("folder_databases_e_a_b", None),
+        ("databases/F/plain", None),
+        ("unknown", None),
+        ("", None),
+    ];
+    for (identity, expected) in table {
+        assert_eq!(&index.lookup(identity), expected, "{:?}", identity);
+    }
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn candidates_leaving_the_export_roots_are_rejected() {
+    let root = temp_repo(
+        "confine",
+        &[
+            &format!("{}/_ghtml01.html", GAME),
+            &format!("{}/{}/notes.md", GAME, LEARNINGS),
+            &format!("outside/game/{}/notes.md", LEARNINGS),
+        ],
+    );
+    let routes = export(
+        &root,
+        &[
+            feedback("inside", GAME),
+            feedback("absolute", &root.join("outside/game").to_string_lossy()),
+            feedback("traversal", "databases/../outside/game"),
+            feedback("outside", "outside/game"),
+        ],
+    );
+
+    assert_eq!(routes[0].outcome, "exported");
+    assert_eq!(
+        routes[0].learning_folder.as_deref(),
+        Some(&*format!("{}/{}", GAME, LEARNINGS))
+    );
+    assert_rejected(
+        &routes[1],
+        &root.join("outside/game").to_string_lossy(),
+        "absolute_path",
+    );
+    assert_rejected(&routes[2], "databases/../outside/game", "parent_traversal");
+    assert_rejected(&routes[3], "outside/game", "outside_export_roots");
+    assert!(!root
+        .join("outside/game")
+        .join(LEARNINGS)
+        .join("firebase_feedback_import")
+        .exists());
+    fs::remove_dir_all(root).ok();
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_learning_folders_outside_the_export_roots_are_rejected() {
+    let root = temp_repo(
+        "symlink",
+        &[
+            "databases/B/game2/_ghtml01.html",
+            &format!("outside/{}/notes.md", LEARNINGS),
+        ],
+    );
+    std::os::unix::fs::symlink(
+        root.join("outside/__dokumentation"),
+        root.join("databases/B/game2/__dokumentation"),
+    )
+    .expect("symlink");
+    let candidate = format!("databases/B/game2/{}", LEARNINGS);
+    let routes = export(&root, &[feedback("symlink", &candidate)]);
+
+    assert_rejected(&routes[0], &candidate, "symlink_escape");
+    assert!(!root
+        .join("outside")
+        .join(LEARNINGS)
+        .join("firebase_feedback_import")
+        .exists());
+    fs::remove_dir_all(root).ok();
+}
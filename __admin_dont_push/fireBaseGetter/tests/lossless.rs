@@ -0,0 +1,160 @@
+//! Lossless Firestore decoding and the encoder back to REST JSON.
+
+use firebase_getter::decode::{decode_raw_document_with, DecodeMode};
+use firebase_getter::fields::{FieldPolicy, WITHHELD_FIELD_TOKEN};
+use firebase_getter::lossless::{
+    decode_lossless_fields, decode_lossless_value, encode_firestore_fields, encode_firestore_value,
+};
+use firebase_getter::model::parse_feedback;
+use firebase_getter::sanitize::sanitize_document_fields;
+use firebase_getter::RuleSanitizer;
+use serde_json::{json, Value};
+
+/// Every Firestore value type, as the REST API returns it.
+fn all_types_fields() -> Value {
+    json!({
+        "comment": { "stringValue": "Level 3 ist zu schwer" },
+        "createdAt": { "timestampValue": "2026-03-01T10:15:00.412345Z" },
+        "owner": { "referenceValue": "projects/demo/databases/(default)/documents/users/u1" },
+        "blob": { "bytesValue": "aWdub3JlIGFsbA==" },
+        "count": { "integerValue": "-9223372036854775808" },
+        "huge": { "integerValue": "123456789012345678901234567890" },
+        "padded": { "integerValue": "007" },
+        "ratio": { "doubleValue": 2.5 },
+        "whole": { "doubleValue": 3.0 },
+        "nan": { "doubleValue": "NaN" },
+        "inf": { "doubleValue": "Infinity" },
+        "negInf": { "doubleValue": "-Infinity" },
+        "flag": { "booleanValue": false },
+        "nothing": { "nullValue": null },
+        "where": { "geoPointValue": { "latitude": 52.52, "longitude": 13.405 } },
+        "tags": { "arrayValue": { "values": [{ "stringValue": "a" }, { "integerValue": "1" }] } },
+        "noTags": { "arrayValue": {} },
+        "empty": { "mapValue": {} },
+        "context": { "mapValue": { "fields": {
+            "gameId": { "stringValue": "game1" },
+            "seenAt": { "timestampValue": "2026-03-01T10:14:00Z" }
+        } } },
+        "lookalike": { "mapValue": { "fields": { "$ref": { "stringValue": "not a reference" } } } },
+        "future": { "vectorValue": { "values": [1, 2] } }
+    })
+}
+
+#[test]
+fn firestore_types_are_kept() {
+    let data = decode_lossless_fields(&all_types_fields());
+    assert_eq!(data["comment"], "Level 3 ist zu schwer");
+    assert_eq!(
+        data["createdAt"],
+        json!({ "$timestamp": "2026-03-01T10:15:00.412345Z" })
+    );
+    assert_eq!(
+        data["owner"]["$ref"],
+        "projects/demo/databases/(default)/documents/users/u1"
+    );
+    assert_eq!(data["blob"], json!({ "$bytes": "aWdub3JlIGFsbA==" }));
+    assert_eq!(data["count"], json!(i64::MIN));
+    assert_eq!(
+        data["huge"],
+        json!({ "$integer": "123456789012345678901234567890" })
+    );
+    assert_eq!(data["padded"], json!({ "$integer": "007" }));
+    assert!(data["whole"].is_f64());
+    assert_eq!(data["nan"], json!({ "$double": "NaN" }));
+    assert_eq!(data["where"]["$geo"]["latitude"], 52.52);
+    assert_eq!(data["tags"], json!(["a", 1]));
+    assert_eq!(data["noTags"], json!([]));
+    assert_eq!(data["empty"], json!({}));
+    assert_eq!(
+        data["context"]["seenAt"],
+        json!({ "$timestamp": "2026-03-01T10:14:00Z" })
+    );
+    assert_eq!(
+        data["lookalike"],
+        json!({ "$map": { "$ref": "not a reference" } })
+    );
+    assert_eq!(
+        data["future"],
+        json!({ "$raw": { "vectorValue": { "values": [1, 2] } } })
+    );
+}
+
+#[test]
+fn raw_fields_survive_decode_and_encode() {
+    let raw = all_types_fields();
+    let encoded = encode_firestore_fields(&decode_lossless_fields(&raw)).expect("encodes");
+    assert_eq!(encoded, raw);
+}
+
+#[test]
+fn decoded_values_survive_encode_and_decode() {
+    for value in [
+        json!(null),
+        json!(true),
+        json!(0),
+        json!(-42),
+        json!(0.1),
+        json!(-0.0),
+        json!("$timestamp"),
+        json!({ "$timestamp": "2026-01-01T00:00:00Z" }),
+        json!({ "$integer": "-18446744073709551616" }),
+        json!({ "$double": "-Infinity" }),
+        json!({ "$map": { "$bytes": "plain text" } }),
+        json!({ "$map": { "$map": {} } }),
+        json!({ "a": [{ "$ref": "projects/p/databases/(default)/documents/c/d" }, []], "b": {} }),
+        json!([[], {}, [{ "$geo": { "latitude": 0, "longitude": 0 } }]]),
+    ] {
+        let encoded = encode_firestore_value(&value).expect("encodes");
+        assert_eq!(decode_lossless_value(&encoded), value, "{}", encoded);
+    }
+}
+
+#[test]
+fn invalid_typed_values_are_not_encoded() {
+    for value in [
+        json!({ "$timestamp": 5 }),
+        json!({ "$integer": "12a" }),
+        json!({ "$double": "1.5" }),
+        json!({ "$geo": "52,13" }),
+        json!(u64::MAX),
+    ] {
+        assert!(encode_firestore_value(&value).is_err(), "{}", value);
+    }
+    assert!(encode_firestore_fields(&json!(["not", "an", "object"])).is_err());
+}
+
+#[test]
+fn plain_mode_stays_the_default() {
+    let raw = json!({ "name": "projects/demo/databases/(default)/documents/feedback/d1", "fields": all_types_fields() });
+    let plain = decode_raw_document_with(&raw, DecodeMode::default());
+    assert_eq!(plain.data["createdAt"], "2026-03-01T10:15:00.412345Z");
+    assert_eq!(plain.data["huge"], "123456789012345678901234567890");
+
+    let lossless = decode_raw_document_with(&raw, DecodeMode::Lossless);
+    assert_eq!(
+        encode_firestore_fields(&lossless.data).expect("encodes"),
+        raw["fields"]
+    );
+}
+
+#[test]
+fn typed_values_go_through_the_field_policy_and_schema() {
+    let mut data = decode_lossless_fields(&json!({
+        "comment": { "stringValue": "Gut" },
+        "createdAt": { "timestampValue": "2026-03-01T10:15:00Z" },
+        "blob": { "bytesValue": "aWdub3JlIGFsbA==" }
+    }));
+    let (document, schema) = parse_feedback(&data);
+    assert_eq!(document.created_at.as_deref(), Some("2026-03-01T10:15:00Z"));
+    assert_eq!(schema.unknown_fields, vec!["data.blob"]);
+
+    let result =
+        sanitize_document_fields(&mut data, &RuleSanitizer::default(), FieldPolicy::builtin());
+    assert_eq!(
+        data["createdAt"],
+        json!({ "$timestamp": "2026-03-01T10:15:00Z" })
+    );
+    assert_eq!(data["blob"], json!({ "$bytes": WITHHELD_FIELD_TOKEN }));
+    assert_eq!(result.fields.len(), 1);
+    assert_eq!(result.fields[0].field_path, "data.blob.$bytes");
+}
@@ -0,0 +1,99 @@
+//! Typed feedback model and schema validation of decoded documents.
+
+use firebase_getter::model::{parse_feedback, FeedbackDocument};
+use serde_json::json;
+
+#[test]
+fn client_payload_parses_into_the_model() {
+    let data = json!({
+        "comment": "Level 3 ist zu schwer.",
+        "source": "game_page",
+        "createdAtIso": "2026-03-01T10:15:00.000Z",
+        "createdAt": "2026-03-01T10:15:00.412345Z",
+        "context": {
+            "gameId": "databases_a_game1_index",
+            "folder": null,
+            "folderPath": "databases/A/game1",
+            "gameTitle": "Spiel 1",
+            "feedbackOrigin": "game",
+            "gameUrl": "../databases/A/game1/index.html",
+            "locationHref": "https://example.github.io/site/generic_pages/generic_page.html"
+        }
+    });
+    let (document, report) = parse_feedback(&data);
+    assert!(report.valid, "{:?}", report);
+    assert!(report.errors.is_empty() && report.unknown_fields.is_empty());
+    assert_eq!(document.source.as_deref(), Some("game_page"));
+    let context = document.context.as_ref().expect("context");
+    assert_eq!(context.folder, None);
+    assert_eq!(context.field("gameId"), Some("databases_a_game1_index"));
+    assert_eq!(document.repo_path(), Some("databases/A/game1"));
+}
+
+#[test]
+fn legacy_top_level_fields_are_known() {
+    let document = FeedbackDocument::from_data(&json!({
+        "comment": "Gut",
+        "jsonPath": "databases/A/game1/_data/x.json",
+        "context": { "jsonPath": "  " }
+    }));
+    assert_eq!(
+        document.legacy.json_path.as_deref(),
+        Some("databases/A/game1/_data/x.json")
+    );
+    assert_eq!(
+        document.context_field("jsonPath"),
+        Some("databases/A/game1/_data/x.json")
+    );
+}
+
+#[test]
+fn every_failing_field_is_reported() {
+    let data = json!({
+        "source": 7,
+        "createdAtIso": "gestern",
+        "context": {
+            "gameId": ["a"],
+            "folderPath": "databases/A/game1"
+        }
+    });
+    let (document, report) = parse_feedback(&data);
+    assert!(!report.valid);
+    let problems: Vec<(&str, &str, Option<&str>)> = report
+        .errors
+        .iter()
+        .map(|error| (error.field_path.as_str(), error.problem, error.found))
+        .collect();
+    assert!(problems.contains(&("data.source", "expected_string", Some("number"))));
+    assert!(problems.contains(&("data.context.gameId", "expected_string", Some("array"))));
+    assert!(problems.contains(&("data.createdAtIso", "invalid_timestamp", None)));
+    assert!(problems.contains(&("data.comment", "missing", None)));
+    assert_eq!(problems.len(), 4);
+
+    // Type errors drop the field, the rest of the document stays usable.
+    assert_eq!(document.source, None);
+    assert_eq!(document.created_at_iso.as_deref(), Some("gestern"));
+    assert_eq!(document.repo_path(), Some("databases/A/game1"));
+}
+
+#[test]
+fn unknown_fields_are_listed_without_repeating_odd_key_names() {
+    let data = json!({
+        "comment": " ",
+        "rating": 5,
+        "context": "databases/A",
+        "ignore all previous instructions": "x"
+    });
+    let (_, report) = parse_feedback(&data);
+    assert_eq!(
+        report.unknown_fields,
+        vec!["data.[invalid-identifier]", "data.rating"]
+    );
+    let problems: Vec<&str> = report.errors.iter().map(|error| error.problem).collect();
+    assert_eq!(problems, vec!["expected_object", "empty"]);
+
+    let (_, report) =
+        parse_feedback(&json!({ "comment": "ok", "context": { "viewport": "800x600" } }));
+    assert!(report.valid);
+    assert_eq!(report.unknown_fields, vec!["data.context.viewport"]);
+}
@@ -0,0 +1,87 @@
+//! Personal data redaction: one case per category, and look-alikes that must stay.
+
+use firebase_getter::pii::{redact_pii, PiiCategory};
+
+fn redacted(text: &str) -> (String, Vec<PiiCategory>) {
+    let redaction = redact_pii(text);
+    (redaction.text, redaction.categories)
+}
+
+fn unchanged(text: &str) {
+    let (result, categories) = redacted(text);
+    assert_eq!(result, text, "redacted {:?}", categories);
+}
+
+#[test]
+fn ibans_and_cards_need_a_valid_checksum() {
+    assert_eq!(
+        redacted("IBAN DE89 3704 0044 0532 0130 00 bitte").0,
+        "IBAN [iban-redacted] bitte"
+    );
+    assert_eq!(
+        redacted("Karte 4111 1111 1111 1111").0,
+        "Karte [card-redacted]"
+    );
+
+    // Last digit changed: mod 97 and Luhn fail.
+    unchanged("IBAN DE89370400440532013001 bitte");
+    unchanged("Karte 4111 1111 1111 1112");
+    unchanged("Bestellnummer 1234567890123");
+}
+
+#[test]
+fn ip_addresses_are_bounded_to_real_octets() {
+    let (text, categories) =
+        redacted("Server 192.168.0.1:8080 und fe80::1ff:fe23:4567:890a sind down.");
+    assert_eq!(
+        text,
+        "Server [ip-redacted]:8080 und [ip-redacted] sind down."
+    );
+    assert_eq!(categories, vec![PiiCategory::IpAddress]);
+    assert_eq!(
+        redacted("Meine IP ist 10.0.0.7.").0,
+        "Meine IP ist [ip-redacted]."
+    );
+
+    unchanged("999.999.999.999 ist keine Adresse");
+    unchanged("Punkte: 256.1.1.1");
+    unchanged("Ab Version 1.2.0.1 geht es, v1.2.0.1 auch.");
+    unchanged("Build: 10.0.19045.1 stuerzt ab");
+    unchanged("Seit 1.2.0.1.5 haengt das Spiel");
+}
+
+#[test]
+fn phone_numbers_need_a_prefix_and_enough_digits() {
+    assert_eq!(
+        redacted("Ruf an: 0171 2345678").0,
+        "Ruf an: [phone-redacted]"
+    );
+    assert_eq!(redacted("oder +49 30 1234567").0, "oder [phone-redacted]");
+    assert_eq!(
+        redacted("oder 0049 (0) 30 123456").0,
+        "oder [phone-redacted]"
+    );
+
+    unchanged("Level 12 34 ist zu schwer");
+    unchanged("Punkte 012 34");
+}
+
+#[test]
+fn addresses_need_a_street_and_a_house_number() {
+    let (text, categories) = redacted("Ich wohne in der Hauptstraße 12, 10115 Berlin.");
+    assert_eq!(text, "Ich wohne in der [address-redacted].");
+    assert_eq!(categories, vec![PiiCategory::Address]);
+    assert_eq!(redacted("Berliner Allee 5a").0, "[address-redacted]");
+
+    unchanged("Auf Platz 3 im Ranking");
+    unchanged("Die Hauptstraße ist im Level zu lang");
+}
+
+#[test]
+fn names_only_after_a_lead_in() {
+    assert_eq!(
+        redacted("Hallo, ich heiße Max Muster!").0,
+        "Hallo, ich heiße [name-redacted]!"
+    );
+    unchanged("Max findet das Spiel gut");
+}
@@ -0,0 +1,117 @@
+//! The built-in rule pack against the labeled corpus in `rules/corpus.toml`.
+
+use std::collections::BTreeSet;
+
+use firebase_getter::corpus::{evaluate, CaseLabel, Corpus, CorpusEvaluation};
+use firebase_getter::RuleSanitizer;
+use once_cell::sync::Lazy;
+
+static SANITIZER: Lazy<RuleSanitizer> = Lazy::new(RuleSanitizer::default);
+static CORPUS: Lazy<Corpus> = Lazy::new(Corpus::builtin);
+static EVALUATION: Lazy<CorpusEvaluation> =
+    Lazy::new(|| evaluate(&CORPUS, &SANITIZER).expect("corpus fits the built-in pack"));
+
+#[test]
+fn every_case_matches_its_label_and_rules() {
+    let failures: Vec<String> = EVALUATION
+        .failures()
+        .map(|case| {
+            format!(
+                "{} (label {}, blocked {}, missing {:?}, unexpected {:?})",
+                case.id,
+                case.label.as_str(),
+                case.blocked,
+                case.missing_rules(),
+                case.unexpected_rules()
+            )
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "corpus failures:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn every_detection_rule_has_a_positive_case() {
+    let expected: BTreeSet<&str> = CORPUS
+        .cases
+        .iter()
+        .flat_map(|case| case.rules.iter().map(String::as_str))
+        .collect();
+    let uncovered: Vec<&str> = SANITIZER
+        .rules()
+        .rules
+        .iter()
+        .filter(|rule| rule.spec.action.is_detection())
+        .map(|rule| rule.spec.id.as_str())
+        .filter(|id| !expected.contains(id))
+        .collect();
+    assert!(
+        uncovered.is_empty(),
+        "detection rules without a corpus case: {:?}",
+        uncovered
+    );
+}
+
+#[test]
+fn known_issues_still_disagree() {
+    // A known issue that passes has been fixed; drop its `known_issue` so it guards the fix.
+    let fixed: Vec<&str> = EVALUATION
+        .cases
+        .iter()
+        .filter(|case| case.known_issue.is_some() && case.passed())
+        .map(|case| case.id.as_str())
+        .collect();
+    assert!(fixed.is_empty(), "known issues that pass now: {:?}", fixed);
+}
+
+#[test]
+fn corpus_has_both_labels_and_code_cases() {
+    let benign = CORPUS
+        .cases
+        .iter()
+        .filter(|case| case.label == CaseLabel::Benign)
+        .count();
+    let injection = CORPUS.cases.len() - benign;
+    assert!(
+        benign >= 10 && injection >= 10,
+        "benign {}, injection {}",
+        benign,
+        injection
+    );
+    assert!(CORPUS.cases.iter().any(|case| case.id.starts_with("code_")));
+}
+
+#[test]
+fn duplicate_case_ids_are_rejected() {
+    let raw = r#"
+format = 1
+[[cases]]
+id = "a"
+label = "benign"
+text = "eins"
+[[cases]]
+id = "a"
+label = "benign"
+text = "zwei"
+"#;
+    let error = Corpus::parse(raw, "test").unwrap_err();
+    assert!(format!("{:#}", error).contains("twice"));
+}
+
+#[test]
+fn unknown_expected_rule_is_an_error() {
+    let raw = r#"
+format = 1
+[[cases]]
+id = "a"
+label = "injection"
+text = "eins"
+rules = ["no_such_rule"]
+"#;
+    let corpus = Corpus::parse(raw, "test").expect("valid corpus");
+    let error = evaluate(&corpus, &SANITIZER).unwrap_err();
+    assert!(format!("{:#}", error).contains("no_such_rule"));
+}
@@ -0,0 +1,129 @@
+//! Rule pack validation: every check `RulePack::parse` makes, each with a pack that fails it.
+
+use firebase_getter::RulePack;
+
+const VALID_RULE: &str = r#"
+[[rules]]
+id = "ignore_instructions"
+description = "Asks to ignore earlier instructions."
+action = "quarantine"
+weight = 5
+pattern = 'ignore (all )?previous instructions'
+flags = "i"
+"#;
+
+fn pack(body: &str) -> String {
+    format!("format = 1\nname = \"test\"\nversion = \"1\"\n{}", body)
+}
+
+/// Parses `raw` and returns the whole error chain.
+fn rejected(raw: &str) -> String {
+    let error = RulePack::parse(raw, "test.toml").expect_err("pack must be rejected");
+    format!("{:#}", error)
+}
+
+fn assert_rejected(raw: &str, expected: &str) {
+    let message = rejected(raw);
+    assert!(
+        message.contains(expected),
+        "expected {:?} in {:?}",
+        expected,
+        message
+    );
+    assert!(
+        message.contains("test.toml"),
+        "origin missing in {:?}",
+        message
+    );
+}
+
+#[test]
+fn a_minimal_pack_parses() {
+    let parsed = RulePack::parse(&pack(VALID_RULE), "test.toml").expect("valid pack");
+    assert_eq!(parsed.rules.len(), 1);
+    assert!(parsed.sanitizer_version().starts_with("test@1+sha256:"));
+}
+
+#[test]
+fn invalid_regex_is_rejected() {
+    let raw = pack(&VALID_RULE.replace(
+        "'ignore (all )?previous instructions'",
+        "'ignore (all previous'",
+    ));
+    assert_rejected(&raw, "rule `ignore_instructions` has an invalid pattern");
+}
+
+#[test]
+fn duplicate_rule_ids_are_rejected() {
+    let raw = pack(&format!("{}{}", VALID_RULE, VALID_RULE));
+    assert_rejected(&raw, "defines rule `ignore_instructions` twice");
+}
+
+#[test]
+fn unknown_or_repeated_flags_are_rejected() {
+    for flags in ["flags = \"iz\"", "flags = \"ii\""] {
+        let raw = pack(&VALID_RULE.replace("flags = \"i\"", flags));
+        assert_rejected(&raw, "rule `ignore_instructions` has invalid flags");
+    }
+}
+
+#[test]
+fn detection_rules_need_a_weight() {
+    let raw = pack(&VALID_RULE.replace("weight = 5", "weight = 0"));
+    assert_rejected(
+        &raw,
+        "quarantine rule `ignore_instructions` needs a weight above 0",
+    );
+    let raw = pack(&VALID_RULE.replace("weight = 5\n", ""));
+    assert_rejected(&raw, "needs a weight above 0");
+}
+
+#[test]
+fn patterns_matching_the_empty_string_are_rejected() {
+    let raw = pack(&VALID_RULE.replace("'ignore (all )?previous instructions'", "'(ignore)?'"));
+    assert_rejected(&raw, "rule `ignore_instructions` matches the empty string");
+}
+
+#[test]
+fn policies_must_name_known_detection_rules() {
+    let policy = |rule_id: &str| {
+        format!(
+            r#"
+[[policies]]
+id = "lenient"
+description = "Lenient for game pages."
+source = "game_page"
+actions = {{ {} = "flag" }}
+"#,
+            rule_id
+        )
+    };
+    let raw = pack(&format!("{}{}", VALID_RULE, policy("unknown_rule")));
+    assert_rejected(
+        &raw,
+        "policy `lenient` sets an action for `unknown_rule`, which is no detection rule",
+    );
+
+    let redact = r#"
+[[rules]]
+id = "mask_secret"
+description = "Masks secrets."
+action = "redact"
+pattern = 'secret'
+"#;
+    let raw = pack(&format!(
+        "{}{}{}",
+        VALID_RULE,
+        redact,
+        policy("mask_secret")
+    ));
+    assert_rejected(
+        &raw,
+        "sets an action for `mask_secret`, which is no detection rule",
+    );
+    RulePack::parse(
+        &pack(&format!("{}{}", VALID_RULE, policy("ignore_instructions"))),
+        "test.toml",
+    )
+    .expect("known rule");
+}
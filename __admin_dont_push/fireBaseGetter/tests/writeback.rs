@@ -0,0 +1,194 @@
+//! Write-back of the processed markers: planning, batching and the field policy.
+
+use std::cell::RefCell;
+
+use anyhow::{bail, Result};
+use firebase_getter::export::FeedbackRoute;
+use firebase_getter::fields::FieldPolicy;
+use firebase_getter::firestore::{commit_in_batches, MAX_WRITES_PER_COMMIT};
+use firebase_getter::model::parse_feedback;
+use firebase_getter::sanitize::sanitize_document_fields;
+use firebase_getter::writeback::plan_processed_writes;
+use firebase_getter::{DocumentWriter, RuleSanitizer};
+use serde_json::{json, Value};
+
+const VERSION: &str = "prompt_injection@4+sha256:0123abcd";
+const PREFIX: &str = "projects/demo/databases/(default)/documents/feedback_all_games";
+
+/// Records every commit instead of sending it; fails the commit with the given number.
+#[derive(Default)]
+struct RecordingWriter {
+    commits: RefCell<Vec<Vec<Value>>>,
+    fail_on: Option<usize>,
+}
+
+impl DocumentWriter for RecordingWriter {
+    fn project_id(&self) -> Result<String> {
+        Ok("demo".to_string())
+    }
+
+    fn commit(&self, writes: &[Value]) -> Result<()> {
+        let mut commits = self.commits.borrow_mut();
+        if self.fail_on == Some(commits.len() + 1) {
+            bail!("ABORTED");
+        }
+        commits.push(writes.to_vec());
+        Ok(())
+    }
+}
+
+fn mapped(id: &str, data: Value) -> Value {
+    json!({
+        "id": id,
+        "name": format!("{}/{}", PREFIX, id),
+        "data": data,
+        "commentSecurity": { "sanitizerVersion": VERSION }
+    })
+}
+
+fn exported(id: &str) -> FeedbackRoute {
+    FeedbackRoute {
+        id: id.to_string(),
+        outcome: "exported",
+        written_path: Some(format!(
+            "databases/A/game1/__dokumentation/__04_lernings/feedback_{}.json",
+            id
+        )),
+        ..FeedbackRoute::default()
+    }
+}
+
+fn update(id: &str) -> Value {
+    json!({ "update": { "name": format!("{}/{}", PREFIX, id), "fields": {} } })
+}
+
+#[test]
+fn only_exported_documents_are_marked() {
+    let docs = vec![
+        mapped("d1", json!({ "comment": "Gut" })),
+        mapped("d2", json!({ "comment": "Schlecht" })),
+    ];
+    let routes = vec![
+        exported("d1"),
+        FeedbackRoute {
+            id: "d2".to_string(),
+            outcome: "unresolved",
+            ..FeedbackRoute::default()
+        },
+    ];
+    let plan = plan_processed_writes(&docs, &routes, "fireBaseGetter").expect("plans");
+    assert_eq!(plan.already_processed, 0);
+    assert_eq!(plan.writes.len(), 1);
+
+    let write = &plan.writes[0];
+    assert_eq!(write["update"]["name"], format!("{}/d1", PREFIX));
+    assert_eq!(
+        write["update"]["fields"]["processedBy"],
+        json!({ "stringValue": "fireBaseGetter" })
+    );
+    assert_eq!(
+        write["update"]["fields"]["sanitizerVersion"],
+        json!({ "stringValue": VERSION })
+    );
+    assert_eq!(
+        write["updateMask"]["fieldPaths"],
+        json!(["processedBy", "sanitizerVersion", "exportPath"])
+    );
+    assert_eq!(
+        write["updateTransforms"][0]["setToServerValue"],
+        "REQUEST_TIME"
+    );
+    assert_eq!(write["currentDocument"], json!({ "exists": true }));
+}
+
+#[test]
+fn documents_marked_by_the_same_export_are_skipped() {
+    let route = exported("d1");
+    let marked = json!({
+        "comment": "Gut",
+        "processedAt": "2026-03-02T08:00:00Z",
+        "sanitizerVersion": VERSION,
+        "exportPath": route.written_path.clone()
+    });
+    let plan = plan_processed_writes(&[mapped("d1", marked.clone())], &[exported("d1")], "x")
+        .expect("plans");
+    assert_eq!((plan.writes.len(), plan.already_processed), (0, 1));
+
+    // A new sanitizer version marks the document again.
+    let mut stale = marked;
+    stale["sanitizerVersion"] = json!("prompt_injection@3+sha256:ffff");
+    let plan =
+        plan_processed_writes(&[mapped("d1", stale)], &[exported("d1")], "x").expect("plans");
+    assert_eq!((plan.writes.len(), plan.already_processed), (1, 0));
+}
+
+#[test]
+fn markers_pass_the_field_policy_and_schema() {
+    let mut data = json!({
+        "comment": "Gut",
+        "processedAt": "2026-03-02T08:00:00.123Z",
+        "processedBy": "fireBaseGetter",
+        "sanitizerVersion": VERSION,
+        "exportPath": "databases/A/game1/__dokumentation/__04_lernings/feedback_d1.json"
+    });
+    let original = data.clone();
+    let result =
+        sanitize_document_fields(&mut data, &RuleSanitizer::default(), FieldPolicy::builtin());
+    assert!(result.fields.is_empty(), "{:?}", result.fields);
+    assert_eq!(data, original);
+
+    let (document, schema) = parse_feedback(&data);
+    assert!(
+        schema.valid && schema.unknown_fields.is_empty(),
+        "{:?}",
+        schema
+    );
+    assert_eq!(document.sanitizer_version.as_deref(), Some(VERSION));
+}
+
+#[test]
+fn large_runs_are_split_into_commits() {
+    let writes: Vec<Value> = (0..MAX_WRITES_PER_COMMIT * 2 + 1)
+        .map(|i| update(&format!("d{}", i)))
+        .collect();
+    let writer = RecordingWriter::default();
+    assert_eq!(commit_in_batches(&writer, &writes).expect("commits"), 3);
+    let sizes: Vec<usize> = writer.commits.borrow().iter().map(Vec::len).collect();
+    assert_eq!(sizes, vec![MAX_WRITES_PER_COMMIT, MAX_WRITES_PER_COMMIT, 1]);
+
+    assert_eq!(commit_in_batches(&writer, &[]).expect("nothing to do"), 0);
+}
+
+#[test]
+fn foreign_documents_are_refused_before_any_commit() {
+    let writer = RecordingWriter::default();
+    for foreign in [
+        json!({ "update": { "name": "projects/other/databases/(default)/documents/feedback_all_games/d1" } }),
+        json!({ "delete": format!("{}/", "projects/demo/databases/(default)/documents") }),
+        json!({ "transform": {} }),
+    ] {
+        let writes = vec![update("d0"), foreign];
+        assert!(commit_in_batches(&writer, &writes).is_err());
+    }
+    assert!(writer.commits.borrow().is_empty());
+}
+
+#[test]
+fn failed_commit_reports_progress() {
+    let writes: Vec<Value> = (0..MAX_WRITES_PER_COMMIT + 1)
+        .map(|i| update(&format!("d{}", i)))
+        .collect();
+    let writer = RecordingWriter {
+        fail_on: Some(2),
+        ..RecordingWriter::default()
+    };
+    let error = commit_in_batches(&writer, &writes).expect_err("second commit fails");
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "commit 2 failed after {} of {} writes",
+            MAX_WRITES_PER_COMMIT,
+            writes.len()
+        )
+    );
+}